@@ -1,11 +1,109 @@
 use clap::Parser;
+use cleanbox::{HashAlgorithm, MediaLayout, MetadataWriting};
+use std::path::PathBuf;
 
 #[derive(clap::Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     /// Path to the root of the life directory
     #[clap(long, value_name = "PATH")]
     pub life_path: String,
+
+    /// Command used to open documents for inspection (defaults to the system viewer)
+    #[clap(long, value_name = "COMMAND")]
+    pub viewer: Option<String>,
+
+    /// Never prompt; documents without a sidecar or matching rule stay in the inbox
+    #[clap(long)]
+    pub non_interactive: bool,
+
+    /// Ask for a name for each event of the events media layout (otherwise "event")
+    #[clap(long, conflicts_with = "non_interactive")]
+    pub name_events: bool,
+
+    /// Read answers as JSON lines from a file ("-" for stdin) and print questions as JSON
+    #[clap(long, value_name = "PATH", conflicts_with = "non_interactive")]
+    pub answers: Option<String>,
+
+    /// Emit a per-file run report in the given format instead of the summary
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    pub report: Option<ReportFormat>,
+
+    /// Write the report to a file instead of stdout
+    #[clap(long, value_name = "PATH", requires = "report")]
+    pub report_path: Option<String>,
+
+    /// Number of threads reading metadata and hashing media (defaults to the CPU count)
+    #[clap(short, long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// Hash for duplicate suffixes: sha1, sha256, blake3 or xxh3 (overrides cleanbox.toml)
+    #[clap(long, value_name = "ALGORITHM")]
+    pub hash_algorithm: Option<HashAlgorithm>,
+
+    /// Media folders: monthly, yearly, flat, events[:<hours>h[,<km>km]] or a pattern such as {year}/{media_type} (overrides cleanbox.toml)
+    #[clap(long, value_name = "LAYOUT")]
+    pub media_layout: Option<MediaLayout>,
+
+    /// Write resolved capture times and document tags as XMP: off, sidecar or in_place (overrides cleanbox.toml)
+    #[clap(long, value_name = "MODE")]
+    pub write_metadata: Option<MetadataWriting>,
+
+    /// Print more detail; repeat for metadata traces
+    #[clap(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet", global = true)]
+    pub verbose: u8,
+
+    /// Only print errors and prompts, for cron jobs
+    #[clap(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Do not write cleanbox.log in the life directory
+    #[clap(long, global = true)]
+    pub no_log_file: bool,
+}
+
+/// Commands other than processing the inbox
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Re-hash archived files and report any that were modified, are missing or are not in the manifest
+    Verify {
+        /// Hash at most N files, starting with those verified longest ago
+        #[clap(long, value_name = "N", conflicts_with = "sample")]
+        limit: Option<usize>,
+
+        /// Hash N files picked at random
+        #[clap(long, value_name = "N")]
+        sample: Option<usize>,
+    },
+
+    /// Find archived files whose name or folder breaks the cleanbox layout and offer to fix them
+    Lint {
+        /// Apply the fix plan without asking
+        #[clap(long)]
+        apply: bool,
+    },
+
+    /// File the media of an unpacked Google Takeout or iCloud Photos export, dated by its JSON and CSV files
+    Import {
+        /// Directory the export was unpacked into
+        #[clap(value_name = "PATH")]
+        source: PathBuf,
+    },
+
+    /// Move archived media into the folders of the current media layout
+    Reorganize {
+        /// Print the moves without making them
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    Json,
 }
 
 pub fn parse_args() -> Args {
@@ -0,0 +1,202 @@
+use crate::media::{FileMetadata, FileType};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Files captured together, such as `IMG_1234.CR2` + `IMG_1234.JPG` or the HEIC and MOV
+/// of a Live Photo, which are named and filed as one unit
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompanionGroup {
+    /// The file with the best metadata, named and filed first
+    pub primary: PathBuf,
+    /// Files that follow the primary, in inbox order
+    pub companions: Vec<PathBuf>,
+}
+
+/// Group files sharing a directory and stem (ignoring case) or a content identifier.
+///
+/// Groups keep the order of their first file in `paths`, and every path lands in
+/// exactly one group.
+pub fn group_companions(
+    paths: &[PathBuf],
+    metadata: &HashMap<PathBuf, FileMetadata>,
+) -> Vec<CompanionGroup> {
+    // Union-find over indexes into `paths`
+    let mut parent: Vec<usize> = (0..paths.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    let mut by_key: HashMap<(PathBuf, String), usize> = HashMap::new();
+    let mut by_content_id: HashMap<&str, usize> = HashMap::new();
+    for (i, path) in paths.iter().enumerate() {
+        let mut links = Vec::new();
+        if let Some(key) = stem_key(path) {
+            links.push(*by_key.entry(key).or_insert(i));
+        }
+        if let Some(id) = metadata.get(path).and_then(|m| m.content_id.as_deref()) {
+            links.push(*by_content_id.entry(id).or_insert(i));
+        }
+        for other in links {
+            let (a, b) = (root(&mut parent, i), root(&mut parent, other));
+            // The earlier file stays the root so groups keep inbox order
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut members: Vec<Vec<PathBuf>> = vec![Vec::new(); paths.len()];
+    for (i, path) in paths.iter().enumerate() {
+        let root = root(&mut parent, i);
+        members[root].push(path.clone());
+    }
+
+    members
+        .into_iter()
+        .filter(|files| !files.is_empty())
+        .map(|mut files| {
+            let best = files
+                .iter()
+                .enumerate()
+                // max_by_key keeps the last of equals, so rank in reverse inbox order
+                .rev()
+                .max_by_key(|(_, path)| metadata_rank(metadata.get(*path)))
+                .map_or(0, |(i, _)| i);
+            let primary = files.remove(best);
+            CompanionGroup {
+                primary,
+                companions: files,
+            }
+        })
+        .collect()
+}
+
+/// Fill what a companion lacks, such as a video's datetime, from the primary
+pub fn merge_metadata(mut companion: FileMetadata, primary: &FileMetadata) -> FileMetadata {
+    fn fill(field: &mut Option<String>, from: &Option<String>) {
+        if field.is_none() {
+            field.clone_from(from);
+        }
+    }
+
+    // The pair shares one name, so it must share one capture time
+    companion
+        .datetime_original
+        .clone_from(&primary.datetime_original);
+    companion.subsec_time.clone_from(&primary.subsec_time);
+    fill(&mut companion.camera_make, &primary.camera_make);
+    fill(&mut companion.camera_model, &primary.camera_model);
+    fill(&mut companion.lens_model, &primary.lens_model);
+    fill(&mut companion.serial_number, &primary.serial_number);
+    fill(&mut companion.city, &primary.city);
+    fill(&mut companion.country, &primary.country);
+    if companion.gps.is_none() {
+        companion.gps = primary.gps;
+    }
+    companion
+}
+
+fn stem_key(path: &Path) -> Option<(PathBuf, String)> {
+    let stem = path.file_stem()?.to_str()?.to_lowercase();
+    Some((path.parent()?.to_path_buf(), stem))
+}
+
+/// How useful a file's metadata is for naming the whole group
+fn metadata_rank(metadata: Option<&FileMetadata>) -> (bool, bool, bool, bool) {
+    match metadata {
+        Some(m) => (
+            m.datetime_original.is_some(),
+            m.subsec_time.is_some(),
+            m.camera_model.is_some(),
+            m.file_type == FileType::Image,
+        ),
+        None => (false, false, false, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(mime: &str, datetime: Option<&str>) -> FileMetadata {
+        let metadata = FileMetadata::new(mime.to_string());
+        match datetime {
+            Some(datetime) => metadata.with_datetime(datetime.to_string()),
+            None => metadata,
+        }
+    }
+
+    #[test]
+    fn test_groups_by_stem_and_content_id() {
+        let paths: Vec<PathBuf> = [
+            "/inbox/IMG_0001.MOV",
+            "/inbox/IMG_0001.HEIC",
+            "/inbox/DSC_0002.CR2",
+            "/inbox/clip.mov",
+            "/inbox/dsc_0002.jpg",
+            "/inbox/IMG_0003.HEIC",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let mut metadata = HashMap::new();
+        metadata.insert(paths[0].clone(), meta("video/quicktime", None));
+        metadata.insert(
+            paths[1].clone(),
+            meta("image/heic", Some("2024-05-01_10-00-00")),
+        );
+        metadata.insert(
+            paths[2].clone(),
+            meta("image/x-canon-cr2", Some("2024-05-02_10-00-00")),
+        );
+        let mut clip = meta("video/quicktime", None);
+        clip.content_id = Some("B1E2".to_string());
+        metadata.insert(paths[3].clone(), clip);
+        metadata.insert(
+            paths[4].clone(),
+            meta("image/jpeg", Some("2024-05-02_10-00-00")),
+        );
+        let mut photo = meta("image/heic", Some("2024-05-03_10-00-00"));
+        photo.content_id = Some("B1E2".to_string());
+        metadata.insert(paths[5].clone(), photo);
+
+        let groups = group_companions(&paths, &metadata);
+
+        assert_eq!(
+            groups,
+            vec![
+                CompanionGroup {
+                    primary: paths[1].clone(),
+                    companions: vec![paths[0].clone()],
+                },
+                // Equally good metadata keeps the first file as the primary
+                CompanionGroup {
+                    primary: paths[2].clone(),
+                    companions: vec![paths[4].clone()],
+                },
+                CompanionGroup {
+                    primary: paths[5].clone(),
+                    companions: vec![paths[3].clone()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_metadata() {
+        let primary = meta("image/heic", Some("2024-05-01_10-00-00"))
+            .with_camera_model("iPhone 15 Pro".to_string());
+        let video = meta("video/quicktime", Some("2024-05-01_10-00-01"));
+
+        let merged = merge_metadata(video, &primary);
+
+        assert_eq!(
+            merged.datetime_original.as_deref(),
+            Some("2024-05-01_10-00-00")
+        );
+        assert_eq!(merged.camera_model.as_deref(), Some("iPhone 15 Pro"));
+        assert_eq!(merged.file_type, FileType::Video);
+    }
+}
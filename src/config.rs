@@ -1,10 +1,18 @@
-use std::path::PathBuf;
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::FileManager;
+use crate::hashing::HashAlgorithm;
+use crate::organization::MediaLayout;
+use crate::pipeline::default_workers;
+use crate::xmp::MetadataWriting;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct ProcessingConfig {
     pub inbox_path: PathBuf,
     pub media_root: PathBuf,
     pub hash_length: usize,
+    pub hash_algorithm: HashAlgorithm,
     pub handle_duplicates: DuplicateHandling,
     pub skip_unsupported_files: bool,
     pub create_backup: bool,
@@ -14,17 +22,77 @@ pub struct ProcessingConfig {
 pub struct LifeConfig {
     pub life_path: PathBuf,
     pub hash_length: usize,
+    /// Algorithm for duplicate suffixes and recorded checksums
+    pub hash_algorithm: HashAlgorithm,
+    /// Folder layout of `media/`
+    pub media_layout: MediaLayout,
     pub handle_duplicates: DuplicateHandling,
     pub skip_unsupported_files: bool,
     pub create_backup: bool,
+    /// Command used to open documents for inspection (system viewer when unset)
+    pub viewer_command: Option<String>,
+    /// Prompt for documents without a sidecar or rule (false leaves them in the inbox)
+    pub interactive: bool,
+    /// Ask for a name for each event of the `events` media layout
+    pub name_events: bool,
+    /// Threads reading metadata and hashing files; naming and moving stay sequential
+    pub workers: usize,
+    /// GeoNames-style cities dataset for reverse geocoding (bundled cities when unset)
+    pub geonames_file: Option<PathBuf>,
+    /// Whether resolved capture times and document tags are written back as XMP
+    pub write_metadata: MetadataWriting,
 }
 
-#[derive(Debug, Clone)]
+/// Per-life-directory settings stored in `cleanbox.toml`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LifeSettings {
+    pub hash_algorithm: HashAlgorithm,
+    pub media_layout: MediaLayout,
+    pub duplicate_handling: DuplicateHandling,
+    /// GeoNames `cities*.txt` file, relative to the life directory unless absolute
+    pub geonames_file: Option<PathBuf>,
+    pub write_metadata: MetadataWriting,
+}
+
+impl LifeSettings {
+    /// Load settings, using defaults if the file does not exist
+    pub fn load_from_file<P: AsRef<Path>, F: FileManager>(
+        path: P,
+        file_manager: &F,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        if !file_manager.is_file(path) {
+            return Ok(Self::default());
+        }
+
+        let content = file_manager
+            .read_file(path)
+            .and_then(|bytes| {
+                String::from_utf8(bytes).map_err(|e| CleanboxError::InvalidSettings(e.to_string()))
+            })
+            .map_err(|e| {
+                CleanboxError::InvalidSettings(format!("Cannot read {}: {}", path.display(), e))
+            })?;
+
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        toml::from_str(content).map_err(|e| CleanboxError::InvalidSettings(e.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum DuplicateHandling {
     Skip,
+    #[default]
     AppendHash,
     Overwrite,
     Error,
+    /// Number same-second files `_01`, `_02`... in shooting order
+    Sequence,
 }
 
 impl ProcessingConfig {
@@ -33,6 +101,7 @@ impl ProcessingConfig {
             inbox_path,
             media_root,
             hash_length: 6,
+            hash_algorithm: HashAlgorithm::default(),
             handle_duplicates: DuplicateHandling::AppendHash,
             skip_unsupported_files: true,
             create_backup: false,
@@ -44,6 +113,11 @@ impl ProcessingConfig {
         self
     }
 
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
     pub fn with_duplicate_handling(mut self, handling: DuplicateHandling) -> Self {
         self.handle_duplicates = handling;
         self
@@ -65,9 +139,17 @@ impl LifeConfig {
         Self {
             life_path,
             hash_length: 6,
+            hash_algorithm: HashAlgorithm::default(),
+            media_layout: MediaLayout::default(),
             handle_duplicates: DuplicateHandling::AppendHash,
             skip_unsupported_files: true,
             create_backup: false,
+            viewer_command: None,
+            interactive: true,
+            name_events: false,
+            workers: default_workers(),
+            geonames_file: None,
+            write_metadata: MetadataWriting::Off,
         }
     }
 
@@ -87,11 +169,32 @@ impl LifeConfig {
         self.documents_root().join("tags.txt")
     }
 
+    pub fn rules_file(&self) -> PathBuf {
+        self.life_path.join("rules.toml")
+    }
+
+    pub fn log_file(&self) -> PathBuf {
+        self.life_path.join("cleanbox.log")
+    }
+
+    pub fn settings_file(&self) -> PathBuf {
+        self.life_path.join("cleanbox.toml")
+    }
+
+    pub fn manifest_file(&self) -> PathBuf {
+        self.life_path.join("manifest.json")
+    }
+
     pub fn with_hash_length(mut self, length: usize) -> Self {
         self.hash_length = length;
         self
     }
 
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
     pub fn with_duplicate_handling(mut self, handling: DuplicateHandling) -> Self {
         self.handle_duplicates = handling;
         self
@@ -107,12 +210,63 @@ impl LifeConfig {
         self
     }
 
+    pub fn with_viewer_command(mut self, command: String) -> Self {
+        self.viewer_command = Some(command);
+        self
+    }
+
+    pub fn with_interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    pub fn with_name_events(mut self, name_events: bool) -> Self {
+        self.name_events = name_events;
+        self
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    pub fn with_media_layout(mut self, layout: MediaLayout) -> Self {
+        self.media_layout = layout;
+        self
+    }
+
+    pub fn with_geonames_file(mut self, path: PathBuf) -> Self {
+        self.geonames_file = Some(path);
+        self
+    }
+
+    pub fn with_write_metadata(mut self, mode: MetadataWriting) -> Self {
+        self.write_metadata = mode;
+        self
+    }
+
+    pub fn with_settings(self, settings: LifeSettings) -> Self {
+        let config = self
+            .with_hash_algorithm(settings.hash_algorithm)
+            .with_media_layout(settings.media_layout)
+            .with_duplicate_handling(settings.duplicate_handling)
+            .with_write_metadata(settings.write_metadata);
+        match settings.geonames_file {
+            Some(path) => {
+                let path = config.life_path.join(path);
+                config.with_geonames_file(path)
+            }
+            None => config,
+        }
+    }
+
     // Convenience method to convert to ProcessingConfig for compatibility
     pub fn to_processing_config(&self) -> ProcessingConfig {
         ProcessingConfig {
             inbox_path: self.inbox_path(),
             media_root: self.media_root(),
             hash_length: self.hash_length,
+            hash_algorithm: self.hash_algorithm,
             handle_duplicates: self.handle_duplicates.clone(),
             skip_unsupported_files: self.skip_unsupported_files,
             create_backup: self.create_backup,
@@ -198,6 +352,7 @@ mod tests {
         let append = DuplicateHandling::AppendHash;
         let overwrite = DuplicateHandling::Overwrite;
         let error = DuplicateHandling::Error;
+        let sequence = DuplicateHandling::Sequence;
 
         match skip {
             DuplicateHandling::Skip => {}
@@ -218,6 +373,11 @@ mod tests {
             DuplicateHandling::Error => {}
             _ => panic!("Wrong variant"),
         }
+
+        match sequence {
+            DuplicateHandling::Sequence => {}
+            _ => panic!("Wrong variant"),
+        }
     }
 
     #[test]
@@ -233,6 +393,8 @@ mod tests {
         ));
         assert!(config.skip_unsupported_files);
         assert!(!config.create_backup);
+        assert!(config.viewer_command.is_none());
+        assert!(config.interactive);
     }
 
     #[test]
@@ -250,6 +412,14 @@ mod tests {
             config.tags_file(),
             PathBuf::from("/home/user/life/documents/tags.txt")
         );
+        assert_eq!(
+            config.rules_file(),
+            PathBuf::from("/home/user/life/rules.toml")
+        );
+        assert_eq!(
+            config.log_file(),
+            PathBuf::from("/home/user/life/cleanbox.log")
+        );
     }
 
     #[test]
@@ -258,18 +428,29 @@ mod tests {
             .with_hash_length(8)
             .with_duplicate_handling(DuplicateHandling::Skip)
             .with_backup(true)
-            .skip_unsupported(false);
+            .skip_unsupported(false)
+            .with_viewer_command("zathura".to_string())
+            .with_interactive(false)
+            .with_name_events(true)
+            .with_write_metadata(MetadataWriting::Sidecar)
+            .with_workers(0);
 
         assert_eq!(config.hash_length, 8);
         assert!(matches!(config.handle_duplicates, DuplicateHandling::Skip));
         assert!(config.create_backup);
         assert!(!config.skip_unsupported_files);
+        assert_eq!(config.viewer_command, Some("zathura".to_string()));
+        assert!(!config.interactive);
+        assert!(config.name_events);
+        assert_eq!(config.write_metadata, MetadataWriting::Sidecar);
+        assert_eq!(config.workers, 1);
     }
 
     #[test]
     fn test_life_config_to_processing_config() {
         let life_config = LifeConfig::new(PathBuf::from("/home/user/life"))
             .with_hash_length(10)
+            .with_hash_algorithm(HashAlgorithm::Blake3)
             .with_duplicate_handling(DuplicateHandling::Overwrite);
 
         let processing_config = life_config.to_processing_config();
@@ -283,9 +464,50 @@ mod tests {
             PathBuf::from("/home/user/life/media")
         );
         assert_eq!(processing_config.hash_length, 10);
+        assert_eq!(processing_config.hash_algorithm, HashAlgorithm::Blake3);
         assert!(matches!(
             processing_config.handle_duplicates,
             DuplicateHandling::Overwrite
         ));
     }
+
+    #[test]
+    fn test_life_settings() {
+        use crate::testing::InMemoryFileManager;
+
+        let settings = LifeSettings::parse("hash_algorithm = \"xxh3\"\n").unwrap();
+        assert_eq!(settings.hash_algorithm, HashAlgorithm::Xxh3);
+        assert_eq!(settings.media_layout, MediaLayout::Monthly);
+        let settings = LifeSettings::parse("media_layout = \"{year}/{media_type}\"\n").unwrap();
+        assert_eq!(
+            settings.media_layout,
+            "{year}/{media_type}".parse::<MediaLayout>().unwrap()
+        );
+        assert!(LifeSettings::parse("media_layout = \"montly\"").is_err());
+        let settings = LifeSettings::parse("duplicate_handling = \"sequence\"\n").unwrap();
+        assert_eq!(settings.duplicate_handling, DuplicateHandling::Sequence);
+        let settings = LifeSettings::parse("write_metadata = \"in_place\"\n").unwrap();
+        assert_eq!(settings.write_metadata, MetadataWriting::InPlace);
+        assert!(LifeSettings::parse("write_metadata = \"exif\"").is_err());
+        assert_eq!(LifeSettings::parse("").unwrap(), LifeSettings::default());
+        assert!(LifeSettings::parse("hash_algorithm = \"md5\"").is_err());
+        assert!(LifeSettings::parse("hash = \"sha256\"").is_err());
+
+        let config = LifeConfig::new(PathBuf::from("/life"));
+        let file_manager = InMemoryFileManager::new();
+        let missing = LifeSettings::load_from_file(config.settings_file(), &file_manager);
+        assert_eq!(missing.unwrap(), LifeSettings::default());
+
+        file_manager.add_file(config.settings_file(), b"hash_algorithm = \"sha256\"\n");
+        let settings = LifeSettings::load_from_file(config.settings_file(), &file_manager).unwrap();
+        let config = config.with_settings(settings);
+        assert_eq!(config.hash_algorithm, HashAlgorithm::Sha256);
+
+        let settings = LifeSettings::parse("geonames_file = \"cities1000.txt\"\n").unwrap();
+        let config = config.with_settings(settings);
+        assert_eq!(
+            config.geonames_file,
+            Some(PathBuf::from("/life/cities1000.txt"))
+        );
+    }
 }
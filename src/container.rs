@@ -0,0 +1,524 @@
+use std::ops::Range;
+
+/// Major brand of Canon's CR3, an ISO base media file
+const CR3_BRAND: &[u8] = b"crx ";
+/// Box in a CR3's `moov` holding the `CMT1`...`CMT4` TIFF blocks
+const CANON_METADATA_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+/// CR3 blocks with IFD0, the EXIF IFD and the GPS IFD; `CMT3` holds maker notes
+const CR3_EXIF_BOXES: [&[u8; 4]; 3] = [b"CMT1", b"CMT2", b"CMT4"];
+const RAF_MAGIC: &[u8] = b"FUJIFILMCCD-RAW ";
+/// Offset of the big-endian offset and length of a RAF's JPEG preview
+const RAF_JPEG_POINTER: usize = 84;
+/// EXIF fits in one JPEG APP1 segment near the start, so at most this much of a preview
+/// is read
+const JPEG_EXIF_LIMIT: u64 = 128 * 1024;
+/// Boxes a QuickTime or MP4 movie can start with
+const MOVIE_FIRST_BOXES: [&[u8; 4]; 6] = [b"ftyp", b"moov", b"mdat", b"wide", b"free", b"skip"];
+/// QuickTime metadata key whose value is a Live Photo's content identifier
+const QUICKTIME_CONTENT_ID_KEY: &[u8] = b"com.apple.quicktime.content.identifier";
+/// Header of Apple's EXIF MakerNote: the signature, a version and the byte order
+const APPLE_MAKER_NOTE: &[u8] = b"Apple iOS\0\0\x01MM";
+/// Apple MakerNote tag holding the content identifier shared with a Live Photo's movie
+const APPLE_CONTENT_ID_TAG: u16 = 0x0011;
+
+/// How a block of a container holds its EXIF
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExifBlockKind {
+    /// HEIF `Exif` item: a 4-byte offset to the TIFF header, then the TIFF
+    HeifItem,
+    /// TIFF data
+    Tiff,
+    /// JPEG with an APP1 EXIF segment
+    Jpeg,
+}
+
+/// Byte range of a file that holds EXIF
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExifBlock {
+    pub kind: ExifBlockKind,
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ExifBlock {
+    /// The TIFF or JPEG within the block's bytes
+    pub fn payload<'a>(&self, bytes: &'a [u8]) -> Option<&'a [u8]> {
+        match self.kind {
+            ExifBlockKind::HeifItem => {
+                let header_offset = Reader::new(bytes, 0).u32()?;
+                bytes.get(4usize.checked_add(usize::try_from(header_offset).ok()?)?..)
+            }
+            ExifBlockKind::Tiff | ExifBlockKind::Jpeg => Some(bytes),
+        }
+    }
+}
+
+/// MIME type of a RAW format `infer` does not recognize
+pub fn raw_mime_type(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(RAF_MAGIC) {
+        Some("image/x-fuji-raf")
+    } else if ftyp_brand(head) == Some(CR3_BRAND) {
+        Some("image/x-canon-cr3")
+    } else {
+        None
+    }
+}
+
+/// Where a HEIF, AVIF, CR3 or RAF file keeps its EXIF, found from its first bytes.
+/// Blocks may lie past `head`; other files have none.
+pub fn exif_blocks(head: &[u8]) -> Vec<ExifBlock> {
+    if head.starts_with(RAF_MAGIC) {
+        return raf_block(head).into_iter().collect();
+    }
+    match ftyp_brand(head) {
+        Some(CR3_BRAND) => cr3_blocks(head),
+        Some(_) => heif_block(head).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Header of an ISO base media box
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxHeader {
+    pub kind: [u8; 4],
+    /// Bytes before the payload, 8 or 16
+    pub length: u64,
+    /// Size including the header; `None` for a box that runs to the end of the file
+    pub size: Option<u64>,
+}
+
+/// The header of the box starting `bytes`, which need hold only the header
+pub fn box_header(bytes: &[u8]) -> Option<BoxHeader> {
+    let mut reader = Reader::new(bytes, 0);
+    let size = reader.u32()?;
+    let kind = reader.bytes(4)?.try_into().ok()?;
+    let size = match size {
+        0 => None,
+        1 => Some(reader.u64()?),
+        size => Some(u64::from(size)),
+    };
+    let length = reader.offset as u64;
+    if size.is_some_and(|size| size < length) {
+        return None;
+    }
+    Some(BoxHeader { kind, length, size })
+}
+
+/// Whether `head` starts like a QuickTime or MP4 movie
+pub fn is_movie(head: &[u8]) -> bool {
+    box_header(head).is_some_and(|header| MOVIE_FIRST_BOXES.contains(&&header.kind))
+}
+
+/// Live Photo identifier in the `keys` and `ilst` boxes of a movie's `moov` payload
+pub fn quicktime_content_identifier(moov: &[u8]) -> Option<String> {
+    let top = boxes(moov, 0..moov.len());
+    let meta = find(&top, b"meta")?;
+    // QuickTime's `meta` is a plain box, ISO's a full box with a version and flags
+    let children = [0, 4]
+        .into_iter()
+        .map(|skip| boxes(moov, meta.payload.start + skip..meta.payload.end))
+        .find(|children| find(children, b"keys").is_some())?;
+
+    let keys = find(&children, b"keys")?;
+    let mut reader = Reader::new(moov, keys.payload.start + 4);
+    let count = reader.u32()?;
+    let mut index = None;
+    for key_index in 1..=count {
+        let size = usize::try_from(reader.u32()?).ok()?;
+        let _namespace = reader.bytes(4)?;
+        if reader.bytes(size.checked_sub(8)?)? == QUICKTIME_CONTENT_ID_KEY {
+            index = Some(key_index);
+            break;
+        }
+    }
+
+    // Items of `ilst` are named by the 1-based index of their key
+    let ilst = find(&children, b"ilst")?;
+    let items = boxes(moov, ilst.payload.clone());
+    let item = find(&items, &index?.to_be_bytes())?;
+    let values = boxes(moov, item.payload.clone());
+    let data = find(&values, b"data")?;
+    // A type indicator and a locale precede the value
+    let value = moov.get(data.payload.start + 8..data.payload.end)?;
+    identifier(value)
+}
+
+/// Live Photo identifier in an Apple EXIF MakerNote
+pub fn apple_content_identifier(maker_note: &[u8]) -> Option<String> {
+    if !maker_note.starts_with(APPLE_MAKER_NOTE) {
+        return None;
+    }
+    let mut reader = Reader::new(maker_note, APPLE_MAKER_NOTE.len());
+    let count = reader.u16()?;
+    for _ in 0..count {
+        let (tag, _format, length, value) =
+            (reader.u16()?, reader.u16()?, reader.u32()?, reader.u32()?);
+        if tag != APPLE_CONTENT_ID_TAG {
+            continue;
+        }
+        let length = usize::try_from(length).ok()?;
+        // Offsets are from the start of the MakerNote; values of up to 4 bytes are inline
+        let start = if length <= 4 {
+            reader.offset - 4
+        } else {
+            usize::try_from(value).ok()?
+        };
+        return identifier(maker_note.get(start..start.checked_add(length)?)?);
+    }
+    None
+}
+
+/// A content identifier from its text, upper-cased as Photos writes it
+fn identifier(value: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(value)
+        .ok()?
+        .trim_end_matches('\0')
+        .trim();
+    (!text.is_empty()).then(|| text.to_uppercase())
+}
+
+fn ftyp_brand(head: &[u8]) -> Option<&[u8]> {
+    (head.get(4..8)? == b"ftyp").then(|| head.get(8..12))?
+}
+
+/// A box of an ISO base media file, with the payload range it declares, which may run
+/// past the bytes read
+struct IsoBox {
+    kind: [u8; 4],
+    payload: Range<usize>,
+}
+
+/// The boxes in `range` of `bytes`, up to the first that starts past them
+fn boxes(bytes: &[u8], range: Range<usize>) -> Vec<IsoBox> {
+    let end = range.end.min(bytes.len());
+    let mut found = Vec::new();
+    let mut offset = range.start;
+    while offset < end {
+        let mut reader = Reader::new(bytes, offset);
+        let (Some(size), Some(kind)) = (reader.u32(), reader.bytes(4)) else {
+            break;
+        };
+        let size = match size {
+            0 => Some((end - offset) as u64),
+            1 => reader.u64(),
+            size => Some(u64::from(size)),
+        };
+        let header = reader.offset - offset;
+        let Some(box_end) = size
+            .and_then(|size| usize::try_from(size).ok())
+            .filter(|&size| size >= header)
+            .and_then(|size| offset.checked_add(size))
+        else {
+            break;
+        };
+        found.push(IsoBox {
+            kind: kind.try_into().expect("four bytes were read"),
+            payload: reader.offset..box_end,
+        });
+        offset = box_end;
+    }
+    found
+}
+
+fn find<'a>(boxes: &'a [IsoBox], kind: &[u8; 4]) -> Option<&'a IsoBox> {
+    boxes.iter().find(|found| &found.kind == kind)
+}
+
+/// The `Exif` item of a HEIF or AVIF file, located by the `iinf` and `iloc` boxes of `meta`
+fn heif_block(head: &[u8]) -> Option<ExifBlock> {
+    let top = boxes(head, 0..head.len());
+    let meta = find(&top, b"meta")?;
+    // `meta` and the boxes below are full boxes, with a version and flags first
+    let children = boxes(head, meta.payload.start + 4..meta.payload.end);
+    let item_id = exif_item_id(head, find(&children, b"iinf")?)?;
+    let (offset, length) = item_location(head, find(&children, b"iloc")?, item_id)?;
+    Some(ExifBlock {
+        kind: ExifBlockKind::HeifItem,
+        offset,
+        length,
+    })
+}
+
+fn exif_item_id(head: &[u8], iinf: &IsoBox) -> Option<u32> {
+    let mut reader = Reader::new(head, iinf.payload.start);
+    let version = reader.u8()?;
+    reader.skip(3);
+    if version == 0 {
+        reader.u16()?;
+    } else {
+        reader.u32()?;
+    }
+
+    boxes(head, reader.offset..iinf.payload.end)
+        .iter()
+        .filter(|entry| &entry.kind == b"infe")
+        .find_map(|entry| {
+            let mut reader = Reader::new(head, entry.payload.start);
+            let version = reader.u8()?;
+            reader.skip(3);
+            let id = match version {
+                2 => u32::from(reader.u16()?),
+                3 => reader.u32()?,
+                _ => return None,
+            };
+            reader.u16()?;
+            (reader.bytes(4)? == b"Exif").then_some(id)
+        })
+}
+
+/// Offset and length of a single-extent item stored in the file itself
+fn item_location(head: &[u8], iloc: &IsoBox, item_id: u32) -> Option<(u64, u64)> {
+    let mut reader = Reader::new(head, iloc.payload.start);
+    let version = reader.u8()?;
+    reader.skip(3);
+    let sizes = reader.u8()?;
+    let (offset_size, length_size) = (sizes >> 4, sizes & 0x0f);
+    let sizes = reader.u8()?;
+    let base_offset_size = sizes >> 4;
+    let index_size = if matches!(version, 1 | 2) {
+        sizes & 0x0f
+    } else {
+        0
+    };
+    let item_count = if version < 2 {
+        u32::from(reader.u16()?)
+    } else {
+        reader.u32()?
+    };
+
+    for _ in 0..item_count {
+        let id = if version < 2 {
+            u32::from(reader.u16()?)
+        } else {
+            reader.u32()?
+        };
+        let construction_method = if matches!(version, 1 | 2) {
+            reader.u16()? & 0x0f
+        } else {
+            0
+        };
+        reader.u16()?;
+        let base_offset = reader.uint(base_offset_size)?;
+        let extent_count = reader.u16()?;
+        let mut first_extent = None;
+        for _ in 0..extent_count {
+            reader.uint(index_size)?;
+            let extent = (reader.uint(offset_size)?, reader.uint(length_size)?);
+            first_extent.get_or_insert(extent);
+        }
+
+        if id == item_id {
+            // Items split into extents or kept in `idat` do not occur for EXIF in practice
+            if construction_method != 0 || extent_count != 1 {
+                return None;
+            }
+            let (offset, length) = first_extent?;
+            return Some((base_offset.checked_add(offset)?, length));
+        }
+    }
+    None
+}
+
+/// The `CMT` TIFF blocks in the Canon `uuid` box of a CR3's `moov`
+fn cr3_blocks(head: &[u8]) -> Vec<ExifBlock> {
+    let top = boxes(head, 0..head.len());
+    let Some(moov) = find(&top, b"moov") else {
+        return Vec::new();
+    };
+    let children = boxes(head, moov.payload.clone());
+    let Some(canon) = children.iter().find(|child| {
+        &child.kind == b"uuid"
+            && head.get(child.payload.start..child.payload.start + 16)
+                == Some(&CANON_METADATA_UUID[..])
+    }) else {
+        return Vec::new();
+    };
+
+    boxes(head, canon.payload.start + 16..canon.payload.end)
+        .iter()
+        .filter(|block| CR3_EXIF_BOXES.contains(&&block.kind))
+        .map(|block| ExifBlock {
+            kind: ExifBlockKind::Tiff,
+            offset: block.payload.start as u64,
+            length: block.payload.len() as u64,
+        })
+        .collect()
+}
+
+/// The start of a RAF's JPEG preview, whose EXIF describes the RAW image
+fn raf_block(head: &[u8]) -> Option<ExifBlock> {
+    let mut reader = Reader::new(head, RAF_JPEG_POINTER);
+    let offset = u64::from(reader.u32()?);
+    let length = u64::from(reader.u32()?);
+    Some(ExifBlock {
+        kind: ExifBlockKind::Jpeg,
+        offset,
+        length: length.min(JPEG_EXIF_LIMIT),
+    })
+}
+
+/// Big-endian reads from a byte slice, as ISO base media files and RAF headers use
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], offset: usize) -> Self {
+        Self { bytes, offset }
+    }
+
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.checked_add(count)?)?;
+        self.offset += count;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.offset = self.offset.saturating_add(count);
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    /// An unsigned integer of `size` bytes, as `iloc` declares them; 0 bytes read as 0
+    fn uint(&mut self, size: u8) -> Option<u64> {
+        match size {
+            0 => Some(0),
+            4 => self.u32().map(u64::from),
+            8 => self.u64(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exif_blocks() {
+        let heic = include_bytes!("../tests/fixtures/IMG_0001.HEIC");
+        let blocks = exif_blocks(heic);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].kind, ExifBlockKind::HeifItem);
+        let end = (blocks[0].offset + blocks[0].length) as usize;
+        assert_eq!(end, heic.len());
+        let item = &heic[blocks[0].offset as usize..end];
+        assert!(blocks[0].payload(item).unwrap().starts_with(b"MM\0*"));
+
+        let cr3 = include_bytes!("../tests/fixtures/IMG_0002.CR3");
+        assert_eq!(raw_mime_type(cr3), Some("image/x-canon-cr3"));
+        assert_eq!(exif_blocks(cr3).len(), 3);
+
+        let raf = include_bytes!("../tests/fixtures/DSCF0001.RAF");
+        assert_eq!(raw_mime_type(raf), Some("image/x-fuji-raf"));
+        assert_eq!(exif_blocks(raf)[0].kind, ExifBlockKind::Jpeg);
+
+        // Plain JPEG and TIFF files, and truncated containers, have no blocks
+        assert!(exif_blocks(&[0xFF, 0xD8, 0xFF, 0xE1]).is_empty());
+        assert!(exif_blocks(&heic[..40]).is_empty());
+        assert_eq!(raw_mime_type(b"II*\0"), None);
+    }
+
+    fn iso_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut bytes = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        bytes.extend(kind);
+        bytes.extend(payload);
+        bytes
+    }
+
+    /// `moov` payload with a QuickTime `meta` box holding `key` = `value`
+    fn movie_metadata(key: &[u8], value: &str) -> Vec<u8> {
+        let mut keys = vec![0, 0, 0, 0];
+        keys.extend(2u32.to_be_bytes());
+        for key in [b"com.apple.quicktime.make".as_slice(), key] {
+            keys.extend(((key.len() + 8) as u32).to_be_bytes());
+            keys.extend(b"mdta");
+            keys.extend(key);
+        }
+        let mut data = 1u32.to_be_bytes().to_vec();
+        data.extend([0; 4]);
+        data.extend(value.as_bytes());
+        let mut make = 1u32.to_be_bytes().to_vec();
+        make.extend([0; 4]);
+        make.extend(b"Apple");
+        let ilst = [
+            iso_box(&1u32.to_be_bytes(), &iso_box(b"data", &make)),
+            iso_box(&2u32.to_be_bytes(), &iso_box(b"data", &data)),
+        ]
+        .concat();
+        let meta = [
+            iso_box(b"hdlr", &[0; 25]),
+            iso_box(b"keys", &keys),
+            iso_box(b"ilst", &ilst),
+        ]
+        .concat();
+        [iso_box(b"mvhd", &[0; 100]), iso_box(b"meta", &meta)].concat()
+    }
+
+    #[test]
+    fn test_quicktime_content_identifier() {
+        let uuid = "4f0b8c1e-2d3a-4b5c-9e8f-0a1b2c3d4e5f";
+        let moov = movie_metadata(QUICKTIME_CONTENT_ID_KEY, uuid);
+
+        assert_eq!(
+            quicktime_content_identifier(&moov),
+            Some(uuid.to_uppercase())
+        );
+        let other = movie_metadata(b"com.apple.quicktime.model", uuid);
+        assert_eq!(quicktime_content_identifier(&other), None);
+
+        let header = box_header(&iso_box(b"moov", &moov)).unwrap();
+        assert_eq!(&header.kind, b"moov");
+        assert_eq!(header.length, 8);
+        assert_eq!(header.size, Some(moov.len() as u64 + 8));
+        assert!(is_movie(&iso_box(b"ftyp", b"qt  ")));
+        assert!(!is_movie(b"\xFF\xD8\xFF\xE0\0\x10JFIF"));
+    }
+
+    #[test]
+    fn test_apple_content_identifier() {
+        let uuid = "4F0B8C1E-2D3A-4B5C-9E8F-0A1B2C3D4E5F";
+        let mut maker_note = APPLE_MAKER_NOTE.to_vec();
+        maker_note.extend(2u16.to_be_bytes());
+        // An empty inline string, then the content identifier after the IFD
+        let value_offset = APPLE_MAKER_NOTE.len() + 2 + 2 * 12 + 4;
+        for (tag, count, value) in [
+            (0x000b, 1u32, 0u32),
+            (APPLE_CONTENT_ID_TAG, 37, value_offset as u32),
+        ] {
+            maker_note.extend(tag.to_be_bytes());
+            maker_note.extend(2u16.to_be_bytes());
+            maker_note.extend(count.to_be_bytes());
+            maker_note.extend(value.to_be_bytes());
+        }
+        maker_note.extend(0u32.to_be_bytes());
+        maker_note.extend(uuid.as_bytes());
+        maker_note.push(0);
+
+        assert_eq!(
+            apple_content_identifier(&maker_note),
+            Some(uuid.to_string())
+        );
+        assert_eq!(apple_content_identifier(&maker_note[..30]), None);
+        assert_eq!(apple_content_identifier(b"Nikon\0\x02\x10\0\0"), None);
+    }
+}
@@ -26,6 +26,21 @@ impl DocumentInput {
         }
     }
 
+    /// Validated input for `document_path`, suggesting a date from its name or
+    /// modification time when none is given. Sidecars and rules both go through here.
+    pub fn for_document<P: AsRef<Path>, F: FileManager>(
+        date: Option<String>,
+        description: String,
+        tags: Vec<String>,
+        document_path: P,
+        file_manager: &F,
+    ) -> Result<Self> {
+        let date = date.unwrap_or_else(|| suggest_document_date(&document_path, file_manager));
+        let input = Self::new(date, description, tags);
+        input.validate()?;
+        Ok(input)
+    }
+
     pub fn validate(&self) -> Result<()> {
         self.validate_date()?;
         self.validate_description()?;
@@ -206,21 +221,32 @@ pub fn today_date_string() -> String {
 /// ```
 pub fn extract_date_from_filename<P: AsRef<Path>>(filename: P) -> Option<String> {
     let filename_str = filename.as_ref().file_name()?.to_str()?;
+    extract_date_from_text(filename_str)
+}
 
+/// Extracts the first valid date from arbitrary text in YYYY-MM-DD format.
+///
+/// Uses the same patterns as [`extract_date_from_filename`].
+///
+/// # Examples
+/// ```
+/// # use cleanbox::document::extract_date_from_text;
+/// assert_eq!(extract_date_from_text("20240315"), Some("2024-03-15".to_string()));
+/// assert_eq!(extract_date_from_text("2024"), None);
+/// ```
+pub fn extract_date_from_text(text: &str) -> Option<String> {
     for regex in DATE_PATTERNS.iter() {
-        if let Some(captures) = regex.captures(filename_str) {
-            if captures.len() >= 4 {
-                let year = captures.get(1)?.as_str().parse::<u32>().ok()?;
-                let month = captures.get(2)?.as_str().parse::<u32>().ok()?;
-                let day = captures.get(3)?.as_str().parse::<u32>().ok()?;
-
-                // Basic validation
-                if (1900..=2100).contains(&year)
-                    && (1..=12).contains(&month)
-                    && (1..=31).contains(&day)
-                {
-                    return Some(format!("{year:04}-{month:02}-{day:02}"));
-                }
+        if let Some(captures) = regex.captures(text)
+            && captures.len() >= 4
+        {
+            let year = captures.get(1)?.as_str().parse::<u32>().ok()?;
+            let month = captures.get(2)?.as_str().parse::<u32>().ok()?;
+            let day = captures.get(3)?.as_str().parse::<u32>().ok()?;
+
+            // Basic validation
+            if (1900..=2100).contains(&year) && (1..=12).contains(&month) && (1..=31).contains(&day)
+            {
+                return Some(format!("{year:04}-{month:02}-{day:02}"));
             }
         }
     }
@@ -278,10 +304,10 @@ pub fn suggest_document_date<P: AsRef<Path>, F: FileManager>(
     }
 
     // Priority 2: Try to get filesystem modified time
-    if let Ok(modified_time) = file_manager.get_file_modified_time(&filename) {
-        if let Some(date_from_filesystem) = format_system_time_to_date(modified_time) {
-            return date_from_filesystem;
-        }
+    if let Ok(modified_time) = file_manager.get_file_modified_time(&filename)
+        && let Some(date_from_filesystem) = format_system_time_to_date(modified_time)
+    {
+        return date_from_filesystem;
     }
 
     // Priority 3: Fall back to current date
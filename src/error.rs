@@ -1,8 +1,14 @@
 use std::fmt;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub enum CleanboxError {
     Io(std::io::Error),
+    /// An IO error on a known file or directory
+    IoAtPath {
+        path: PathBuf,
+        source: std::io::Error,
+    },
     Exif(String),
     InvalidPath(String),
     InvalidDateTime(String),
@@ -13,12 +19,24 @@ pub enum CleanboxError {
     UserCancelled,
     InvalidUserInput(String),
     TagDictionaryCorrupted(String),
+    ViewerFailed(String),
+    InvalidSidecar(String),
+    InvalidRule(String),
+    InvalidSettings(String),
+    InvalidManifest(String),
+    InvalidTemplate(String),
+    InvalidGeonames(String),
+    /// Output such as the run report could not be encoded
+    Serialization(String),
 }
 
 impl fmt::Display for CleanboxError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CleanboxError::Io(err) => write!(f, "IO error: {err}"),
+            CleanboxError::IoAtPath { path, source } => {
+                write!(f, "IO error at {}: {}", path.display(), source)
+            }
             CleanboxError::Exif(msg) => write!(f, "EXIF error: {msg}"),
             CleanboxError::InvalidPath(path) => write!(f, "Invalid path: {path}"),
             CleanboxError::InvalidDateTime(dt) => write!(f, "Invalid datetime format: {dt}"),
@@ -35,6 +53,41 @@ impl fmt::Display for CleanboxError {
             CleanboxError::TagDictionaryCorrupted(msg) => {
                 write!(f, "Tag dictionary corrupted: {msg}")
             }
+            CleanboxError::ViewerFailed(msg) => write!(f, "Failed to open viewer: {msg}"),
+            CleanboxError::InvalidSidecar(msg) => write!(f, "Invalid sidecar file: {msg}"),
+            CleanboxError::InvalidRule(msg) => write!(f, "Invalid document rule: {msg}"),
+            CleanboxError::InvalidSettings(msg) => write!(f, "Invalid settings: {msg}"),
+            CleanboxError::InvalidManifest(msg) => write!(f, "Invalid manifest: {msg}"),
+            CleanboxError::InvalidTemplate(msg) => write!(f, "Invalid template: {msg}"),
+            CleanboxError::InvalidGeonames(msg) => write!(f, "Invalid GeoNames dataset: {msg}"),
+            CleanboxError::Serialization(msg) => write!(f, "Serialization error: {msg}"),
+        }
+    }
+}
+
+impl CleanboxError {
+    /// Stable snake_case name of the variant, for machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CleanboxError::Io(_) | CleanboxError::IoAtPath { .. } => "io",
+            CleanboxError::Exif(_) => "exif",
+            CleanboxError::InvalidPath(_) => "invalid_path",
+            CleanboxError::InvalidDateTime(_) => "invalid_date_time",
+            CleanboxError::InvalidFileExtension(_) => "invalid_file_extension",
+            CleanboxError::InvalidFileStem(_) => "invalid_file_stem",
+            CleanboxError::FileAlreadyExists(_) => "file_already_exists",
+            CleanboxError::UnsupportedFileType(_) => "unsupported_file_type",
+            CleanboxError::UserCancelled => "user_cancelled",
+            CleanboxError::InvalidUserInput(_) => "invalid_user_input",
+            CleanboxError::TagDictionaryCorrupted(_) => "tag_dictionary_corrupted",
+            CleanboxError::ViewerFailed(_) => "viewer_failed",
+            CleanboxError::InvalidSidecar(_) => "invalid_sidecar",
+            CleanboxError::InvalidRule(_) => "invalid_rule",
+            CleanboxError::InvalidSettings(_) => "invalid_settings",
+            CleanboxError::InvalidManifest(_) => "invalid_manifest",
+            CleanboxError::InvalidTemplate(_) => "invalid_template",
+            CleanboxError::InvalidGeonames(_) => "invalid_geonames",
+            CleanboxError::Serialization(_) => "serialization",
         }
     }
 }
@@ -43,6 +96,7 @@ impl std::error::Error for CleanboxError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             CleanboxError::Io(err) => Some(err),
+            CleanboxError::IoAtPath { source, .. } => Some(source),
             _ => None,
         }
     }
@@ -62,6 +116,20 @@ impl From<rexif::ExifError> for CleanboxError {
 
 pub type Result<T> = std::result::Result<T, CleanboxError>;
 
+/// Attach the affected path to IO errors
+pub trait IoResultExt<T> {
+    fn at_path<P: AsRef<Path>>(self, path: P) -> Result<T>;
+}
+
+impl<T> IoResultExt<T> for std::io::Result<T> {
+    fn at_path<P: AsRef<Path>>(self, path: P) -> Result<T> {
+        self.map_err(|source| CleanboxError::IoAtPath {
+            path: path.as_ref().to_path_buf(),
+            source,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -73,6 +141,15 @@ mod tests {
         let io_err = CleanboxError::Io(io::Error::new(io::ErrorKind::NotFound, "file not found"));
         assert!(format!("{io_err}").contains("IO error:"));
 
+        let path_io_err = CleanboxError::IoAtPath {
+            path: PathBuf::from("/inbox/a.jpg"),
+            source: io::Error::new(io::ErrorKind::NotFound, "file not found"),
+        };
+        assert_eq!(
+            format!("{path_io_err}"),
+            "IO error at /inbox/a.jpg: file not found"
+        );
+
         let exif_err = CleanboxError::Exif("parsing failed".to_string());
         assert_eq!(format!("{exif_err}"), "EXIF error: parsing failed");
 
@@ -114,6 +191,58 @@ mod tests {
             format!("{tag_err}"),
             "Tag dictionary corrupted: malformed tags.txt"
         );
+
+        let viewer_err = CleanboxError::ViewerFailed("'xdg-open' not found".to_string());
+        assert_eq!(
+            format!("{viewer_err}"),
+            "Failed to open viewer: 'xdg-open' not found"
+        );
+
+        let sidecar_err = CleanboxError::InvalidSidecar("missing description".to_string());
+        assert_eq!(
+            format!("{sidecar_err}"),
+            "Invalid sidecar file: missing description"
+        );
+
+        let rule_err = CleanboxError::InvalidRule("bad pattern".to_string());
+        assert_eq!(format!("{rule_err}"), "Invalid document rule: bad pattern");
+
+        let settings_err = CleanboxError::InvalidSettings("unknown key".to_string());
+        assert_eq!(format!("{settings_err}"), "Invalid settings: unknown key");
+
+        let manifest_err = CleanboxError::InvalidManifest("expected value".to_string());
+        assert_eq!(
+            format!("{manifest_err}"),
+            "Invalid manifest: expected value"
+        );
+
+        let template_err = CleanboxError::InvalidTemplate("unknown placeholder {yaer}".to_string());
+        assert_eq!(
+            format!("{template_err}"),
+            "Invalid template: unknown placeholder {yaer}"
+        );
+
+        let geonames_err = CleanboxError::InvalidGeonames("line 3: bad latitude".to_string());
+        assert_eq!(
+            format!("{geonames_err}"),
+            "Invalid GeoNames dataset: line 3: bad latitude"
+        );
+
+        let serialization_err = CleanboxError::Serialization("Cannot encode report".to_string());
+        assert_eq!(
+            format!("{serialization_err}"),
+            "Serialization error: Cannot encode report"
+        );
+    }
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(CleanboxError::Io(io::Error::other("x")).kind(), "io");
+        assert_eq!(
+            CleanboxError::UnsupportedFileType("text/plain".to_string()).kind(),
+            "unsupported_file_type"
+        );
+        assert_eq!(CleanboxError::UserCancelled.kind(), "user_cancelled");
     }
 
     #[test]
@@ -128,11 +257,24 @@ mod tests {
 
     #[test]
     fn test_error_source() {
-        let io_err = io::Error::new(io::ErrorKind::Other, "test error");
+        let io_err = io::Error::other("test error");
         let cleanbox_err = CleanboxError::Io(io_err);
         assert!(cleanbox_err.source().is_some());
 
         let exif_err = CleanboxError::Exif("test".to_string());
         assert!(exif_err.source().is_none());
     }
+
+    #[test]
+    fn test_at_path() {
+        let result: io::Result<()> = Err(io::Error::other("disk full"));
+        let err = result.at_path("/media/a.jpg").unwrap_err();
+
+        match &err {
+            CleanboxError::IoAtPath { path, .. } => assert_eq!(path, Path::new("/media/a.jpg")),
+            _ => panic!("Expected IoAtPath variant"),
+        }
+        assert_eq!(err.source().unwrap().to_string(), "disk full");
+        assert_eq!(err.kind(), "io");
+    }
 }
@@ -0,0 +1,442 @@
+use crate::filesystem::FileManager;
+use crate::media::{FileMetadata, GpsPosition};
+use crate::naming::DATETIME_FORMAT;
+use crate::sidecar::MediaSidecar;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Longest file name Google Takeout writes; longer names lose the end of their stem
+const TAKEOUT_NAME_LIMIT: usize = 47;
+
+/// Dates in iCloud's `Photo Details.csv`, such as `Monday December 5,2022 2:37 PM GMT`
+const ICLOUD_DATE_FORMAT: &str = "%A %B %d,%Y %I:%M %p GMT";
+
+lazy_static! {
+    /// The `(1)` Takeout puts before `.json` when two files share a title
+    static ref DUPLICATE_INDEX: Regex = Regex::new(r"\((\d+)\)\.json$").unwrap();
+}
+
+/// Capture time and place an export keeps beside a media file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportEntry {
+    pub datetime: Option<String>,
+    pub gps: Option<GpsPosition>,
+}
+
+/// Metadata that Google Takeout and iCloud Photos exports keep in JSON and CSV files
+/// instead of in the EXIF of the media
+#[derive(Debug, Default)]
+pub struct ExportMetadata {
+    entries: HashMap<PathBuf, ExportEntry>,
+    /// Takeout JSON files, keyed by the media file they describe
+    sidecars: HashMap<PathBuf, Vec<MediaSidecar>>,
+    /// Every JSON and CSV file of the export, which are never documents of their own
+    metadata_files: HashSet<PathBuf>,
+}
+
+impl ExportMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the Takeout JSON and iCloud `Photo Details` CSV files among `paths` and match
+    /// them to the media files among `paths`. Files that cannot be read are logged and
+    /// skipped.
+    pub fn scan<F: FileManager>(file_manager: &F, paths: &[PathBuf]) -> Self {
+        let mut export = Self::new();
+        let media: Vec<&PathBuf> = paths
+            .iter()
+            .filter(|path| !is_json(path) && !is_photo_details(path))
+            .collect();
+
+        for path in paths {
+            if is_json(path) {
+                export.metadata_files.insert(path.clone());
+                export.read_takeout_json(file_manager, path, &media);
+            } else if is_photo_details(path) {
+                export.metadata_files.insert(path.clone());
+                export.read_photo_details(file_manager, path, &media);
+            }
+        }
+
+        export
+    }
+
+    pub fn get(&self, media_path: &Path) -> Option<&ExportEntry> {
+        self.entries.get(media_path)
+    }
+
+    /// Fill what the EXIF of a media file lacks from the export, returning true if the
+    /// datetime came from the export
+    pub fn apply(&self, media_path: &Path, metadata: &mut FileMetadata) -> bool {
+        let Some(entry) = self.get(media_path) else {
+            return false;
+        };
+        if metadata.gps.is_none() {
+            metadata.gps = entry.gps;
+        }
+        if metadata.datetime_original.is_none() && entry.datetime.is_some() {
+            metadata.datetime_original.clone_from(&entry.datetime);
+            return true;
+        }
+        false
+    }
+
+    /// Takeout JSON files, keyed by the media file they describe
+    pub fn sidecars(&self) -> &HashMap<PathBuf, Vec<MediaSidecar>> {
+        &self.sidecars
+    }
+
+    /// Returns true for a JSON or CSV file of the export
+    pub fn is_metadata_file(&self, path: &Path) -> bool {
+        self.metadata_files.contains(path)
+    }
+
+    fn read_takeout_json<F: FileManager>(
+        &mut self,
+        file_manager: &F,
+        path: &Path,
+        media: &[&PathBuf],
+    ) {
+        let bytes = match file_manager.read_file(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::warn!("Cannot read {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let json: Value = match serde_json::from_slice(&bytes) {
+            Ok(json) => json,
+            Err(e) => {
+                log::warn!("Cannot parse {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        // Album and account files have no capture time and describe no media file
+        let datetime = ["photoTakenTime", "creationTime"]
+            .iter()
+            .find_map(|key| takeout_timestamp(&json[key]));
+        let Some(title) = json["title"].as_str().filter(|_| datetime.is_some()) else {
+            return;
+        };
+        let Some(media_path) = takeout_media(path, title, media) else {
+            log::debug!("No media file for {} ({})", path.display(), title);
+            return;
+        };
+
+        let gps = ["geoData", "geoDataExif"]
+            .iter()
+            .find_map(|key| takeout_position(&json[key]));
+        self.entries.insert(
+            media_path.clone(),
+            ExportEntry {
+                datetime: datetime.map(local_datetime),
+                gps,
+            },
+        );
+        self.sidecars
+            .entry(media_path.clone())
+            .or_default()
+            .push(MediaSidecar::after_name(path.to_path_buf(), ".json"));
+    }
+
+    fn read_photo_details<F: FileManager>(
+        &mut self,
+        file_manager: &F,
+        path: &Path,
+        media: &[&PathBuf],
+    ) {
+        let contents = match file_manager.read_file(path) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                log::warn!("Cannot read {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        let mut lines = contents.lines();
+        let header = lines.next().map(split_csv_line).unwrap_or_default();
+        let column = |name: &str| header.iter().position(|field| field == name);
+        let (Some(name_column), Some(date_column)) =
+            (column("imgName"), column("originalCreationDate"))
+        else {
+            log::warn!("{} has no imgName and originalCreationDate", path.display());
+            return;
+        };
+
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let fields = merge_split_dates(split_csv_line(line), header.len());
+            let (Some(name), Some(date)) = (fields.get(name_column), fields.get(date_column))
+            else {
+                continue;
+            };
+            let Ok(datetime) = NaiveDateTime::parse_from_str(date.trim(), ICLOUD_DATE_FORMAT)
+            else {
+                log::debug!("Cannot parse iCloud date {date} of {name}");
+                continue;
+            };
+            if let Some(media_path) = icloud_media(path, name, media) {
+                self.entries.entry(media_path.clone()).or_default().datetime =
+                    Some(local_datetime(datetime.and_utc()));
+            }
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// iCloud writes `Photo Details.csv`, and `Photo Details-1.csv` and so on for large libraries
+fn is_photo_details(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_lowercase)
+        .is_some_and(|name| name.starts_with("photo details") && name.ends_with(".csv"))
+}
+
+fn local_datetime(utc: DateTime<Utc>) -> String {
+    utc.with_timezone(&Local)
+        .format(DATETIME_FORMAT)
+        .to_string()
+}
+
+/// A Takeout `{"timestamp": "1714557600", ...}` object, where 0 means unknown
+fn takeout_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    let seconds: i64 = value["timestamp"].as_str()?.parse().ok()?;
+    (seconds != 0)
+        .then(|| DateTime::from_timestamp(seconds, 0))
+        .flatten()
+}
+
+/// A Takeout `{"latitude": 48.85, "longitude": 2.35, ...}` object, where 0, 0 means unknown
+fn takeout_position(value: &Value) -> Option<GpsPosition> {
+    let latitude = value["latitude"].as_f64()?;
+    let longitude = value["longitude"].as_f64()?;
+    (latitude != 0.0 || longitude != 0.0).then(|| GpsPosition::new(latitude, longitude))
+}
+
+/// Find the media file a Takeout JSON describes.
+///
+/// The JSON's name may be truncated, so the match starts from the title inside it. A
+/// `(1)` before `.json` moves before the extension of the media file, and a title longer
+/// than Takeout's name limit matches a media file named with the start of it.
+fn takeout_media<'a>(json_path: &Path, title: &str, media: &[&'a PathBuf]) -> Option<&'a PathBuf> {
+    let directory = json_path.parent()?;
+    let json_name = json_path.file_name()?.to_str()?;
+    let index = DUPLICATE_INDEX
+        .captures(json_name)
+        .map(|captures| format!("({})", &captures[1]))
+        .unwrap_or_default();
+    let (title_stem, title_ext) = title.rsplit_once('.').unwrap_or((title, ""));
+    let expected = format!("{title_stem}{index}.{title_ext}");
+
+    let siblings: Vec<(&'a PathBuf, &str)> = media
+        .iter()
+        .filter(|path| path.parent() == Some(directory))
+        .filter_map(|path| Some((*path, path.file_name()?.to_str()?)))
+        .collect();
+    if let Some((path, _)) = siblings
+        .iter()
+        .find(|(_, name)| *name == expected)
+        .or_else(|| {
+            siblings
+                .iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(&expected))
+        })
+    {
+        return Some(path);
+    }
+
+    if title.chars().count() <= TAKEOUT_NAME_LIMIT {
+        return None;
+    }
+    let mut truncated = siblings.iter().filter(|(_, name)| {
+        let (stem, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+        ext.eq_ignore_ascii_case(title_ext)
+            && stem
+                .strip_suffix(index.as_str())
+                .is_some_and(|stem| !stem.is_empty() && title_stem.starts_with(stem))
+            && (index.is_empty() || stem.ends_with(index.as_str()))
+    });
+    // Two files cut from similar titles cannot be told apart
+    match (truncated.next(), truncated.next()) {
+        (Some((path, _)), None) => Some(path),
+        _ => None,
+    }
+}
+
+/// Find the media file named `name` in an iCloud export, preferring the CSV's folder
+fn icloud_media<'a>(csv_path: &Path, name: &str, media: &[&'a PathBuf]) -> Option<&'a PathBuf> {
+    let named: Vec<&'a PathBuf> = media
+        .iter()
+        .filter(|path| path.file_name().and_then(|n| n.to_str()) == Some(name))
+        .copied()
+        .collect();
+    named
+        .iter()
+        .find(|path| path.parent() == csv_path.parent())
+        .or_else(|| (named.len() == 1).then(|| &named[0]))
+        .copied()
+}
+
+/// Split a CSV line on commas outside double quotes
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Rejoin dates that older iCloud exports left unquoted, such as `Monday December 5,2022 ...`,
+/// which splitting cut in two
+fn merge_split_dates(fields: Vec<String>, columns: usize) -> Vec<String> {
+    if fields.len() <= columns {
+        return fields;
+    }
+    let mut merged: Vec<String> = Vec::with_capacity(columns);
+    for field in fields {
+        let year_follows = field.chars().take(4).filter(char::is_ascii_digit).count() == 4;
+        match merged.last_mut() {
+            Some(last) if year_follows && last.ends_with(|c: char| c.is_ascii_digit()) => {
+                last.push(',');
+                last.push_str(&field);
+            }
+            _ => merged.push(field),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::InMemoryFileManager;
+
+    fn takeout_json(title: &str, timestamp: i64) -> String {
+        format!(
+            r#"{{"title": "{title}", "photoTakenTime": {{"timestamp": "{timestamp}"}},
+                "geoData": {{"latitude": 48.8584, "longitude": 2.2945, "altitude": 0.0}}}}"#
+        )
+    }
+
+    fn local(timestamp: i64) -> Option<String> {
+        Some(local_datetime(
+            DateTime::from_timestamp(timestamp, 0).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_takeout_json_matches_plain_duplicate_and_truncated_names() {
+        let file_manager = InMemoryFileManager::new();
+        let dir = "/export/Takeout/Google Photos/Photos from 2024";
+        let long_title = "Screenshot_20240501-100000_A Rather Long App Name.png";
+        let truncated = "Screenshot_20240501-100000_A Rather Long App N.png";
+        for (name, contents) in [
+            ("IMG_0001.JPG", String::new()),
+            (
+                "IMG_0001.JPG.supplemental-metadata.json",
+                takeout_json("IMG_0001.JPG", 1714557600),
+            ),
+            ("IMG_0001(1).JPG", String::new()),
+            (
+                "IMG_0001.JPG(1).json",
+                takeout_json("IMG_0001.JPG", 1714557660),
+            ),
+            (truncated, String::new()),
+            (
+                "Screenshot_20240501-100000_A Rather Long App.json",
+                takeout_json(long_title, 1714557720),
+            ),
+            (
+                "metadata.json",
+                r#"{"title": "Photos from 2024"}"#.to_string(),
+            ),
+        ] {
+            file_manager.add_file(format!("{dir}/{name}"), contents);
+        }
+        let paths = crate::filesystem::walk_files(&file_manager, "/export").unwrap();
+
+        let export = ExportMetadata::scan(&file_manager, &paths);
+
+        let entry = |name: &str| export.get(&Path::new(dir).join(name)).cloned();
+        assert_eq!(
+            entry("IMG_0001.JPG"),
+            Some(ExportEntry {
+                datetime: local(1714557600),
+                gps: Some(GpsPosition::new(48.8584, 2.2945)),
+            })
+        );
+        assert_eq!(
+            entry("IMG_0001(1).JPG").unwrap().datetime,
+            local(1714557660)
+        );
+        assert_eq!(entry(truncated).unwrap().datetime, local(1714557720));
+
+        let sidecar = &export.sidecars()[&Path::new(dir).join("IMG_0001(1).JPG")][0];
+        assert_eq!(
+            sidecar.renamed("2024-05-01_10-01-00.JPG"),
+            "2024-05-01_10-01-00.JPG.json"
+        );
+        assert!(export.is_metadata_file(&Path::new(dir).join("metadata.json")));
+        assert_eq!(export.sidecars().len(), 3);
+    }
+
+    #[test]
+    fn test_icloud_photo_details() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/export/Photos/IMG_0001.HEIC", b"");
+        file_manager.add_file("/export/Photos/IMG_0002.HEIC", b"");
+        file_manager.add_file("/export/Photos/写真.HEIC", b"");
+        file_manager.add_file(
+            "/export/Photos/Photo Details.csv",
+            "imgName,fileChecksum,favorite,hidden,deleted,originalCreationDate,viewCount,importDate\n\
+             IMG_0001.HEIC,abc=,no,no,no,\"Wednesday May 1,2024 8:00 AM GMT\",0,\"Wednesday May 1,2024 9:00 AM GMT\"\n\
+             IMG_0002.HEIC,def=,no,no,no,Wednesday May 1,2024 2:05 PM GMT,3,Wednesday May 1,2024 3:00 PM GMT\n\
+             写真.HEIC,ghi=,no,no,no,Wednesday May 1,2024 2:05 PM GMT,1,Wednesday May 1,2024 3:00 PM GMT\n",
+        );
+        let paths = crate::filesystem::walk_files(&file_manager, "/export").unwrap();
+
+        let export = ExportMetadata::scan(&file_manager, &paths);
+
+        let datetime = |name: &str| {
+            export
+                .get(&Path::new("/export/Photos").join(name))
+                .and_then(|entry| entry.datetime.clone())
+        };
+        assert_eq!(datetime("IMG_0001.HEIC"), local(1714550400));
+        assert_eq!(datetime("IMG_0002.HEIC"), local(1714572300));
+        // A name whose fourth byte is inside a character next to a split date
+        assert_eq!(datetime("写真.HEIC"), local(1714572300));
+        assert!(export.sidecars().is_empty());
+
+        // EXIF wins over the export, which only fills the gaps
+        let mut metadata = FileMetadata::new("image/heic".to_string())
+            .with_datetime("2024-05-01_10-00-00".to_string());
+        assert!(!export.apply(Path::new("/export/Photos/IMG_0001.HEIC"), &mut metadata));
+        assert_eq!(
+            metadata.datetime_original.as_deref(),
+            Some("2024-05-01_10-00-00")
+        );
+    }
+}
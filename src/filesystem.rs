@@ -1,7 +1,7 @@
-use crate::error::{CleanboxError, Result};
-use sha1::{Digest, Sha1};
+use crate::error::{CleanboxError, IoResultExt, Result};
+use crate::hashing::{ContentHasher, HashAlgorithm};
 use std::fs;
-use std::io::Read;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 
 #[cfg(test)]
@@ -12,9 +12,44 @@ pub trait FileManager {
     fn create_directories<P: AsRef<Path>>(&self, path: P) -> Result<()>;
     fn rename_file<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()>;
     fn move_file<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()>;
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+    /// Remove a directory, failing unless it is empty
+    fn remove_directory<P: AsRef<Path>>(&self, path: P) -> Result<()>;
     fn file_exists<P: AsRef<Path>>(&self, path: P) -> bool;
     fn is_file<P: AsRef<Path>>(&self, path: P) -> bool;
-    fn calculate_file_hash<P: AsRef<Path>>(&self, path: P) -> Result<String>;
+    fn file_size<P: AsRef<Path>>(&self, path: P) -> Result<u64>;
+    fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>>;
+
+    /// Open a file for streaming; the default reads it into memory
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Box<dyn Read + Send>> {
+        Ok(Box::new(Cursor::new(self.read_file(path)?)))
+    }
+
+    /// Up to `length` bytes from `offset`, fewer at the end of the file; the default
+    /// skips ahead in `open_file`
+    fn read_range<P: AsRef<Path>>(&self, path: P, offset: u64, length: u64) -> Result<Vec<u8>> {
+        let path = path.as_ref();
+        let mut reader = self.open_file(path)?;
+        io::copy(&mut (&mut reader).take(offset), &mut io::sink()).at_path(path)?;
+        let mut bytes = Vec::new();
+        reader.take(length).read_to_end(&mut bytes).at_path(path)?;
+        Ok(bytes)
+    }
+
+    fn write_file<P: AsRef<Path>>(&self, path: P, contents: &[u8]) -> Result<()>;
+
+    /// Hex digest of the file contents with `algorithm`
+    fn hash_file<P: AsRef<Path>>(&self, path: P, algorithm: HashAlgorithm) -> Result<String> {
+        let path = path.as_ref();
+        let reader = self.open_file(path)?;
+        ContentHasher::hash_reader(algorithm, reader).at_path(path)
+    }
+
+    /// Hex digest of the file contents with the default algorithm
+    fn calculate_file_hash<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        self.hash_file(path, HashAlgorithm::default())
+    }
+
     fn get_file_modified_time<P: AsRef<Path>>(&self, path: P) -> Result<std::time::SystemTime>;
 }
 
@@ -35,11 +70,12 @@ impl Default for StdFileManager {
 
 impl FileManager for StdFileManager {
     fn read_directory<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>> {
-        let entries = fs::read_dir(path)?;
+        let path = path.as_ref();
+        let entries = fs::read_dir(path).at_path(path)?;
         let mut paths = Vec::new();
 
         for entry in entries {
-            let entry = entry?;
+            let entry = entry.at_path(path)?;
             paths.push(entry.path());
         }
 
@@ -47,13 +83,11 @@ impl FileManager for StdFileManager {
     }
 
     fn create_directories<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        fs::create_dir_all(path)?;
-        Ok(())
+        fs::create_dir_all(&path).at_path(path)
     }
 
     fn rename_file<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
-        fs::rename(from, to)?;
-        Ok(())
+        fs::rename(&from, to).at_path(from)
     }
 
     fn move_file<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
@@ -63,6 +97,14 @@ impl FileManager for StdFileManager {
         self.rename_file(from, to)
     }
 
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::remove_file(&path).at_path(path)
+    }
+
+    fn remove_directory<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::remove_dir(&path).at_path(path)
+    }
+
     fn file_exists<P: AsRef<Path>>(&self, path: P) -> bool {
         path.as_ref().exists()
     }
@@ -71,29 +113,62 @@ impl FileManager for StdFileManager {
         path.as_ref().is_file()
     }
 
-    fn calculate_file_hash<P: AsRef<Path>>(&self, path: P) -> Result<String> {
-        let mut file = fs::File::open(path)?;
-        let mut hasher = Sha1::new();
-        let mut buffer = [0u8; 8192];
-
-        loop {
-            let n = file.read(&mut buffer)?;
-            if n == 0 {
-                break;
-            }
-            hasher.update(&buffer[..n]);
-        }
+    fn file_size<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        Ok(fs::metadata(&path).at_path(path)?.len())
+    }
+
+    fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        fs::read(&path).at_path(path)
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(&path).at_path(path)?))
+    }
+
+    fn read_range<P: AsRef<Path>>(&self, path: P, offset: u64, length: u64) -> Result<Vec<u8>> {
+        let path = path.as_ref();
+        let mut file = fs::File::open(path).at_path(path)?;
+        file.seek(SeekFrom::Start(offset)).at_path(path)?;
+        let mut bytes = Vec::new();
+        file.take(length).read_to_end(&mut bytes).at_path(path)?;
+        Ok(bytes)
+    }
 
-        let hash = hasher.finalize();
-        Ok(format!("{hash:x}"))
+    fn write_file<P: AsRef<Path>>(&self, path: P, contents: &[u8]) -> Result<()> {
+        fs::write(&path, contents).at_path(path)
     }
 
     fn get_file_modified_time<P: AsRef<Path>>(&self, path: P) -> Result<std::time::SystemTime> {
-        let metadata = fs::metadata(path)?;
-        Ok(metadata.modified()?)
+        let metadata = fs::metadata(&path).at_path(&path)?;
+        metadata.modified().at_path(path)
     }
 }
 
+/// All files below `root`, sorted; an absent `root` has none
+pub fn walk_files<F: FileManager, P: AsRef<Path>>(
+    file_manager: &F,
+    root: P,
+) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![root.as_ref().to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        if !file_manager.file_exists(&directory) {
+            continue;
+        }
+        for entry in file_manager.read_directory(&directory)? {
+            if file_manager.is_file(&entry) {
+                files.push(entry);
+            } else {
+                directories.push(entry);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
 pub struct FileHasher;
 
 impl FileHasher {
@@ -178,6 +253,14 @@ impl FileManager for MockFileManager {
         Ok(())
     }
 
+    fn remove_file<P: AsRef<Path>>(&self, _path: P) -> Result<()> {
+        Ok(())
+    }
+
+    fn remove_directory<P: AsRef<Path>>(&self, _path: P) -> Result<()> {
+        Ok(())
+    }
+
     fn file_exists<P: AsRef<Path>>(&self, path: P) -> bool {
         self.files.contains_key(path.as_ref())
     }
@@ -186,18 +269,29 @@ impl FileManager for MockFileManager {
         self.files.contains_key(path.as_ref())
     }
 
-    fn calculate_file_hash<P: AsRef<Path>>(&self, path: P) -> Result<String> {
-        if let Some(content) = self.files.get(path.as_ref()) {
-            let mut hasher = sha1::Sha1::new();
-            hasher.update(content);
-            let hash = hasher.finalize();
-            Ok(format!("{hash:x}"))
-        } else {
-            Err(CleanboxError::Io(std::io::Error::new(
+    fn file_size<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        self.files
+            .get(path.as_ref())
+            .map(|content| content.len() as u64)
+            .ok_or_else(|| {
+                CleanboxError::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "File not found",
+                ))
+            })
+    }
+
+    fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        self.files.get(path.as_ref()).cloned().ok_or_else(|| {
+            CleanboxError::Io(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
                 "File not found",
-            )))
-        }
+            ))
+        })
+    }
+
+    fn write_file<P: AsRef<Path>>(&self, _path: P, _contents: &[u8]) -> Result<()> {
+        Ok(())
     }
 
     fn get_file_modified_time<P: AsRef<Path>>(&self, path: P) -> Result<std::time::SystemTime> {
@@ -256,6 +350,73 @@ mod tests {
         assert!(!hash1.is_empty());
     }
 
+    #[test]
+    fn test_std_file_manager_errors_carry_path() {
+        let manager = StdFileManager::new();
+        let missing = PathBuf::from("/nonexistent/cleanbox/file.jpg");
+
+        match manager.calculate_file_hash(&missing).unwrap_err() {
+            CleanboxError::IoAtPath { path, source } => {
+                assert_eq!(path, missing);
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("Expected IoAtPath, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_streamed_hash_matches_file_hash() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("video.mp4");
+        let contents: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &contents).unwrap();
+
+        let manager = StdFileManager::new();
+        let mut streamed = Vec::new();
+        manager
+            .open_file(&path)
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .unwrap();
+
+        assert_eq!(streamed, contents);
+        assert_eq!(
+            manager.calculate_file_hash(&path).unwrap(),
+            ContentHasher::hash_bytes(HashAlgorithm::Sha1, &contents)
+        );
+    }
+
+    #[test]
+    fn test_read_range() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("IMG_0001.DNG");
+        fs::write(&path, b"0123456789").unwrap();
+        let mut mock = MockFileManager::new();
+        mock.add_file(PathBuf::from("/inbox/IMG_0001.DNG"), b"0123456789".to_vec());
+
+        let std_manager = StdFileManager::new();
+        assert_eq!(std_manager.read_range(&path, 2, 3).unwrap(), b"234");
+        assert_eq!(std_manager.read_range(&path, 8, 5).unwrap(), b"89");
+        assert_eq!(
+            mock.read_range("/inbox/IMG_0001.DNG", 2, 3).unwrap(),
+            b"234"
+        );
+        assert!(
+            mock.read_range("/inbox/IMG_0001.DNG", 20, 5)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_mock_file_manager_file_size() {
+        let mut manager = MockFileManager::new();
+        manager.add_file(PathBuf::from("/test/file1.txt"), b"12345".to_vec());
+
+        assert_eq!(manager.file_size("/test/file1.txt").unwrap(), 5);
+        assert!(manager.file_size("/test/nonexistent.txt").is_err());
+    }
+
     #[test]
     fn test_mock_file_manager_calculate_hash_nonexistent() {
         let manager = MockFileManager::new();
@@ -270,6 +431,13 @@ mod tests {
         assert_eq!(FileHasher::generate_hash_suffix(hash, 3), "abc");
         assert_eq!(FileHasher::generate_hash_suffix(hash, 20), hash); // longer than hash
         assert_eq!(FileHasher::generate_hash_suffix(hash, 0), "");
+
+        for algorithm in HashAlgorithm::ALL {
+            let digest = ContentHasher::hash_bytes(algorithm, b"photo");
+            let suffix = FileHasher::generate_hash_suffix(&digest, 6);
+            assert_eq!(suffix.len(), 6);
+            assert!(digest.starts_with(&suffix));
+        }
     }
 
     #[test]
@@ -369,6 +537,25 @@ mod tests {
         assert_eq!(modified_time, test_time);
     }
 
+    #[test]
+    fn test_walk_files() {
+        let manager = crate::testing::InMemoryFileManager::new();
+        manager.add_file("/life/media/2024/05/b.jpg", b"b");
+        manager.add_file("/life/media/2024/a.jpg", b"a");
+        manager.add_file("/life/media/c.jpg", b"c");
+        manager.add_directory("/life/media/empty");
+
+        assert_eq!(
+            walk_files(&manager, "/life/media").unwrap(),
+            vec![
+                PathBuf::from("/life/media/2024/05/b.jpg"),
+                PathBuf::from("/life/media/2024/a.jpg"),
+                PathBuf::from("/life/media/c.jpg"),
+            ]
+        );
+        assert!(walk_files(&manager, "/life/documents").unwrap().is_empty());
+    }
+
     // Integration tests with StdFileManager would require actual file system operations
     // These are typically run in a separate test environment or with temp directories
 }
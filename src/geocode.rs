@@ -0,0 +1,221 @@
+use crate::config::LifeConfig;
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::FileManager;
+use crate::media::{FileMetadata, GpsPosition};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Major cities bundled with cleanbox, in the GeoNames `cities*.txt` layout
+const BUNDLED_CITIES: &str = include_str!("../data/cities.tsv");
+const COUNTRY_NAMES: &str = include_str!("../data/countries.tsv");
+
+/// Farthest a city can be from a position and still name it
+const MAX_DISTANCE_KM: f64 = 100.0;
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const KM_PER_DEGREE: f64 = EARTH_RADIUS_KM * std::f64::consts::PI / 180.0;
+
+lazy_static! {
+    static ref COUNTRIES: HashMap<&'static str, &'static str> = COUNTRY_NAMES
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('\t'))
+        .collect();
+}
+
+/// English name of an ISO 3166 country code such as `PT`
+pub fn country_name(code: &str) -> Option<&'static str> {
+    COUNTRIES.get(code).copied()
+}
+
+/// A populated place of a GeoNames-style dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub name: String,
+    pub country_code: String,
+    pub position: GpsPosition,
+}
+
+impl Place {
+    /// Country name, or its code if the name is not known
+    pub fn country(&self) -> &str {
+        country_name(&self.country_code).unwrap_or(&self.country_code)
+    }
+}
+
+/// Offline reverse geocoder naming the nearest city to a GPS position
+#[derive(Debug)]
+pub struct Geocoder {
+    places: Vec<Place>,
+    /// Indexes into `places` by whole-degree latitude and longitude
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl Geocoder {
+    /// The major cities bundled with cleanbox
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_CITIES).expect("bundled cities are valid")
+    }
+
+    /// Parse a dataset in the layout of GeoNames' `cities*.txt`: tab-separated, with the
+    /// name in column 2, latitude and longitude in columns 5 and 6 and the country code in
+    /// column 9
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut places = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid =
+                |msg: &str| CleanboxError::InvalidGeonames(format!("line {}: {}", number + 1, msg));
+            let columns: Vec<&str> = line.split('\t').collect();
+            if columns.len() < 9 {
+                return Err(invalid("expected at least 9 tab-separated columns"));
+            }
+            let coordinate = |column: &str, limit: f64| {
+                column
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.abs() <= limit)
+            };
+            let latitude = coordinate(columns[4], 90.0).ok_or_else(|| invalid("bad latitude"))?;
+            let longitude =
+                coordinate(columns[5], 180.0).ok_or_else(|| invalid("bad longitude"))?;
+            places.push(Place {
+                name: columns[1].trim().to_string(),
+                country_code: columns[8].trim().to_uppercase(),
+                position: GpsPosition::new(latitude, longitude),
+            });
+        }
+
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (index, place) in places.iter().enumerate() {
+            cells.entry(cell(place.position)).or_default().push(index);
+        }
+        Ok(Self { places, cells })
+    }
+
+    /// The dataset at `path`, or the bundled one without a path
+    pub fn load<F: FileManager>(path: Option<&Path>, file_manager: &F) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::bundled());
+        };
+        let bytes = file_manager.read_file(path).map_err(|e| {
+            CleanboxError::InvalidGeonames(format!("Cannot read {}: {}", path.display(), e))
+        })?;
+        Self::parse(&String::from_utf8_lossy(&bytes)).map_err(|e| match e {
+            CleanboxError::InvalidGeonames(msg) => {
+                CleanboxError::InvalidGeonames(format!("{}: {}", path.display(), msg))
+            }
+            e => e,
+        })
+    }
+
+    /// The geocoder for `life_config`, or None if its media layout names no place
+    pub fn for_life<F: FileManager>(
+        life_config: &LifeConfig,
+        file_manager: &F,
+    ) -> Result<Option<Self>> {
+        if !life_config.media_layout.needs_location() {
+            return Ok(None);
+        }
+        Self::load(life_config.geonames_file.as_deref(), file_manager).map(Some)
+    }
+
+    /// The nearest place within `MAX_DISTANCE_KM`
+    pub fn lookup(&self, position: GpsPosition) -> Option<&Place> {
+        let (lat_cell, lon_cell) = cell(position);
+        // A degree of longitude narrows towards the poles, so search more of them there
+        let widest = (position.latitude.abs() + 1.0).min(89.0).to_radians().cos();
+        let lon_cells = ((MAX_DISTANCE_KM / (KM_PER_DEGREE * widest)).ceil() as i32).min(180);
+
+        (lat_cell - 1..=lat_cell + 1)
+            .flat_map(|lat| {
+                (lon_cell - lon_cells..=lon_cell + lon_cells)
+                    .map(move |lon| (lat, (lon + 180).rem_euclid(360) - 180))
+            })
+            .filter_map(|key| self.cells.get(&key))
+            .flatten()
+            .map(|&index| {
+                let place = &self.places[index];
+                (distance_km(position, place.position), place)
+            })
+            .filter(|(distance, _)| *distance <= MAX_DISTANCE_KM)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, place)| place)
+    }
+
+    /// Fill the city and country of media with a GPS position but no city yet
+    pub fn locate(&self, metadata: &mut FileMetadata) {
+        if metadata.city.is_some() {
+            return;
+        }
+        if let Some(place) = metadata.gps.and_then(|gps| self.lookup(gps)) {
+            metadata.city = Some(place.name.clone());
+            metadata.country = Some(place.country().to_string());
+        }
+    }
+}
+
+fn cell(position: GpsPosition) -> (i32, i32) {
+    (
+        position.latitude.floor() as i32,
+        position.longitude.floor() as i32,
+    )
+}
+
+/// Great-circle distance by the haversine formula
+pub(crate) fn distance_km(a: GpsPosition, b: GpsPosition) -> f64 {
+    let (lat_a, lat_b) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let half_lat = (lat_b - lat_a) / 2.0;
+    let half_lon = (b.longitude - a.longitude).to_radians() / 2.0;
+    let h = half_lat.sin().powi(2) + lat_a.cos() * lat_b.cos() * half_lon.sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_lookup() {
+        let geocoder = Geocoder::bundled();
+
+        // Belém, a few kilometres west of central Lisbon
+        let place = geocoder.lookup(GpsPosition::new(38.6916, -9.2160)).unwrap();
+        assert_eq!(place.name, "Lisbon");
+        assert_eq!(place.country(), "Portugal");
+
+        // Nothing lies within reach in the middle of the Pacific
+        assert!(geocoder.lookup(GpsPosition::new(0.0, -140.0)).is_none());
+
+        let mut metadata =
+            FileMetadata::new("image/jpeg".to_string()).with_gps(GpsPosition::new(40.7, -74.0));
+        geocoder.locate(&mut metadata);
+        assert_eq!(
+            metadata.place().as_deref(),
+            Some("New York City, United States")
+        );
+    }
+
+    #[test]
+    fn test_parse_geonames_lines() {
+        let geocoder = Geocoder::parse(
+            "# comment\n\
+             2267057\tLisbon\tLisbon\t\t38.71667\t-9.13333\tP\tPPLC\tPT\t\t14\t\t\t\t517802\t\t45\tEurope/Lisbon\t2022-03-12\n\
+             1\tCascais\tCascais\t\t38.69750\t-9.42146\tP\tPPLA2\tpt\n",
+        )
+        .unwrap();
+
+        let place = geocoder.lookup(GpsPosition::new(38.70, -9.40)).unwrap();
+        assert_eq!(place.name, "Cascais");
+        assert_eq!(place.country_code, "PT");
+
+        let err = Geocoder::parse("1\tNowhere\tNowhere\t\tnorth\t0\tP\tPPL\tXX\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid GeoNames dataset: line 1: bad latitude"
+        );
+    }
+}
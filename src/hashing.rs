@@ -0,0 +1,203 @@
+use crate::error::{CleanboxError, Result};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+use xxhash_rust::xxh3::Xxh3;
+
+/// Content hash used for duplicate suffixes and persisted checksums
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    /// The original algorithm, kept as default so existing suffixes stay stable
+    #[default]
+    Sha1,
+    /// For integrity manifests
+    Sha256,
+    /// Cryptographic and fast
+    Blake3,
+    /// Fastest, for deduplication only
+    Xxh3,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 4] = [
+        HashAlgorithm::Sha1,
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Xxh3,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Xxh3 => "xxh3",
+        }
+    }
+
+    /// A digest prefixed with the algorithm, such as `blake3:af13...`, for storage
+    pub fn tag(&self, digest: &str) -> String {
+        format!("{}:{}", self.name(), digest)
+    }
+
+    /// Split a tagged digest into its algorithm and digest
+    pub fn parse_tagged(tagged: &str) -> Result<(HashAlgorithm, &str)> {
+        let (name, digest) = tagged.split_once(':').ok_or_else(|| {
+            CleanboxError::InvalidSettings(format!("Hash without algorithm: {tagged}"))
+        })?;
+        Ok((name.parse()?, digest))
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = CleanboxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        HashAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == s.trim().to_lowercase())
+            .ok_or_else(|| {
+                CleanboxError::InvalidSettings(format!(
+                    "Unknown hash algorithm '{s}', expected one of sha1, sha256, blake3, xxh3"
+                ))
+            })
+    }
+}
+
+enum HasherState {
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+}
+
+/// Incremental content hash with any `HashAlgorithm`
+pub struct ContentHasher {
+    state: HasherState,
+}
+
+impl ContentHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        let state = match algorithm {
+            HashAlgorithm::Sha1 => HasherState::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => HasherState::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => HasherState::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Xxh3 => HasherState::Xxh3(Box::new(Xxh3::new())),
+        };
+        Self { state }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match &mut self.state {
+            HasherState::Sha1(hasher) => hasher.update(bytes),
+            HasherState::Sha256(hasher) => hasher.update(bytes),
+            HasherState::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+            HasherState::Xxh3(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// Lowercase hex digest
+    pub fn finish(self) -> String {
+        match self.state {
+            HasherState::Sha1(hasher) => format!("{:x}", hasher.finalize()),
+            HasherState::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            HasherState::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            HasherState::Xxh3(hasher) => format!("{:016x}", hasher.digest()),
+        }
+    }
+
+    pub fn hash_bytes(algorithm: HashAlgorithm, bytes: &[u8]) -> String {
+        let mut hasher = Self::new(algorithm);
+        hasher.update(bytes);
+        hasher.finish()
+    }
+
+    /// Hash everything `reader` yields
+    pub fn hash_reader<R: Read>(
+        algorithm: HashAlgorithm,
+        mut reader: R,
+    ) -> std::io::Result<String> {
+        let mut hasher = Self::new(algorithm);
+        let mut buffer = [0u8; 64 * 1024];
+
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+
+        Ok(hasher.finish())
+    }
+}
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        Self::new(HashAlgorithm::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_digests() {
+        let digest = |algorithm| ContentHasher::hash_bytes(algorithm, b"abc");
+
+        assert_eq!(
+            digest(HashAlgorithm::Sha1),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            digest(HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            digest(HashAlgorithm::Blake3),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(digest(HashAlgorithm::Xxh3).len(), 16);
+    }
+
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let contents: Vec<u8> = (0..300_000u32).map(|i| (i % 253) as u8).collect();
+
+        for algorithm in HashAlgorithm::ALL {
+            let streamed = ContentHasher::hash_reader(algorithm, contents.as_slice()).unwrap();
+            assert_eq!(streamed, ContentHasher::hash_bytes(algorithm, &contents));
+        }
+    }
+
+    #[test]
+    fn test_names_and_tags() {
+        assert_eq!(
+            "BLAKE3".parse::<HashAlgorithm>().unwrap(),
+            HashAlgorithm::Blake3
+        );
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+        assert_eq!(HashAlgorithm::default(), HashAlgorithm::Sha1);
+
+        let tagged = HashAlgorithm::Xxh3.tag("00ff");
+        assert_eq!(tagged, "xxh3:00ff");
+        assert_eq!(
+            HashAlgorithm::parse_tagged(&tagged).unwrap(),
+            (HashAlgorithm::Xxh3, "00ff")
+        );
+        assert!(HashAlgorithm::parse_tagged("00ff").is_err());
+    }
+}
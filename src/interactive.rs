@@ -1,20 +1,52 @@
 use crate::document::{DocumentInput, suggest_document_date, today_date_string};
 use crate::error::{CleanboxError, Result};
 use crate::filesystem::FileManager;
+use crate::logging::{self, ConsoleStream};
 use crate::tags::{TagDictionary, TagResolution, TagResolutionFlow};
 use rustyline::completion::{Completer, Pair};
-use rustyline::config::{CompletionType, Config};
+use rustyline::config::{Behavior, Builder, CompletionType, Config};
 use rustyline::highlight::Highlighter;
 use rustyline::hint::Hinter;
 use rustyline::validate::Validator;
 use rustyline::{Context, DefaultEditor, Editor, Helper};
-use std::io::{self, Write};
+use std::io::Write;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 pub trait UserPrompt {
     fn prompt_string(&self, message: &str, default: Option<&str>) -> Result<String>;
     fn prompt_confirmation(&self, message: &str, default: bool) -> Result<bool>;
     fn prompt_selection(&self, message: &str, options: &[&str]) -> Result<usize>;
+
+    /// Prompt for one line of comma-separated tags, pre-filled with `default` if given;
+    /// an empty line finishes tag entry
+    fn prompt_tag_line(
+        &self,
+        message: &str,
+        default: Option<&str>,
+        _tag_dictionary: &TagDictionary,
+    ) -> Result<String> {
+        self.prompt_string(message, Some(default.unwrap_or_default()))
+    }
+
+    /// Whether `prompt_tag_line` completes tags on TAB
+    fn completes_tags(&self) -> bool {
+        false
+    }
+
+    /// Show informational text between prompts, such as validation hints
+    fn show(&self, message: &str) {
+        logging::console_line(message);
+    }
+}
+
+/// Editor settings that keep prompts off stdout when it is reserved
+fn editor_config() -> Builder {
+    let behavior = match logging::console_stream() {
+        ConsoleStream::Stdout => Behavior::Stdio,
+        ConsoleStream::Stderr => Behavior::PreferTerm,
+    };
+    Config::builder().behavior(behavior)
 }
 
 #[derive(Clone)]
@@ -34,7 +66,7 @@ impl Default for ReadlinePrompt {
 
 impl UserPrompt for ReadlinePrompt {
     fn prompt_string(&self, message: &str, default: Option<&str>) -> Result<String> {
-        let mut rl = DefaultEditor::new().map_err(|e| {
+        let mut rl = DefaultEditor::with_config(editor_config().build()).map_err(|e| {
             CleanboxError::InvalidUserInput(format!("Failed to initialize readline: {e}"))
         })?;
 
@@ -52,16 +84,14 @@ impl UserPrompt for ReadlinePrompt {
                         if let Some(default_val) = default {
                             return Ok(default_val.to_string());
                         } else {
-                            println!("Input cannot be empty. Please try again.");
+                            logging::console_line("Input cannot be empty. Please try again.");
                             continue;
                         }
                     }
                     return Ok(input.to_string());
                 }
                 Err(rustyline::error::ReadlineError::Interrupted) => {
-                    return Err(CleanboxError::InvalidUserInput(
-                        "User interrupted input".to_string(),
-                    ));
+                    return Err(CleanboxError::UserCancelled);
                 }
                 Err(rustyline::error::ReadlineError::Eof) => {
                     return Err(CleanboxError::InvalidUserInput(
@@ -78,7 +108,7 @@ impl UserPrompt for ReadlinePrompt {
     }
 
     fn prompt_confirmation(&self, message: &str, default: bool) -> Result<bool> {
-        let mut rl = DefaultEditor::new().map_err(|e| {
+        let mut rl = DefaultEditor::with_config(editor_config().build()).map_err(|e| {
             CleanboxError::InvalidUserInput(format!("Failed to initialize readline: {e}"))
         })?;
 
@@ -94,15 +124,13 @@ impl UserPrompt for ReadlinePrompt {
                         "y" | "yes" => return Ok(true),
                         "n" | "no" => return Ok(false),
                         _ => {
-                            println!("Please enter 'y' for yes or 'n' for no.");
+                            logging::console_line("Please enter 'y' for yes or 'n' for no.");
                             continue;
                         }
                     }
                 }
                 Err(rustyline::error::ReadlineError::Interrupted) => {
-                    return Err(CleanboxError::InvalidUserInput(
-                        "User interrupted input".to_string(),
-                    ));
+                    return Err(CleanboxError::UserCancelled);
                 }
                 Err(rustyline::error::ReadlineError::Eof) => {
                     return Err(CleanboxError::InvalidUserInput(
@@ -119,14 +147,14 @@ impl UserPrompt for ReadlinePrompt {
     }
 
     fn prompt_selection(&self, message: &str, options: &[&str]) -> Result<usize> {
-        let mut rl = DefaultEditor::new().map_err(|e| {
+        let mut rl = DefaultEditor::with_config(editor_config().build()).map_err(|e| {
             CleanboxError::InvalidUserInput(format!("Failed to initialize readline: {e}"))
         })?;
 
         loop {
-            println!("{message}");
+            logging::console_line(message);
             for (i, option) in options.iter().enumerate() {
-                println!("  {}. {}", i + 1, option);
+                logging::console_line(&format!("  {}. {}", i + 1, option));
             }
             let prompt = format!("Select (1-{}): ", options.len());
 
@@ -137,18 +165,16 @@ impl UserPrompt for ReadlinePrompt {
                             return Ok(choice - 1); // Convert to 0-based index
                         }
                         _ => {
-                            println!(
+                            logging::console_line(&format!(
                                 "Invalid selection. Please enter a number between 1 and {}.",
                                 options.len()
-                            );
+                            ));
                             continue;
                         }
                     }
                 }
                 Err(rustyline::error::ReadlineError::Interrupted) => {
-                    return Err(CleanboxError::InvalidUserInput(
-                        "User interrupted input".to_string(),
-                    ));
+                    return Err(CleanboxError::UserCancelled);
                 }
                 Err(rustyline::error::ReadlineError::Eof) => {
                     return Err(CleanboxError::InvalidUserInput(
@@ -163,6 +189,42 @@ impl UserPrompt for ReadlinePrompt {
             }
         }
     }
+
+    fn completes_tags(&self) -> bool {
+        true
+    }
+
+    fn prompt_tag_line(
+        &self,
+        message: &str,
+        default: Option<&str>,
+        tag_dictionary: &TagDictionary,
+    ) -> Result<String> {
+        let completer = FuzzyTagCompleter::new(tag_dictionary);
+        let config = editor_config()
+            .completion_type(CompletionType::List)
+            .build();
+        let mut editor = Editor::with_config(config).map_err(|e| {
+            CleanboxError::InvalidUserInput(format!("Failed to initialize editor: {e}"))
+        })?;
+        editor.set_helper(Some(completer));
+
+        let prompt = format!("{message}: ");
+        let line = match default {
+            Some(default) => editor.readline_with_initial(&prompt, (default, "")),
+            None => editor.readline(&prompt),
+        };
+        match line {
+            Ok(input) => Ok(input.trim().to_string()),
+            Err(rustyline::error::ReadlineError::Interrupted) => Err(CleanboxError::UserCancelled),
+            Err(rustyline::error::ReadlineError::Eof) => Err(CleanboxError::InvalidUserInput(
+                "End of input reached".to_string(),
+            )),
+            Err(e) => Err(CleanboxError::InvalidUserInput(format!(
+                "Readline error: {e}"
+            ))),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -196,6 +258,20 @@ impl UserPrompt for ConsolePrompt {
     fn prompt_selection(&self, message: &str, options: &[&str]) -> Result<usize> {
         self.readline_prompt.prompt_selection(message, options)
     }
+
+    fn prompt_tag_line(
+        &self,
+        message: &str,
+        default: Option<&str>,
+        tag_dictionary: &TagDictionary,
+    ) -> Result<String> {
+        self.readline_prompt
+            .prompt_tag_line(message, default, tag_dictionary)
+    }
+
+    fn completes_tags(&self) -> bool {
+        self.readline_prompt.completes_tags()
+    }
 }
 
 pub struct DatePrompt<P: UserPrompt, F: FileManager> {
@@ -212,24 +288,7 @@ impl<P: UserPrompt, F: FileManager> DatePrompt<P, F> {
     }
 
     pub fn prompt_date(&self) -> Result<String> {
-        let today = today_date_string();
-
-        loop {
-            let input = self
-                .prompter
-                .prompt_string("Date (YYYY-MM-DD)", Some(&today))?;
-
-            // Validate date format
-            if let Err(e) =
-                DocumentInput::new(input.clone(), "temp".to_string(), vec!["temp".to_string()])
-                    .validate_date()
-            {
-                println!("Invalid date format: {e}");
-                continue;
-            }
-
-            return Ok(input);
-        }
+        self.prompt_date_with_default(&today_date_string())
     }
 
     pub fn prompt_date_with_smart_suggestion<PA: AsRef<Path>>(
@@ -237,18 +296,21 @@ impl<P: UserPrompt, F: FileManager> DatePrompt<P, F> {
         filename: PA,
     ) -> Result<String> {
         let suggested_date = suggest_document_date(&filename, &self.file_manager);
+        self.prompt_date_with_default(&suggested_date)
+    }
 
+    pub fn prompt_date_with_default(&self, default: &str) -> Result<String> {
         loop {
             let input = self
                 .prompter
-                .prompt_string("Date (YYYY-MM-DD)", Some(&suggested_date))?;
+                .prompt_string("Date (YYYY-MM-DD)", Some(default))?;
 
             // Validate date format
             if let Err(e) =
                 DocumentInput::new(input.clone(), "temp".to_string(), vec!["temp".to_string()])
                     .validate_date()
             {
-                println!("Invalid date format: {e}");
+                self.prompter.show(&format!("Invalid date format: {e}"));
                 continue;
             }
 
@@ -267,10 +329,14 @@ impl<P: UserPrompt> DescriptionPrompt<P> {
     }
 
     pub fn prompt_description(&self) -> Result<String> {
+        self.prompt_description_with_default(None)
+    }
+
+    pub fn prompt_description_with_default(&self, default: Option<&str>) -> Result<String> {
         loop {
             let input = self
                 .prompter
-                .prompt_string("Description (kebab-case)", None)?;
+                .prompt_string("Description (kebab-case)", default)?;
 
             // Validate description format
             if let Err(e) = DocumentInput::new(
@@ -280,7 +346,8 @@ impl<P: UserPrompt> DescriptionPrompt<P> {
             )
             .validate_description()
             {
-                println!("Invalid description format: {e}");
+                self.prompter
+                    .show(&format!("Invalid description format: {e}"));
                 continue;
             }
 
@@ -289,6 +356,113 @@ impl<P: UserPrompt> DescriptionPrompt<P> {
     }
 }
 
+/// Action chosen for a document before its input is collected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocumentAction {
+    /// Collect date, description and tags, then file the document
+    Process,
+    /// Leave the document in the inbox
+    Skip,
+    /// Move the document to the end of the queue
+    Defer,
+    /// Revise the previous document before it is committed
+    Back,
+    /// Open the document in a viewer, then ask again
+    Open,
+}
+
+pub struct DocumentActionPrompt<P: UserPrompt> {
+    prompter: P,
+}
+
+impl<P: UserPrompt> DocumentActionPrompt<P> {
+    pub fn new(prompter: P) -> Self {
+        Self { prompter }
+    }
+
+    pub fn prompt_action(&self, can_go_back: bool) -> Result<DocumentAction> {
+        let message = if can_go_back {
+            "Action: [p]rocess, [s]kip, [d]efer, [b]ack, [o]pen"
+        } else {
+            "Action: [p]rocess, [s]kip, [d]efer, [o]pen"
+        };
+
+        loop {
+            let input = self.prompter.prompt_string(message, Some("p"))?;
+
+            match Self::parse_action(&input) {
+                Some(DocumentAction::Back) if !can_go_back => {
+                    self.prompter.show("No previous document to go back to.");
+                }
+                Some(action) => return Ok(action),
+                None => self.prompter.show(&format!("Unknown action: {input}")),
+            }
+        }
+    }
+
+    fn parse_action(input: &str) -> Option<DocumentAction> {
+        match input.trim().to_lowercase().as_str() {
+            "p" | "process" => Some(DocumentAction::Process),
+            "s" | "skip" => Some(DocumentAction::Skip),
+            "d" | "defer" => Some(DocumentAction::Defer),
+            "b" | "back" => Some(DocumentAction::Back),
+            "o" | "open" => Some(DocumentAction::Open),
+            _ => None,
+        }
+    }
+}
+
+/// Opens a file in the configured viewer command, or the system default viewer.
+///
+/// The configured command is split on whitespace and the file path is appended as
+/// the last argument, so both `zathura` and `code --wait` work.
+///
+/// # Arguments
+/// * `file_path` - File to open
+/// * `viewer_command` - Optional command overriding the system viewer
+pub fn open_in_viewer(file_path: &Path, viewer_command: Option<&str>) -> Result<()> {
+    let mut parts: Vec<String> = match viewer_command {
+        Some(command) => command.split_whitespace().map(str::to_string).collect(),
+        None => default_viewer_command(),
+    };
+
+    if parts.is_empty() {
+        return Err(CleanboxError::ViewerFailed(
+            "Viewer command is empty".to_string(),
+        ));
+    }
+
+    let program = parts.remove(0);
+    let status = std::process::Command::new(&program)
+        .args(&parts)
+        .arg(file_path)
+        .status()
+        .map_err(|e| CleanboxError::ViewerFailed(format!("Cannot run '{program}': {e}")))?;
+
+    if !status.success() {
+        return Err(CleanboxError::ViewerFailed(format!(
+            "'{program}' exited with {status}"
+        )));
+    }
+
+    Ok(())
+}
+
+fn default_viewer_command() -> Vec<String> {
+    if cfg!(target_os = "macos") {
+        vec!["open".to_string()]
+    } else if cfg!(target_os = "windows") {
+        vec![
+            "cmd".to_string(),
+            "/C".to_string(),
+            "start".to_string(),
+            String::new(),
+        ]
+    } else {
+        vec!["xdg-open".to_string()]
+    }
+}
+
 pub struct FuzzyTagCompleter<'a> {
     tag_dictionary: &'a TagDictionary,
 }
@@ -370,65 +544,57 @@ impl<'a> Validator for FuzzyTagCompleter<'a> {}
 
 impl<'a> Helper for FuzzyTagCompleter<'a> {}
 
-pub struct SmartTagSelector<P: UserPrompt> {
+pub struct SmartTagSelector<P: UserPrompt, F: FileManager> {
     prompter: P,
     flow: TagResolutionFlow,
+    file_manager: F,
     tags_file_path: std::path::PathBuf,
 }
 
-impl<P: UserPrompt> SmartTagSelector<P> {
+impl<P: UserPrompt, F: FileManager> SmartTagSelector<P, F> {
     pub fn new(
         prompter: P,
         tag_dictionary: TagDictionary,
+        file_manager: F,
         tags_file_path: std::path::PathBuf,
     ) -> Self {
         Self {
             prompter,
             flow: TagResolutionFlow::new(tag_dictionary),
+            file_manager,
             tags_file_path,
         }
     }
 
     pub fn prompt_tags(&mut self) -> Result<Vec<String>> {
+        self.prompt_tags_with_defaults(&[])
+    }
+
+    /// Prompt for tags, offering `defaults` as the answer to the first line
+    pub fn prompt_tags_with_defaults(&mut self, defaults: &[String]) -> Result<Vec<String>> {
         let mut selected_tags = Vec::new();
+        let mut default_line = (!defaults.is_empty()).then(|| defaults.join(", "));
 
-        println!(
+        self.prompter.show(if self.prompter.completes_tags() {
             "Enter tags (comma-separated). Use TAB for fuzzy completion. Press Enter when done:"
-        );
+        } else {
+            "Enter tags (comma-separated). Press Enter when done:"
+        });
 
         loop {
-            // Create editor with fuzzy completer fresh each time to avoid borrowing issues
-            let completer = FuzzyTagCompleter::new(self.flow.dictionary());
-            let config = Config::builder()
-                .completion_type(CompletionType::List)
-                .build();
-            let mut editor = Editor::with_config(config).map_err(|e| {
-                CleanboxError::InvalidUserInput(format!("Failed to initialize editor: {e}"))
-            })?;
-            editor.set_helper(Some(completer));
-
-            let input = match editor.readline("Tags: ") {
-                Ok(input) => input.trim().to_string(),
-                Err(rustyline::error::ReadlineError::Interrupted) => {
-                    return Err(CleanboxError::InvalidUserInput(
-                        "User interrupted input".to_string(),
-                    ));
-                }
-                Err(rustyline::error::ReadlineError::Eof) => {
-                    return Err(CleanboxError::InvalidUserInput(
-                        "End of input reached".to_string(),
-                    ));
-                }
-                Err(e) => {
-                    return Err(CleanboxError::InvalidUserInput(format!(
-                        "Readline error: {e}"
-                    )));
-                }
-            };
+            let input = self
+                .prompter
+                .prompt_tag_line(
+                    "Tags",
+                    default_line.take().as_deref(),
+                    self.flow.dictionary(),
+                )?
+                .trim()
+                .to_string();
 
             if input.is_empty() {
                 if selected_tags.is_empty() {
-                    println!("At least one tag is required.");
+                    self.prompter.show("At least one tag is required.");
                     continue;
                 } else {
                     break;
@@ -446,15 +612,18 @@ impl<P: UserPrompt> SmartTagSelector<P> {
                 if let Some(resolved_tag) = self.resolve_single_tag(tag)? {
                     if !selected_tags.contains(&resolved_tag) {
                         selected_tags.push(resolved_tag);
-                        println!("Added tag: {}", selected_tags.last().unwrap());
+                        self.prompter
+                            .show(&format!("Added tag: {}", selected_tags.last().unwrap()));
                     } else {
-                        println!("Tag '{resolved_tag}' already added.");
+                        self.prompter
+                            .show(&format!("Tag '{resolved_tag}' already added."));
                     }
                 }
             }
 
             if !selected_tags.is_empty() {
-                println!("Current tags: {}", selected_tags.join(", "));
+                self.prompter
+                    .show(&format!("Current tags: {}", selected_tags.join(", ")));
                 if self.prompter.prompt_confirmation("Add more tags?", false)? {
                     continue;
                 } else {
@@ -474,7 +643,8 @@ impl<P: UserPrompt> SmartTagSelector<P> {
                 similar,
                 can_create,
             } => {
-                println!("Tag '{input}' not found. Similar tags:");
+                self.prompter
+                    .show(&format!("Tag '{input}' not found. Similar tags:"));
 
                 let mut options: Vec<String> = similar.iter().map(|s| s.tag.clone()).collect();
                 if can_create {
@@ -490,7 +660,7 @@ impl<P: UserPrompt> SmartTagSelector<P> {
                     // User chose to create new tag
                     self.flow.dictionary_mut().add_tag(input.to_string())?;
                     self.save_dictionary_immediately();
-                    println!("Created new tag: {input}");
+                    self.prompter.show(&format!("Created new tag: {input}"));
                     Ok(Some(input.to_string()))
                 } else {
                     // User chose an existing similar tag
@@ -505,15 +675,15 @@ impl<P: UserPrompt> SmartTagSelector<P> {
                     {
                         self.flow.dictionary_mut().add_tag(input.to_string())?;
                         self.save_dictionary_immediately();
-                        println!("Created new tag: {input}");
+                        self.prompter.show(&format!("Created new tag: {input}"));
                         Ok(Some(input.to_string()))
                     } else {
                         Ok(None)
                     }
                 } else {
-                    println!("Invalid tag format: {input}");
-                    println!(
-                        "Tags must be lowercase, kebab-case, and contain only ASCII characters."
+                    self.prompter.show(&format!("Invalid tag format: {input}"));
+                    self.prompter.show(
+                        "Tags must be lowercase, kebab-case, and contain only ASCII characters.",
                     );
                     Ok(None)
                 }
@@ -521,14 +691,32 @@ impl<P: UserPrompt> SmartTagSelector<P> {
         }
     }
 
+    pub fn dictionary(&self) -> &TagDictionary {
+        self.flow.dictionary()
+    }
+
     pub fn save_dictionary(&self, file_path: &std::path::Path) -> Result<()> {
-        self.flow.dictionary().save_to_file(file_path)
+        self.flow
+            .dictionary()
+            .save_to_file(file_path, &self.file_manager)
+    }
+
+    /// Add tags accepted outside the prompt flow (e.g. from a rule) to the dictionary
+    pub fn record_tags(&mut self, tags: &[String]) -> Result<()> {
+        for tag in tags {
+            if !self.flow.dictionary().contains(tag) {
+                self.flow.dictionary_mut().add_tag(tag.clone())?;
+            }
+        }
+        Ok(())
     }
 
     fn save_dictionary_immediately(&self) {
-        if let Err(e) = self.flow.dictionary().save_to_file(&self.tags_file_path) {
-            eprintln!("Warning: Failed to save tag dictionary immediately: {e}");
-            eprintln!("Tags will still be saved at the end of processing.");
+        if let Err(e) = self.save_dictionary(&self.tags_file_path) {
+            log::warn!(
+                "Failed to save tag dictionary immediately: {e}. \
+                 Tags will still be saved at the end of processing."
+            );
         }
     }
 }
@@ -536,7 +724,7 @@ impl<P: UserPrompt> SmartTagSelector<P> {
 pub struct DocumentInputCollector<P: UserPrompt, F: FileManager> {
     date_prompt: DatePrompt<P, F>,
     description_prompt: DescriptionPrompt<P>,
-    tag_selector: SmartTagSelector<P>,
+    tag_selector: SmartTagSelector<P, F>,
 }
 
 impl<F: FileManager + Clone> DocumentInputCollector<ConsolePrompt, F> {
@@ -549,7 +737,12 @@ impl<F: FileManager + Clone> DocumentInputCollector<ConsolePrompt, F> {
         Self {
             date_prompt: DatePrompt::new(ConsolePrompt::new(), file_manager.clone()),
             description_prompt: DescriptionPrompt::new(ConsolePrompt::new()),
-            tag_selector: SmartTagSelector::new(prompter, tag_dictionary, tags_file_path),
+            tag_selector: SmartTagSelector::new(
+                prompter,
+                tag_dictionary,
+                file_manager,
+                tags_file_path,
+            ),
         }
     }
 }
@@ -562,9 +755,14 @@ impl<P: UserPrompt + Clone, F: FileManager + Clone> DocumentInputCollector<P, F>
         tags_file_path: std::path::PathBuf,
     ) -> Self {
         Self {
-            date_prompt: DatePrompt::new(prompter.clone(), file_manager),
+            date_prompt: DatePrompt::new(prompter.clone(), file_manager.clone()),
             description_prompt: DescriptionPrompt::new(prompter.clone()),
-            tag_selector: SmartTagSelector::new(prompter, tag_dictionary, tags_file_path),
+            tag_selector: SmartTagSelector::new(
+                prompter,
+                tag_dictionary,
+                file_manager,
+                tags_file_path,
+            ),
         }
     }
 
@@ -577,14 +775,21 @@ impl<P: UserPrompt + Clone, F: FileManager + Clone> DocumentInputCollector<P, F>
         tags_file_path: std::path::PathBuf,
     ) -> Self {
         Self {
-            date_prompt: DatePrompt::new(date_prompter, file_manager),
+            date_prompt: DatePrompt::new(date_prompter, file_manager.clone()),
             description_prompt: DescriptionPrompt::new(desc_prompter),
-            tag_selector: SmartTagSelector::new(tag_prompter, tag_dictionary, tags_file_path),
+            tag_selector: SmartTagSelector::new(
+                tag_prompter,
+                tag_dictionary,
+                file_manager,
+                tags_file_path,
+            ),
         }
     }
 
     pub fn collect_input(&mut self, filename: &str) -> Result<DocumentInput> {
-        println!("\nProcessing document: {filename}");
+        self.date_prompt
+            .prompter
+            .show(&format!("\nProcessing document: {filename}"));
 
         let date = self
             .date_prompt
@@ -598,60 +803,272 @@ impl<P: UserPrompt + Clone, F: FileManager + Clone> DocumentInputCollector<P, F>
         Ok(input)
     }
 
+    /// Collect input again for a document, offering the previous answers as defaults
+    pub fn revise_input(
+        &mut self,
+        filename: &str,
+        previous: &DocumentInput,
+    ) -> Result<DocumentInput> {
+        self.date_prompt
+            .prompter
+            .show(&format!("\nRevising document: {filename}"));
+
+        let date = self.date_prompt.prompt_date_with_default(&previous.date)?;
+        let description = self
+            .description_prompt
+            .prompt_description_with_default(Some(&previous.description))?;
+        let tags = self
+            .tag_selector
+            .prompt_tags_with_defaults(&previous.tags)?;
+
+        let input = DocumentInput::new(date, description, tags);
+        input.validate()?; // Final validation
+
+        Ok(input)
+    }
+
+    pub fn tag_dictionary(&self) -> &TagDictionary {
+        self.tag_selector.dictionary()
+    }
+
     pub fn save_tag_dictionary(&self, file_path: &std::path::Path) -> Result<()> {
         self.tag_selector.save_dictionary(file_path)
     }
+
+    pub fn record_tags(&mut self, tags: &[String]) -> Result<()> {
+        self.tag_selector.record_tags(tags)
+    }
+}
+
+/// How a `ProgressIndicator` shows progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// A bar redrawn in place, for terminals
+    Bar,
+    /// A status line every few seconds, for logs and pipes
+    Lines,
+    Hidden,
 }
 
 pub struct ProgressIndicator {
     current: usize,
     total: usize,
     task_name: String,
+    bytes_done: u64,
+    total_bytes: Option<u64>,
+    started: Instant,
+    mode: ProgressMode,
+    report_interval: Duration,
+    last_report: Option<Instant>,
+    drawn: bool,
+    stream: ConsoleStream,
 }
 
 impl ProgressIndicator {
+    /// Progress over `total` files on the console stream, drawn as a bar when
+    /// that stream is a terminal
     pub fn new(total: usize, task_name: String) -> Self {
+        let stream = logging::console_stream();
+        let mode = if stream.is_terminal() {
+            ProgressMode::Bar
+        } else {
+            ProgressMode::Lines
+        };
+
         Self {
             current: 0,
             total,
             task_name,
+            bytes_done: 0,
+            total_bytes: None,
+            started: Instant::now(),
+            mode,
+            report_interval: Duration::from_secs(5),
+            last_report: None,
+            drawn: false,
+            stream,
+        }
+    }
+
+    /// Size of all files together, which enables throughput and a byte-based ETA
+    pub fn with_total_bytes(mut self, total_bytes: u64) -> Self {
+        self.total_bytes = Some(total_bytes);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: ProgressMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Match the console verbosity: hidden when quiet, plain lines when per-file
+    /// detail would interleave with a redrawn bar
+    pub fn follow_console(self) -> Self {
+        if !logging::console_enabled(log::Level::Info) {
+            self.with_mode(ProgressMode::Hidden)
+        } else if logging::console_enabled(log::Level::Debug) {
+            self.with_mode(ProgressMode::Lines)
+        } else {
+            self
         }
     }
 
+    /// Minimum time between status lines in `ProgressMode::Lines`
+    pub fn with_report_interval(mut self, interval: Duration) -> Self {
+        self.report_interval = interval;
+        self
+    }
+
     pub fn start(&self) {
-        println!("Starting {}: 0/{} files", self.task_name, self.total);
+        if self.mode != ProgressMode::Hidden {
+            self.write(&format!(
+                "Starting {}: 0/{} files\n",
+                self.task_name, self.total
+            ));
+        }
     }
 
     pub fn update(&mut self, current: usize) {
         self.current = current;
-        let percentage = if self.total > 0 {
-            (current * 100) / self.total
-        } else {
-            100
-        };
+        self.render();
+    }
 
-        let bar_length = 40;
-        let filled = (current * bar_length) / self.total.max(1);
-        let bar = "█".repeat(filled) + &"░".repeat(bar_length - filled);
+    pub fn increment(&mut self) {
+        self.update(self.current + 1);
+    }
 
-        print!(
-            "\r{}: {} {}/{} ({}%)",
-            self.task_name, bar, current, self.total, percentage
-        );
-        io::stdout().flush().unwrap_or(());
+    /// Count one more file of `bytes` bytes as done
+    pub fn advance(&mut self, bytes: u64) {
+        self.bytes_done += bytes;
+        self.increment();
+    }
+
+    /// Remove the bar so other output starts on a clean line; the next update redraws it
+    pub fn clear(&mut self) {
+        if self.drawn {
+            self.write("\r\x1b[K");
+            self.drawn = false;
+        }
     }
 
     pub fn finish(&mut self) {
-        self.update(self.total);
-        println!(); // New line after progress bar
-        println!(
-            "Completed {}: {}/{} files",
-            self.task_name, self.total, self.total
-        );
+        match self.mode {
+            ProgressMode::Bar => {
+                self.draw_bar();
+                self.write("\n"); // New line after progress bar
+                self.drawn = false;
+            }
+            ProgressMode::Lines => {
+                if self.current < self.total || self.last_report.is_none() {
+                    self.write_status_line();
+                }
+            }
+            ProgressMode::Hidden => {}
+        }
     }
 
-    pub fn increment(&mut self) {
-        self.update(self.current + 1);
+    /// Files, percentage, throughput and remaining time after `elapsed`
+    pub fn status(&self, elapsed: Duration) -> String {
+        let percentage = (self.current * 100).checked_div(self.total).unwrap_or(100);
+        let mut status = format!("{}/{} files ({}%)", self.current, self.total, percentage);
+
+        let seconds = elapsed.as_secs_f64();
+        if self.total_bytes.is_some() && seconds > 0.0 {
+            let rate = (self.bytes_done as f64 / seconds) as u64;
+            status.push_str(&format!(", {}/s", format_bytes(rate)));
+        }
+        if let Some(eta) = self.eta(elapsed) {
+            status.push_str(&format!(", ETA {}", format_duration(eta)));
+        }
+        status
+    }
+
+    /// Remaining time extrapolated from bytes done, or from files when sizes are unknown
+    fn eta(&self, elapsed: Duration) -> Option<Duration> {
+        if self.current >= self.total {
+            return None;
+        }
+
+        let (done, total) = match self.total_bytes {
+            Some(total_bytes) if self.bytes_done > 0 => {
+                (self.bytes_done as f64, total_bytes as f64)
+            }
+            _ if self.current > 0 => (self.current as f64, self.total as f64),
+            _ => return None,
+        };
+        let remaining = (total - done).max(0.0);
+        Some(elapsed.mul_f64(remaining / done))
+    }
+
+    fn render(&mut self) {
+        match self.mode {
+            ProgressMode::Bar => self.draw_bar(),
+            ProgressMode::Lines => {
+                let now = Instant::now();
+                let due = self
+                    .last_report
+                    .is_none_or(|last| now.duration_since(last) >= self.report_interval);
+                if due || self.current >= self.total {
+                    self.write_status_line();
+                    self.last_report = Some(now);
+                }
+            }
+            ProgressMode::Hidden => {}
+        }
+    }
+
+    fn draw_bar(&mut self) {
+        let bar_length = 30;
+        let filled = (self.current * bar_length) / self.total.max(1);
+        let bar =
+            "█".repeat(filled.min(bar_length)) + &"░".repeat(bar_length - filled.min(bar_length));
+
+        self.write(&format!(
+            "\r\x1b[K{}: {} {}",
+            self.task_name,
+            bar,
+            self.status(self.started.elapsed())
+        ));
+        self.drawn = true;
+    }
+
+    fn write_status_line(&self) {
+        let status = self.status(self.started.elapsed());
+        self.write(&format!("{}: {}\n", self.task_name, status));
+    }
+
+    /// Progress must never interrupt processing, so write errors are ignored
+    fn write(&self, text: &str) {
+        let mut writer = self.stream.writer();
+        let _ = writer.write_all(text.as_bytes());
+        let _ = writer.flush();
+    }
+}
+
+/// Human-readable size, such as `3.2 MB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// Duration as `m:ss`, or `h:mm:ss` from an hour on
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
     }
 }
 
@@ -687,11 +1104,13 @@ mod tests {
             self
         }
 
+        #[allow(dead_code)]
         pub fn with_confirmations(mut self, responses: Vec<bool>) -> Self {
             self.confirmation_responses = responses;
             self
         }
 
+        #[allow(dead_code)]
         pub fn with_selections(mut self, responses: Vec<usize>) -> Self {
             self.selection_responses = responses;
             self
@@ -712,6 +1131,7 @@ mod tests {
     }
 
     impl UserPrompt for MockPrompt {
+        #[allow(clippy::unnecessary_unwrap)]
         fn prompt_string(&self, _message: &str, default: Option<&str>) -> Result<String> {
             let mut index = self.string_index.borrow_mut();
             if *index < self.string_responses.len() {
@@ -794,9 +1214,103 @@ mod tests {
         assert_eq!(result, "quarterly-report");
     }
 
+    #[test]
+    fn test_document_action_prompt() {
+        let mock = MockPrompt::new().with_strings(vec![
+            "".to_string(),
+            "skip".to_string(),
+            "D".to_string(),
+            "o".to_string(),
+            "b".to_string(),
+        ]);
+        let action_prompt = DocumentActionPrompt::new(mock);
+
+        assert_eq!(
+            action_prompt.prompt_action(false).unwrap(),
+            DocumentAction::Process
+        );
+        assert_eq!(
+            action_prompt.prompt_action(false).unwrap(),
+            DocumentAction::Skip
+        );
+        assert_eq!(
+            action_prompt.prompt_action(false).unwrap(),
+            DocumentAction::Defer
+        );
+        assert_eq!(
+            action_prompt.prompt_action(false).unwrap(),
+            DocumentAction::Open
+        );
+        assert_eq!(
+            action_prompt.prompt_action(true).unwrap(),
+            DocumentAction::Back
+        );
+    }
+
+    #[test]
+    fn test_document_action_prompt_rejects_back_without_previous() {
+        let mock = MockPrompt::new().with_strings(vec![
+            "b".to_string(),
+            "unknown".to_string(),
+            "s".to_string(),
+        ]);
+        let action_prompt = DocumentActionPrompt::new(mock);
+
+        // "b" and "unknown" are rejected, so the prompt asks again until "s"
+        assert_eq!(
+            action_prompt.prompt_action(false).unwrap(),
+            DocumentAction::Skip
+        );
+    }
+
+    #[test]
+    fn test_new_tags_are_saved_through_the_file_manager() {
+        use crate::testing::{InMemoryFileManager, ScriptedPrompt};
+
+        let file_manager = InMemoryFileManager::new();
+        let tags_file = std::path::PathBuf::from("/life/documents/tags.txt");
+        file_manager.add_file(&tags_file, b"finance\n");
+        let mut dictionary = TagDictionary::new();
+        dictionary.add_tag("finance".to_string()).unwrap();
+        let prompter = ScriptedPrompt::new()
+            .with_text("zoo")
+            .with_confirmation(true)
+            .with_confirmation(false);
+        let mut selector = SmartTagSelector::new(
+            prompter.clone(),
+            dictionary,
+            file_manager.clone(),
+            tags_file.clone(),
+        );
+
+        assert_eq!(selector.prompt_tags().unwrap(), vec!["zoo"]);
+        prompter.assert_finished();
+        assert_eq!(
+            file_manager.read_file(&tags_file).unwrap(),
+            b"finance\nzoo\n"
+        );
+        assert!(!tags_file.exists());
+    }
+
+    #[test]
+    fn test_open_in_viewer_configured_command() {
+        let path = Path::new("/tmp/document.pdf");
+
+        assert!(open_in_viewer(path, Some("true")).is_ok());
+        assert!(matches!(
+            open_in_viewer(path, Some("false")).unwrap_err(),
+            CleanboxError::ViewerFailed(_)
+        ));
+        assert!(matches!(
+            open_in_viewer(path, Some("   ")).unwrap_err(),
+            CleanboxError::ViewerFailed(_)
+        ));
+    }
+
     #[test]
     fn test_progress_indicator() {
-        let mut progress = ProgressIndicator::new(10, "Test Task".to_string());
+        let mut progress =
+            ProgressIndicator::new(10, "Test Task".to_string()).with_mode(ProgressMode::Hidden);
 
         assert_eq!(progress.current, 0);
         assert_eq!(progress.total, 10);
@@ -808,6 +1322,51 @@ mod tests {
         assert_eq!(progress.current, 6);
     }
 
+    #[test]
+    fn test_progress_status_with_throughput_and_eta() {
+        let mut progress = ProgressIndicator::new(4, "Media".to_string())
+            .with_total_bytes(4 * 1024 * 1024)
+            .with_mode(ProgressMode::Hidden);
+
+        assert_eq!(progress.status(Duration::ZERO), "0/4 files (0%)");
+
+        progress.advance(1024 * 1024);
+        assert_eq!(
+            progress.status(Duration::from_secs(2)),
+            "1/4 files (25%), 512.0 KB/s, ETA 0:06"
+        );
+
+        progress.advance(3 * 1024 * 1024);
+        progress.advance(0);
+        progress.advance(0);
+        // Done files have no ETA
+        assert_eq!(
+            progress.status(Duration::from_secs(4)),
+            "4/4 files (100%), 1.0 MB/s"
+        );
+    }
+
+    #[test]
+    fn test_progress_eta_without_sizes() {
+        let mut progress =
+            ProgressIndicator::new(10, "Media".to_string()).with_mode(ProgressMode::Hidden);
+        progress.update(5);
+
+        assert_eq!(
+            progress.status(Duration::from_secs(75)),
+            "5/10 files (50%), ETA 1:15"
+        );
+    }
+
+    #[test]
+    fn test_format_bytes_and_duration() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GB");
+        assert_eq!(format_duration(Duration::from_secs(59)), "0:59");
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1:02:05");
+    }
+
     // Note: test_smart_tag_selector_exact_match is skipped because the new implementation
     // uses rustyline directly and cannot be easily mocked. The core completion logic
     // is tested in test_fuzzy_tag_completer_complete instead.
@@ -0,0 +1,390 @@
+use crate::error::{CleanboxError, Result};
+use crate::interactive::UserPrompt;
+use crate::tags::TagDictionary;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+/// Informational text written to the output stream between questions
+#[derive(Debug, Serialize)]
+struct InfoEvent<'a> {
+    event: &'static str,
+    message: &'a str,
+}
+
+/// A question written to the output stream, one JSON object per line
+#[derive(Debug, Serialize)]
+struct PromptEvent<'a> {
+    event: &'static str,
+    id: u64,
+    #[serde(flatten)]
+    question: Question<'a>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Question<'a> {
+    String {
+        message: &'a str,
+        default: Option<&'a str>,
+    },
+    Confirmation {
+        message: &'a str,
+        default: bool,
+    },
+    Selection {
+        message: &'a str,
+        options: &'a [&'a str],
+    },
+    Tags {
+        message: &'a str,
+        default: Option<&'a str>,
+        known_tags: Vec<&'a str>,
+    },
+}
+
+/// An answer read from the input stream, one JSON object per line
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Answer {
+    id: Option<u64>,
+    answer: Option<Value>,
+    #[serde(default)]
+    cancel: bool,
+}
+
+/// UserPrompt driven by JSON lines, for GUIs, scripts and test harnesses.
+///
+/// Every question is written as a single line such as
+/// `{"event":"prompt","id":1,"type":"string","message":"Description (kebab-case)","default":null}`
+/// and answered by one input line:
+///
+/// - `{"answer": "bank-statement"}` answers with a value. Strings answer string and
+///   tag questions, booleans answer confirmations, and selections take either the
+///   0-based option index or the option text.
+/// - `{}` or `{"answer": null}` accepts the default.
+/// - `{"cancel": true}` cancels processing, as Ctrl-C does in the terminal.
+///
+/// An optional `id` must match the question being answered. The end of the input
+/// cancels processing, so the remaining files stay in the inbox. Text shown between
+/// questions is written as `{"event":"info","message":"Added tag: finance"}`, so
+/// every output line is JSON; logging and progress go to stderr.
+#[derive(Clone)]
+pub struct JsonLinesPrompt {
+    reader: Rc<RefCell<Box<dyn BufRead>>>,
+    writer: Rc<RefCell<Box<dyn Write>>>,
+    next_id: Rc<Cell<u64>>,
+}
+
+impl JsonLinesPrompt {
+    pub fn new(reader: impl BufRead + 'static, writer: impl Write + 'static) -> Self {
+        Self {
+            reader: Rc::new(RefCell::new(Box::new(reader))),
+            writer: Rc::new(RefCell::new(Box::new(writer))),
+            next_id: Rc::new(Cell::new(1)),
+        }
+    }
+
+    /// Read answers from stdin and write questions to stdout
+    pub fn from_stdio() -> Self {
+        Self::new(BufReader::new(io::stdin()), io::stdout())
+    }
+
+    /// Read answers from a file and write questions to stdout
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path.as_ref()).map_err(|e| {
+            CleanboxError::InvalidUserInput(format!(
+                "Cannot open answers file {}: {}",
+                path.as_ref().display(),
+                e
+            ))
+        })?;
+        Ok(Self::new(BufReader::new(file), io::stdout()))
+    }
+
+    /// Emit a question and return the answer value, `None` meaning the default
+    fn ask(&self, question: Question) -> Result<Option<Value>> {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+
+        self.emit(&PromptEvent {
+            event: "prompt",
+            id,
+            question,
+        })?;
+
+        let answer = self.read_answer()?;
+        if answer.cancel {
+            return Err(CleanboxError::UserCancelled);
+        }
+        if let Some(answer_id) = answer.id
+            && answer_id != id
+        {
+            return Err(CleanboxError::InvalidUserInput(format!(
+                "Answer for prompt {answer_id} received while prompt {id} was asked"
+            )));
+        }
+
+        Ok(answer.answer.filter(|value| !value.is_null()))
+    }
+
+    /// Write one event as a JSON line
+    fn emit(&self, event: &impl Serialize) -> Result<()> {
+        let line = serde_json::to_string(event)
+            .map_err(|e| CleanboxError::Serialization(format!("Cannot encode event: {e}")))?;
+        let mut writer = self.writer.borrow_mut();
+        writeln!(writer, "{line}")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn read_answer(&self) -> Result<Answer> {
+        let mut reader = self.reader.borrow_mut();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(CleanboxError::UserCancelled);
+            }
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            return serde_json::from_str(line)
+                .map_err(|e| CleanboxError::InvalidUserInput(format!("Invalid answer: {e}")));
+        }
+    }
+
+    fn unexpected(expected: &str, value: &Value) -> CleanboxError {
+        CleanboxError::InvalidUserInput(format!("Expected {expected}, got {value}"))
+    }
+}
+
+impl UserPrompt for JsonLinesPrompt {
+    fn prompt_string(&self, message: &str, default: Option<&str>) -> Result<String> {
+        match self.ask(Question::String { message, default })? {
+            Some(Value::String(answer)) => Ok(answer.trim().to_string()),
+            Some(value) => Err(Self::unexpected("a string", &value)),
+            None => default.map(str::to_string).ok_or_else(|| {
+                CleanboxError::InvalidUserInput(format!("No default for '{message}'"))
+            }),
+        }
+    }
+
+    fn prompt_confirmation(&self, message: &str, default: bool) -> Result<bool> {
+        match self.ask(Question::Confirmation { message, default })? {
+            Some(Value::Bool(answer)) => Ok(answer),
+            Some(value) => Err(Self::unexpected("a boolean", &value)),
+            None => Ok(default),
+        }
+    }
+
+    fn prompt_selection(&self, message: &str, options: &[&str]) -> Result<usize> {
+        let value = self
+            .ask(Question::Selection { message, options })?
+            .ok_or_else(|| {
+                CleanboxError::InvalidUserInput(format!("No default for '{message}'"))
+            })?;
+
+        let index = match &value {
+            Value::Number(number) => number
+                .as_u64()
+                .and_then(|index| usize::try_from(index).ok()),
+            Value::String(text) => options.iter().position(|option| option == text),
+            _ => None,
+        };
+
+        index
+            .filter(|index| *index < options.len())
+            .ok_or_else(|| Self::unexpected("an option index or option text", &value))
+    }
+
+    fn prompt_tag_line(
+        &self,
+        message: &str,
+        default: Option<&str>,
+        tag_dictionary: &TagDictionary,
+    ) -> Result<String> {
+        let known_tags = tag_dictionary.all_tags();
+        match self.ask(Question::Tags {
+            message,
+            default,
+            known_tags,
+        })? {
+            Some(Value::String(answer)) => Ok(answer.trim().to_string()),
+            Some(value) => Err(Self::unexpected("a string", &value)),
+            None => Ok(default.unwrap_or_default().to_string()),
+        }
+    }
+
+    fn show(&self, message: &str) {
+        let event = InfoEvent {
+            event: "info",
+            message: message.trim(),
+        };
+        // Like console output, informational text must not interrupt processing
+        let _ = self.emit(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Writer whose output stays inspectable after the prompt takes ownership
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedOutput {
+        fn events(&self) -> Vec<Value> {
+            String::from_utf8(self.0.borrow().clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    fn prompt_with_answers(answers: &str) -> (JsonLinesPrompt, SharedOutput) {
+        let output = SharedOutput::default();
+        let prompt = JsonLinesPrompt::new(Cursor::new(answers.to_string()), output.clone());
+        (prompt, output)
+    }
+
+    #[test]
+    fn test_string_prompt_and_default() {
+        let (prompt, output) = prompt_with_answers("{\"answer\": \"invoice\"}\n\n{}\n");
+
+        assert_eq!(
+            prompt.prompt_string("Description", None).unwrap(),
+            "invoice"
+        );
+        assert_eq!(
+            prompt.prompt_string("Date", Some("2024-05-01")).unwrap(),
+            "2024-05-01"
+        );
+
+        let events = output.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "prompt");
+        assert_eq!(events[0]["id"], 1);
+        assert_eq!(events[0]["type"], "string");
+        assert_eq!(events[0]["message"], "Description");
+        assert_eq!(events[1]["id"], 2);
+        assert_eq!(events[1]["default"], "2024-05-01");
+    }
+
+    #[test]
+    fn test_confirmation_and_selection() {
+        let (prompt, output) = prompt_with_answers(
+            "{\"answer\": false}\n{\"answer\": 1}\n{\"answer\": \"finance\"}\n",
+        );
+
+        assert!(!prompt.prompt_confirmation("Add more tags?", true).unwrap());
+        assert_eq!(prompt.prompt_selection("Choose", &["a", "b"]).unwrap(), 1);
+        assert_eq!(
+            prompt
+                .prompt_selection("Choose", &["bank", "finance"])
+                .unwrap(),
+            1
+        );
+
+        let events = output.events();
+        assert_eq!(events[0]["type"], "confirmation");
+        assert_eq!(events[0]["default"], true);
+        assert_eq!(events[1]["type"], "selection");
+        assert_eq!(events[1]["options"], serde_json::json!(["a", "b"]));
+    }
+
+    #[test]
+    fn test_tag_prompt_lists_known_tags() {
+        let mut dictionary = TagDictionary::new();
+        dictionary.add_tag("finance".to_string()).unwrap();
+        let (prompt, output) = prompt_with_answers("{\"answer\": \"finance, bank\"}\n{}\n{}\n");
+
+        assert_eq!(
+            prompt.prompt_tag_line("Tags", None, &dictionary).unwrap(),
+            "finance, bank"
+        );
+        assert_eq!(
+            prompt.prompt_tag_line("Tags", None, &dictionary).unwrap(),
+            ""
+        );
+        assert_eq!(
+            prompt
+                .prompt_tag_line("Tags", Some("finance"), &dictionary)
+                .unwrap(),
+            "finance"
+        );
+        let events = output.events();
+        assert_eq!(events[0]["known_tags"], serde_json::json!(["finance"]));
+        assert_eq!(events[2]["type"], "tags");
+        assert_eq!(events[2]["default"], "finance");
+    }
+
+    #[test]
+    fn test_clones_share_the_stream() {
+        let (prompt, output) = prompt_with_answers("{\"answer\": \"a\"}\n{\"answer\": \"b\"}\n");
+        let clone = prompt.clone();
+
+        assert_eq!(prompt.prompt_string("First", None).unwrap(), "a");
+        assert_eq!(clone.prompt_string("Second", None).unwrap(), "b");
+        assert_eq!(output.events()[1]["id"], 2);
+    }
+
+    #[test]
+    fn test_shown_text_is_an_info_event() {
+        let (prompt, output) = prompt_with_answers("");
+
+        prompt.show("\nFile: invoice.pdf");
+
+        assert_eq!(
+            output.events(),
+            vec![serde_json::json!({"event": "info", "message": "File: invoice.pdf"})]
+        );
+        assert!(!prompt.completes_tags());
+    }
+
+    #[test]
+    fn test_cancel_and_end_of_input() {
+        let (prompt, _) = prompt_with_answers("{\"cancel\": true}\n");
+        assert!(matches!(
+            prompt.prompt_string("Description", None),
+            Err(CleanboxError::UserCancelled)
+        ));
+        assert!(matches!(
+            prompt.prompt_confirmation("Continue?", true),
+            Err(CleanboxError::UserCancelled)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_answers() {
+        let (prompt, _) = prompt_with_answers(
+            "{\"answer\": 3}\n{\"answer\": 5}\n{\"id\": 7, \"answer\": true}\nnot json\n{}\n",
+        );
+
+        assert!(prompt.prompt_string("Description", None).is_err());
+        assert!(prompt.prompt_selection("Choose", &["a", "b"]).is_err());
+        assert!(prompt.prompt_confirmation("Continue?", true).is_err());
+        assert!(prompt.prompt_confirmation("Continue?", true).is_err());
+        // No default to fall back on
+        assert!(prompt.prompt_string("Description", None).is_err());
+    }
+}
@@ -1,40 +1,80 @@
+pub mod companions;
 pub mod config;
+pub mod container;
 pub mod document;
 pub mod error;
+pub mod export;
 pub mod filesystem;
+pub mod geocode;
+pub mod hashing;
 pub mod interactive;
+pub mod json_prompt;
+pub mod lint;
+pub mod logging;
+pub mod manifest;
 pub mod media;
 pub mod metadata;
 pub mod naming;
 pub mod organization;
+pub mod outcome;
 pub mod paths;
+pub mod pipeline;
 pub mod processor;
+pub mod reorganize;
+pub mod report;
+pub mod rules;
+pub mod sidecar;
 pub mod tags;
-
-pub use config::{DuplicateHandling, LifeConfig, ProcessingConfig};
+pub mod template;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod verify;
+pub mod xmp;
+
+pub use companions::{CompanionGroup, group_companions, merge_metadata};
+pub use config::{DuplicateHandling, LifeConfig, LifeSettings, ProcessingConfig};
 pub use document::{DocumentInput, today_date_string};
 pub use error::{CleanboxError, Result};
-pub use filesystem::{FileManager, StdFileManager};
+pub use export::{ExportEntry, ExportMetadata};
+pub use filesystem::{FileManager, StdFileManager, walk_files};
+pub use geocode::{Geocoder, Place, country_name};
+pub use hashing::{ContentHasher, HashAlgorithm};
 pub use interactive::{
-    ConsolePrompt, DatePrompt, DescriptionPrompt, DocumentInputCollector, ProgressIndicator,
-    SmartTagSelector, UserPrompt,
+    ConsolePrompt, DatePrompt, DescriptionPrompt, DocumentAction, DocumentActionPrompt,
+    DocumentInputCollector, ProgressIndicator, ProgressMode, SmartTagSelector, UserPrompt,
 };
-pub use media::{File, FileMetadata, FileType};
-pub use metadata::{MetadataParser, RexifParser};
+pub use json_prompt::JsonLinesPrompt;
+pub use lint::{ArchiveLinter, LintIssue, LintProblem, LintReport};
+pub use logging::{CleanboxLogger, ConsoleStream, LogFile, Verbosity};
+pub use manifest::{Manifest, ManifestEntry};
+pub use media::{File, FileMetadata, FileType, GpsPosition};
+pub use metadata::{MetadataParser, RexifParser, read_metadata};
 pub use naming::{
     CustomNamingStrategy, DocumentNamingStrategy, NamingStrategy, TimestampNamingStrategy,
 };
 pub use organization::{
-    CustomOrganizer, DocumentOrganizer, FlatOrganizer, MonthlyOrganizer, OrganizationStrategy,
-    YearlyOrganizer,
+    CustomOrganizer, DocumentOrganizer, Event, EventOrganizer, FlatOrganizer, MediaLayout,
+    MonthlyOrganizer, OrganizationStrategy, YearlyOrganizer,
 };
+pub use outcome::{FileOutcome, FileResult, SkipReason};
 pub use paths::{BasePathResolver, LifeDirectoryResolver, LifePathResolver};
+pub use pipeline::{PreparedFile, prepare_files};
 pub use processor::{
     CategorizedFiles, FileProcessor, ProcessingResult, UnifiedProcessingResult, UnifiedProcessor,
 };
+pub use reorganize::{ArchiveReorganizer, PlannedMove, ReorganizePlan};
+pub use report::{DatetimeSource, FileRecord, RecordStatus, ReportError, RunReport};
+pub use rules::{CaptureRef, DocumentRule, DocumentRules, RuleCandidate};
+pub use sidecar::{
+    DocumentSidecar, MediaSidecar, is_media_only_sidecar_file, is_media_sidecar_file,
+    match_media_sidecars,
+};
 pub use tags::{
     SimilarTag, TagDictionary, TagResolution, TagResolutionFlow, TagValidator, validate_tag_format,
 };
+pub use template::{Field, Template};
+pub use verify::{ArchiveVerifier, VerifyReport};
+pub use xmp::{MetadataWriter, MetadataWriting, XmpPacket, embed_in_jpeg, xmp_sidecar_path};
 
 use std::path::Path;
 
@@ -92,17 +132,48 @@ pub fn process_life_directory_unified(
     life_path: impl AsRef<Path>,
 ) -> Result<UnifiedProcessingResult> {
     let life_config = LifeConfig::new(life_path.as_ref().to_path_buf());
+    process_life_directory_unified_with_config(life_config)
+}
+
+/// Process life directory with unified workflow using a caller-provided configuration
+pub fn process_life_directory_unified_with_config(
+    life_config: LifeConfig,
+) -> Result<UnifiedProcessingResult> {
+    process_life_directory_unified_with_prompt(life_config, ConsolePrompt::new())
+}
 
+/// Process life directory with unified workflow, answering questions through `prompter`
+pub fn process_life_directory_unified_with_prompt<P: UserPrompt + Clone>(
+    life_config: LifeConfig,
+    prompter: P,
+) -> Result<UnifiedProcessingResult> {
     let unified_processor = UnifiedProcessor::new(
         RexifParser::new(),
         StdFileManager::new(),
-        interactive::ConsolePrompt::new(),
+        prompter,
         life_config,
     );
 
     unified_processor.process_life_directory()
 }
 
+/// Import a Google Takeout or iCloud Photos export into the life directory, answering
+/// questions through `prompter`
+pub fn import_export_directory_with_prompt<P: UserPrompt + Clone>(
+    life_config: LifeConfig,
+    source: impl AsRef<Path>,
+    prompter: P,
+) -> Result<UnifiedProcessingResult> {
+    let unified_processor = UnifiedProcessor::new(
+        RexifParser::new(),
+        StdFileManager::new(),
+        prompter,
+        life_config,
+    );
+
+    unified_processor.import_directory(source.as_ref())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -136,10 +207,11 @@ mod tests {
         let _naming: TimestampNamingStrategy = TimestampNamingStrategy::new();
         let _org: MonthlyOrganizer = MonthlyOrganizer::new();
         let _custom_naming: CustomNamingStrategy =
-            CustomNamingStrategy::new("{datetime}.{ext}".to_string());
+            CustomNamingStrategy::new("{datetime}.{ext}".to_string()).unwrap();
         let _yearly_org: YearlyOrganizer = YearlyOrganizer::new();
         let _flat_org: FlatOrganizer = FlatOrganizer::new();
-        let _custom_org: CustomOrganizer = CustomOrganizer::new("{year}/{month}".to_string());
+        let _custom_org: CustomOrganizer =
+            CustomOrganizer::new("{year}/{month}".to_string()).unwrap();
 
         let _duplicate_handling: DuplicateHandling = DuplicateHandling::AppendHash;
 
@@ -243,7 +315,7 @@ mod tests {
 
         assert_eq!(result.files_skipped, 0);
         assert_eq!(result.files_failed, 0);
-        assert!(result.errors.is_empty());
+        assert!(result.outcomes.is_empty());
     }
 
     #[test]
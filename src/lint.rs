@@ -0,0 +1,478 @@
+use crate::config::LifeConfig;
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::{FileHasher, FileManager, walk_files};
+use crate::geocode::Geocoder;
+use crate::media::File;
+use crate::metadata::MetadataParser;
+use crate::naming::{DocumentNamingStrategy, NamingStrategy, TimestampNamingStrategy};
+use crate::organization::MediaLayout;
+use crate::organization::{DocumentOrganizer, OrganizationStrategy};
+use crate::outcome::FileResult;
+use crate::reorganize::{
+    PlannedMove, archived_media_layout, archived_media_metadata, move_archived_files,
+};
+use crate::sidecar::{MediaSidecar, match_document_sidecars, match_media_sidecars};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How an archived file departs from the cleanbox layout
+#[derive(Debug)]
+pub enum LintProblem {
+    /// Media not named `YYYY-MM-DD_HH-MM-SS.ext`
+    MediaName,
+    /// Media outside the folder the media layout gives it
+    MediaFolder,
+    /// Document name that does not parse or validate as `DocumentInput`
+    DocumentName(CleanboxError),
+    /// Document outside the month folder of its date
+    DocumentFolder,
+    /// XMP, AAE or JSON sidecar of a media file or document that moves
+    MediaSidecar,
+}
+
+impl fmt::Display for LintProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintProblem::MediaName => write!(f, "not a timestamp name"),
+            LintProblem::MediaFolder => write!(f, "folder does not match the datetime"),
+            LintProblem::DocumentName(err) => write!(f, "invalid document name: {err}"),
+            LintProblem::DocumentFolder => write!(f, "folder does not match the date"),
+            LintProblem::MediaSidecar => write!(f, "follows its file"),
+        }
+    }
+}
+
+/// One file that violates the layout, with the move that would fix it
+#[derive(Debug)]
+pub struct LintIssue {
+    pub path: PathBuf,
+    pub problem: LintProblem,
+    /// Where the file belongs, or None if fixing it needs a person
+    pub fix: Option<PathBuf>,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.fix {
+            Some(target) => write!(
+                f,
+                "{}: {} -> {}",
+                self.path.display(),
+                self.problem,
+                target.display()
+            ),
+            None => write!(f, "{}: {} (fix by hand)", self.path.display(), self.problem),
+        }
+    }
+}
+
+/// Layout violations found in the archive, in path order
+#[derive(Debug, Default)]
+pub struct LintReport {
+    pub issues: Vec<LintIssue>,
+}
+
+impl LintReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Issues with an automatic fix, which together make up the fix plan
+    pub fn fixes(&self) -> impl Iterator<Item = &LintIssue> {
+        self.issues.iter().filter(|issue| issue.fix.is_some())
+    }
+}
+
+/// Checks archived files against the names and folders cleanbox would give them.
+///
+/// Media names are checked against `TimestampNamingStrategy` and their folder against
+/// the configured `MediaLayout`; document names against the `DocumentInput` validators and their
+/// folder against `DocumentOrganizer`.
+pub struct ArchiveLinter<E: MetadataParser, F: FileManager> {
+    metadata_parser: E,
+    file_manager: F,
+    life_config: LifeConfig,
+}
+
+impl<E: MetadataParser, F: FileManager> ArchiveLinter<E, F> {
+    pub fn new(metadata_parser: E, file_manager: F, life_config: LifeConfig) -> Self {
+        Self {
+            metadata_parser,
+            file_manager,
+            life_config,
+        }
+    }
+
+    pub fn lint(&self) -> Result<LintReport> {
+        let mut report = LintReport::new();
+        // Targets of earlier fixes, so two files are never planned into one name
+        let mut claimed = HashSet::new();
+
+        let media_files: Vec<PathBuf> =
+            walk_files(&self.file_manager, self.life_config.media_root())?
+                .into_iter()
+                .filter(|path| !self.is_bookkeeping(path))
+                .collect();
+        let (media_files, media_sidecars) = match_media_sidecars(&media_files);
+        let geocoder = Geocoder::for_life(&self.life_config, &self.file_manager)?;
+        let layout = archived_media_layout(
+            &self.metadata_parser,
+            &media_files,
+            &self.life_config.media_layout,
+            geocoder.as_ref(),
+        )?;
+        for path in media_files {
+            let Some(issue) = self.lint_media(&path, &layout, geocoder.as_ref(), &mut claimed)
+            else {
+                continue;
+            };
+            let primary_fix = issue.fix.clone();
+            report.issues.push(issue);
+
+            if let Some(primary_fix) = primary_fix {
+                let sidecars = media_sidecars.get(&path).into_iter().flatten();
+                self.lint_sidecars(sidecars, &primary_fix, &mut claimed, &mut report);
+            }
+        }
+
+        // Documents keep their answer sidecars and the XMP sidecars written for their tags
+        let document_files: Vec<PathBuf> =
+            walk_files(&self.file_manager, self.life_config.documents_root())?
+                .into_iter()
+                .filter(|path| !self.is_bookkeeping(path))
+                .collect();
+        let (document_files, document_sidecars) = match_document_sidecars(&document_files);
+        for path in document_files {
+            let Some(issue) = self.lint_document(&path, &mut claimed) else {
+                continue;
+            };
+            let primary_fix = issue.fix.clone();
+            report.issues.push(issue);
+
+            if let Some(primary_fix) = primary_fix {
+                let sidecars = document_sidecars.get(&path).into_iter().flatten();
+                self.lint_sidecars(sidecars, &primary_fix, &mut claimed, &mut report);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Sidecars move with the file they follow
+    fn lint_sidecars<'a>(
+        &self,
+        sidecars: impl Iterator<Item = &'a MediaSidecar>,
+        primary_fix: &Path,
+        claimed: &mut HashSet<PathBuf>,
+        report: &mut LintReport,
+    ) {
+        for sidecar in sidecars {
+            let fix = sidecar.target(primary_fix).ok().filter(|target| {
+                !self.file_manager.file_exists(target) && claimed.insert(target.clone())
+            });
+            report.issues.push(LintIssue {
+                path: sidecar.path.clone(),
+                problem: LintProblem::MediaSidecar,
+                fix,
+            });
+        }
+    }
+
+    /// Move every file in the fix plan and carry its manifest entry along
+    pub fn apply(&self, report: &LintReport) -> Result<Vec<FileResult>> {
+        let moves: Vec<PlannedMove> = report
+            .fixes()
+            .filter_map(|issue| {
+                Some(PlannedMove {
+                    from: issue.path.clone(),
+                    to: issue.fix.clone()?,
+                })
+            })
+            .collect();
+        move_archived_files(&self.file_manager, &self.life_config, &moves, false)
+    }
+
+    fn lint_media(
+        &self,
+        path: &Path,
+        layout: &MediaLayout,
+        geocoder: Option<&Geocoder>,
+        claimed: &mut HashSet<PathBuf>,
+    ) -> Option<LintIssue> {
+        let name = path.file_name()?.to_str()?;
+        let media_root = self.life_config.media_root();
+
+        if TimestampNamingStrategy::parse_name(name).is_some() {
+            let directory = self.media_directory(path, layout, geocoder).ok()?;
+            if path.parent() == Some(directory.as_path()) {
+                return None;
+            }
+            return Some(LintIssue {
+                path: path.to_path_buf(),
+                problem: LintProblem::MediaFolder,
+                fix: self.claim(path, directory.join(name), claimed),
+            });
+        }
+
+        // Without a timestamp name the datetime has to come from the file itself
+        let fix = self
+            .metadata_parser
+            .parse_metadata(path)
+            .ok()
+            .filter(|metadata| metadata.datetime_original.is_some())
+            .and_then(|mut metadata| {
+                if let Some(geocoder) = geocoder {
+                    geocoder.locate(&mut metadata);
+                }
+                let file = File::new(path).with_metadata(metadata);
+                let name = TimestampNamingStrategy::new().generate_name(&file).ok()?;
+                let directory = layout.determine_target_directory(&file, &media_root).ok()?;
+                Some(directory.join(name))
+            })
+            .and_then(|target| self.claim(path, target, claimed));
+
+        Some(LintIssue {
+            path: path.to_path_buf(),
+            problem: LintProblem::MediaName,
+            fix,
+        })
+    }
+
+    fn lint_document(&self, path: &Path, claimed: &mut HashSet<PathBuf>) -> Option<LintIssue> {
+        let name = path.file_name()?.to_str()?;
+
+        let input = match DocumentNamingStrategy::parse_name(name) {
+            Ok(input) => input,
+            Err(e) => {
+                return Some(LintIssue {
+                    path: path.to_path_buf(),
+                    problem: LintProblem::DocumentName(e),
+                    fix: None,
+                });
+            }
+        };
+
+        let directory = DocumentOrganizer::new()
+            .determine_target_directory_from_input(&input, &self.life_config.documents_root())
+            .ok()?;
+        if path.parent() == Some(directory.as_path()) {
+            return None;
+        }
+
+        Some(LintIssue {
+            path: path.to_path_buf(),
+            problem: LintProblem::DocumentFolder,
+            fix: self.claim(path, directory.join(name), claimed),
+        })
+    }
+
+    fn media_directory(
+        &self,
+        path: &Path,
+        layout: &MediaLayout,
+        geocoder: Option<&Geocoder>,
+    ) -> Result<PathBuf> {
+        let metadata = archived_media_metadata(&self.metadata_parser, path, layout, geocoder)?;
+        let file = File::new(path).with_metadata(metadata);
+        layout.determine_target_directory(&file, &self.life_config.media_root())
+    }
+
+    /// Reserve `target` for `source`, adding a hash suffix if the name is taken
+    fn claim(
+        &self,
+        source: &Path,
+        target: PathBuf,
+        claimed: &mut HashSet<PathBuf>,
+    ) -> Option<PathBuf> {
+        let taken =
+            |target: &Path| claimed.contains(target) || self.file_manager.file_exists(target);
+
+        let target = if taken(&target) {
+            let hash = self
+                .file_manager
+                .hash_file(source, self.life_config.hash_algorithm)
+                .ok()?;
+            let suffix = FileHasher::generate_hash_suffix(&hash, self.life_config.hash_length);
+            let name =
+                FileHasher::append_hash_to_filename(target.file_name()?.to_str()?, &suffix).ok()?;
+            let renamed = target.with_file_name(name);
+            if renamed == source || taken(&renamed) {
+                return None;
+            }
+            renamed
+        } else {
+            target
+        };
+
+        claimed.insert(target.clone());
+        Some(target)
+    }
+
+    /// Files cleanbox keeps in the archive for itself, and hidden files
+    fn is_bookkeeping(&self, path: &Path) -> bool {
+        path == self.life_config.tags_file()
+            || path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with('.'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::{ContentHasher, HashAlgorithm};
+    use crate::manifest::{Manifest, ManifestEntry};
+    use crate::outcome::FileOutcome;
+    use crate::testing::{InMemoryFileManager, StaticMetadataParser};
+
+    fn linter(
+        file_manager: &InMemoryFileManager,
+        parser: StaticMetadataParser,
+    ) -> ArchiveLinter<StaticMetadataParser, InMemoryFileManager> {
+        ArchiveLinter::new(
+            parser,
+            file_manager.clone(),
+            LifeConfig::new(PathBuf::from("/life")),
+        )
+    }
+
+    #[test]
+    fn test_clean_archive_has_no_issues() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.jpg", b"a");
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00_a1b2c3.jpg", b"b");
+        file_manager.add_file(
+            "/life/documents/2024/05/2024-05-01_statement@@bank.pdf",
+            b"c",
+        );
+        file_manager.add_file(
+            "/life/documents/2024/05/2024-05-01_statement@@bank.pdf.xmp",
+            b"<x:xmpmeta/>",
+        );
+        file_manager.add_file(
+            "/life/documents/2024/05/2024-05-01_statement@@bank.pdf.cleanbox.toml",
+            b"description = \"statement\"\ntags = [\"bank\"]\n",
+        );
+        file_manager.add_file("/life/documents/tags.txt", b"bank\n");
+
+        let report = linter(&file_manager, StaticMetadataParser::new())
+            .lint()
+            .unwrap();
+        assert!(report.is_clean(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn test_plan_covers_names_and_folders() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file(
+            "/life/media/2024/06/2024-05-01_10-00-00.jpg",
+            b"wrong month",
+        );
+        file_manager.add_file("/life/media/2024/05/IMG_0001.jpg", b"camera name");
+        file_manager.add_file("/life/media/2024/05/IMG_0002.jpg", b"no exif");
+        file_manager.add_file(
+            "/life/documents/2023/2024-05-01_statement@@bank.pdf",
+            b"doc",
+        );
+        file_manager.add_file(
+            "/life/documents/2023/2024-05-01_statement@@bank.pdf.xmp",
+            b"<x:xmpmeta/>",
+        );
+        file_manager.add_file("/life/documents/2024/05/Scan 12.pdf", b"scan");
+        let parser = StaticMetadataParser::new()
+            .with_datetime("/life/media/2024/05/IMG_0001.jpg", "2024-05-02_08-30-00");
+
+        let report = linter(&file_manager, parser).lint().unwrap();
+        let plan: Vec<(&Path, Option<&Path>)> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.path.as_path(), issue.fix.as_deref()))
+            .collect();
+
+        assert_eq!(
+            plan,
+            vec![
+                (
+                    Path::new("/life/media/2024/05/IMG_0001.jpg"),
+                    Some(Path::new("/life/media/2024/05/2024-05-02_08-30-00.jpg"))
+                ),
+                (Path::new("/life/media/2024/05/IMG_0002.jpg"), None),
+                (
+                    Path::new("/life/media/2024/06/2024-05-01_10-00-00.jpg"),
+                    Some(Path::new("/life/media/2024/05/2024-05-01_10-00-00.jpg"))
+                ),
+                (
+                    Path::new("/life/documents/2023/2024-05-01_statement@@bank.pdf"),
+                    Some(Path::new(
+                        "/life/documents/2024/05/2024-05-01_statement@@bank.pdf"
+                    ))
+                ),
+                (
+                    Path::new("/life/documents/2023/2024-05-01_statement@@bank.pdf.xmp"),
+                    Some(Path::new(
+                        "/life/documents/2024/05/2024-05-01_statement@@bank.pdf.xmp"
+                    ))
+                ),
+                (Path::new("/life/documents/2024/05/Scan 12.pdf"), None),
+            ]
+        );
+        assert!(matches!(report.issues[1].problem, LintProblem::MediaName));
+        assert!(matches!(
+            report.issues[5].problem,
+            LintProblem::DocumentName(_)
+        ));
+        assert_eq!(report.fixes().count(), 4);
+    }
+
+    #[test]
+    fn test_taken_target_gets_hash_suffix() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.jpg", b"first");
+        file_manager.add_file("/life/media/2024/06/2024-05-01_10-00-00.jpg", b"second");
+
+        let report = linter(&file_manager, StaticMetadataParser::new())
+            .lint()
+            .unwrap();
+
+        let hash = ContentHasher::hash_bytes(HashAlgorithm::default(), b"second");
+        assert_eq!(
+            report.issues[0].fix,
+            Some(PathBuf::from(format!(
+                "/life/media/2024/05/2024-05-01_10-00-00_{}.jpg",
+                &hash[..6]
+            )))
+        );
+    }
+
+    #[test]
+    fn test_apply_moves_files_and_manifest_entries() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/media/2024/06/2024-05-01_10-00-00.jpg", b"photo");
+        let mut manifest = Manifest::new();
+        manifest.insert(
+            "media/2024/06/2024-05-01_10-00-00.jpg".to_string(),
+            ManifestEntry::new(HashAlgorithm::Sha1, "00", 5),
+        );
+        manifest.save("/life/manifest.json", &file_manager).unwrap();
+
+        let linter = linter(&file_manager, StaticMetadataParser::new());
+        let report = linter.lint().unwrap();
+        let results = linter.apply(&report).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0].outcome, FileOutcome::Moved(_)));
+        file_manager.assert_tree(
+            "/life",
+            &["manifest.json", "media/2024/05/2024-05-01_10-00-00.jpg"],
+        );
+        let manifest = Manifest::load_from_file("/life/manifest.json", &file_manager).unwrap();
+        assert!(manifest.contains("media/2024/05/2024-05-01_10-00-00.jpg"));
+        assert!(linter.lint().unwrap().is_clean());
+    }
+}
@@ -0,0 +1,403 @@
+use crate::error::{IoResultExt, Result};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Console level of the installed logger, `LevelFilter::Info` until `init` runs
+static CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+
+/// Whether informational console output goes to stderr, set by `init`
+static CONSOLE_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Terminal stream for informational output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleStream {
+    /// Interleaved with the interactive prompts
+    Stdout,
+    /// Keeps stdout free for a report or scripted prompts
+    Stderr,
+}
+
+impl ConsoleStream {
+    pub fn writer(&self) -> Box<dyn Write> {
+        match self {
+            ConsoleStream::Stdout => Box::new(io::stdout()),
+            ConsoleStream::Stderr => Box::new(io::stderr()),
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            ConsoleStream::Stdout => io::stdout().is_terminal(),
+            ConsoleStream::Stderr => io::stderr().is_terminal(),
+        }
+    }
+}
+
+/// How much is printed to the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Errors only, for cron jobs
+    Quiet,
+    /// Progress and warnings
+    Normal,
+    /// Per-file decisions
+    Verbose,
+    /// Everything, including metadata details
+    Trace,
+}
+
+impl Verbosity {
+    /// Verbosity for the number of `-v` flags and the `-q` flag
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Trace,
+        }
+    }
+
+    pub fn console_level(&self) -> LevelFilter {
+        match self {
+            Verbosity::Quiet => LevelFilter::Error,
+            Verbosity::Normal => LevelFilter::Info,
+            Verbosity::Verbose => LevelFilter::Debug,
+            Verbosity::Trace => LevelFilter::Trace,
+        }
+    }
+
+    /// The log file keeps per-file detail even when the console is quieter
+    pub fn file_level(&self) -> LevelFilter {
+        self.console_level().max(LevelFilter::Debug)
+    }
+}
+
+/// Log file that is rotated when it grows past a size limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl LogFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            max_bytes: 1024 * 1024,
+            keep: 3,
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Number of rotated files (`cleanbox.log.1`, `cleanbox.log.2`, ...) to keep
+    pub fn with_keep(mut self, keep: usize) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(format!(".{index}"));
+        PathBuf::from(name)
+    }
+
+    /// Shift the log into `.1`, `.1` into `.2` and so on if it is over the limit
+    pub fn rotate_if_needed(&self) -> Result<()> {
+        let size = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).at_path(&self.path),
+        };
+        if size < self.max_bytes {
+            return Ok(());
+        }
+
+        if self.keep == 0 {
+            return fs::remove_file(&self.path).at_path(&self.path);
+        }
+
+        for index in (1..self.keep).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                let to = self.rotated_path(index + 1);
+                fs::rename(&from, &to).at_path(&from)?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1)).at_path(&self.path)
+    }
+
+    /// Rotate if needed and open the log for appending
+    pub fn open(&self) -> Result<File> {
+        self.rotate_if_needed()?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .at_path(&self.path)
+    }
+}
+
+/// Logger writing progress to the terminal and details to a log file.
+///
+/// Info goes to the console stream as is, stdout by default so it reads like the
+/// interactive prompts around it; warnings and errors always go to stderr. Log
+/// file lines carry a timestamp and level.
+pub struct CleanboxLogger {
+    console_level: LevelFilter,
+    console_stream: ConsoleStream,
+    file_level: LevelFilter,
+    file: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl CleanboxLogger {
+    pub fn new(verbosity: Verbosity) -> Self {
+        Self {
+            console_level: verbosity.console_level(),
+            console_stream: ConsoleStream::Stdout,
+            file_level: verbosity.file_level(),
+            file: None,
+        }
+    }
+
+    pub fn with_file(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.file = Some(Mutex::new(Box::new(writer)));
+        self
+    }
+
+    pub fn with_console_stream(mut self, stream: ConsoleStream) -> Self {
+        self.console_stream = stream;
+        self
+    }
+
+    /// Stream a console line of `level` is written to
+    fn stream_for(&self, level: Level) -> ConsoleStream {
+        if level <= Level::Warn {
+            ConsoleStream::Stderr
+        } else {
+            self.console_stream
+        }
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        if self.file.is_some() {
+            self.console_level.max(self.file_level)
+        } else {
+            self.console_level
+        }
+    }
+
+    fn format_console(record: &Record) -> String {
+        match record.level() {
+            Level::Error => format!("Error: {}", record.args()),
+            Level::Warn => format!("Warning: {}", record.args()),
+            _ => record.args().to_string(),
+        }
+    }
+
+    fn format_file(record: &Record) -> String {
+        format!(
+            "{} {:<5} {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            record.level(),
+            record.args().to_string().trim()
+        )
+    }
+}
+
+impl Log for CleanboxLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.console_level {
+            let line = Self::format_console(record);
+            match self.stream_for(record.level()) {
+                ConsoleStream::Stdout => println!("{line}"),
+                ConsoleStream::Stderr => eprintln!("{line}"),
+            }
+        }
+
+        if record.level() <= self.file_level
+            && let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            // A failing log file must not interrupt processing
+            let _ = writeln!(file, "{}", Self::format_file(record));
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Install the global logger, appending to `log_file` if given.
+///
+/// Informational console output goes to `stream`. If the log file cannot be
+/// opened, logging continues on the console only and the returned error says why.
+pub fn init(verbosity: Verbosity, log_file: Option<&LogFile>, stream: ConsoleStream) -> Result<()> {
+    let mut logger = CleanboxLogger::new(verbosity).with_console_stream(stream);
+    let file_error = match log_file.map(LogFile::open) {
+        Some(Ok(file)) => {
+            logger = logger.with_file(file);
+            None
+        }
+        Some(Err(e)) => Some(e),
+        None => None,
+    };
+
+    CONSOLE_LEVEL.store(logger.console_level as usize, Ordering::Relaxed);
+    CONSOLE_STDERR.store(stream == ConsoleStream::Stderr, Ordering::Relaxed);
+    log::set_max_level(logger.max_level());
+    // Only the first call installs a logger
+    let _ = log::set_boxed_logger(Box::new(logger));
+
+    match file_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Whether messages of `level` reach the terminal, for output such as progress
+/// lines that bypasses the logger
+pub fn console_enabled(level: Level) -> bool {
+    level as usize <= CONSOLE_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Write a line of informational output that bypasses the logger, such as prompt
+/// hints, to the console stream
+pub fn console_line(line: &str) {
+    let _ = writeln!(console_stream().writer(), "{line}");
+}
+
+/// Stream chosen in `init` for informational output that bypasses the logger
+pub fn console_stream() -> ConsoleStream {
+    if CONSOLE_STDERR.load(Ordering::Relaxed) {
+        ConsoleStream::Stderr
+    } else {
+        ConsoleStream::Stdout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log_line(logger: &CleanboxLogger, level: Level, message: &str) {
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .args(format_args!("{message}"))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_verbosity_from_flags() {
+        assert_eq!(Verbosity::from_flags(0, false), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(1, false), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_flags(3, false), Verbosity::Trace);
+        assert_eq!(Verbosity::from_flags(2, true), Verbosity::Quiet);
+
+        assert_eq!(Verbosity::Quiet.console_level(), LevelFilter::Error);
+        assert_eq!(Verbosity::Quiet.file_level(), LevelFilter::Debug);
+        assert_eq!(Verbosity::Trace.file_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_file_receives_detail_hidden_from_quiet_console() {
+        let buffer = SharedBuffer::default();
+        let logger = CleanboxLogger::new(Verbosity::Quiet).with_file(buffer.clone());
+
+        log_line(&logger, Level::Debug, "\n  Moved to /media/a.jpg");
+        log_line(&logger, Level::Trace, "EXIF details");
+
+        let contents = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with("DEBUG Moved to /media/a.jpg"));
+        assert_eq!(logger.max_level(), LevelFilter::Debug);
+        assert_eq!(
+            CleanboxLogger::new(Verbosity::Quiet).max_level(),
+            LevelFilter::Error
+        );
+    }
+
+    #[test]
+    fn test_info_follows_the_console_stream() {
+        let logger = CleanboxLogger::new(Verbosity::Normal);
+        assert_eq!(logger.stream_for(Level::Info), ConsoleStream::Stdout);
+        assert_eq!(logger.stream_for(Level::Warn), ConsoleStream::Stderr);
+
+        let logger = logger.with_console_stream(ConsoleStream::Stderr);
+        assert_eq!(logger.stream_for(Level::Info), ConsoleStream::Stderr);
+        assert_eq!(logger.stream_for(Level::Debug), ConsoleStream::Stderr);
+        assert_eq!(logger.stream_for(Level::Error), ConsoleStream::Stderr);
+    }
+
+    #[test]
+    fn test_log_file_rotation() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let log_file = LogFile::new(temp_dir.path().join("cleanbox.log"))
+            .with_max_bytes(10)
+            .with_keep(2);
+
+        // Nothing to rotate yet
+        log_file.rotate_if_needed().unwrap();
+
+        for run in ["first run\n", "second run\n", "third run\n"] {
+            let mut file = log_file.open().unwrap();
+            file.write_all(run.as_bytes()).unwrap();
+        }
+        log_file.rotate_if_needed().unwrap();
+
+        let read = |name: &str| fs::read_to_string(temp_dir.path().join(name)).ok();
+        assert_eq!(read("cleanbox.log"), None);
+        assert_eq!(read("cleanbox.log.1").as_deref(), Some("third run\n"));
+        assert_eq!(read("cleanbox.log.2").as_deref(), Some("second run\n"));
+        assert_eq!(read("cleanbox.log.3"), None);
+    }
+
+    #[test]
+    fn test_small_log_file_is_appended() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let log_file = LogFile::new(temp_dir.path().join("cleanbox.log"));
+
+        log_file.open().unwrap().write_all(b"one\n").unwrap();
+        log_file.open().unwrap().write_all(b"two\n").unwrap();
+
+        assert_eq!(fs::read_to_string(log_file.path()).unwrap(), "one\ntwo\n");
+    }
+}
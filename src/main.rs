@@ -1,31 +1,281 @@
 mod cli;
-use cleanbox::process_life_directory_unified;
-use cli::parse_args;
+use cleanbox::{
+    ArchiveLinter, ArchiveReorganizer, ArchiveVerifier, ConsolePrompt, ConsoleStream, FileOutcome,
+    JsonLinesPrompt, LifeConfig, LifeSettings, LogFile, RexifParser, StdFileManager,
+    UnifiedProcessingResult, UserPrompt, Verbosity, import_export_directory_with_prompt, logging,
+    process_life_directory_unified_with_prompt,
+};
+use cli::{Command, ReportFormat, parse_args};
+use std::path::{Path, PathBuf};
 use std::process;
 
 fn main() {
     let args = parse_args();
 
-    match process_life_directory_unified(&args.life_path) {
+    let mut life_config = LifeConfig::new(PathBuf::from(&args.life_path))
+        .with_interactive(!args.non_interactive)
+        .with_name_events(args.name_events);
+    if let Some(viewer) = args.viewer {
+        life_config = life_config.with_viewer_command(viewer);
+    }
+    if let Some(jobs) = args.jobs {
+        life_config = life_config.with_workers(jobs.into());
+    }
+
+    let verbosity = Verbosity::from_flags(args.verbose, args.quiet);
+    let log_file = (!args.no_log_file).then(|| LogFile::new(life_config.log_file()));
+    // Keep stdout for the report or the scripted prompts when they use it
+    let stdout_reserved = (args.report.is_some() && args.report_path.is_none())
+        || args.answers.as_deref() == Some("-");
+    let stream = if stdout_reserved {
+        ConsoleStream::Stderr
+    } else {
+        ConsoleStream::Stdout
+    };
+    if let Err(e) = logging::init(verbosity, log_file.as_ref(), stream) {
+        log::warn!("Logging to the console only: {e}");
+    }
+
+    match LifeSettings::load_from_file(life_config.settings_file(), &StdFileManager::new()) {
+        Ok(settings) => life_config = life_config.with_settings(settings),
+        Err(e) => {
+            log::error!("{e}");
+            process::exit(1);
+        }
+    }
+    if let Some(algorithm) = args.hash_algorithm {
+        life_config = life_config.with_hash_algorithm(algorithm);
+    }
+    if let Some(layout) = args.media_layout {
+        life_config = life_config.with_media_layout(layout);
+    }
+    if let Some(mode) = args.write_metadata {
+        life_config = life_config.with_write_metadata(mode);
+    }
+
+    let mut import_source = None;
+    match args.command {
+        Some(Command::Verify { limit, sample }) => {
+            process::exit(verify(life_config, limit, sample));
+        }
+        Some(Command::Lint { apply }) => {
+            let ask = !apply && !args.non_interactive;
+            process::exit(lint(life_config, apply, ask));
+        }
+        Some(Command::Reorganize { dry_run }) => {
+            process::exit(reorganize(life_config, dry_run));
+        }
+        Some(Command::Import { source }) => import_source = Some(source),
+        None => {}
+    }
+
+    let source = import_source.as_deref();
+    let outcome = match args.answers.as_deref() {
+        Some("-") => process(life_config, source, JsonLinesPrompt::from_stdio()),
+        Some(path) => JsonLinesPrompt::from_file(path)
+            .and_then(|prompter| process(life_config, source, prompter)),
+        None => process(life_config, source, ConsolePrompt::new()),
+    };
+
+    match outcome {
+        Ok(result) if args.report == Some(ReportFormat::Json) => {
+            let json = match result.report.to_json() {
+                Ok(json) => json,
+                Err(e) => {
+                    eprintln!("Failed to build report: {e}");
+                    process::exit(1);
+                }
+            };
+
+            match &args.report_path {
+                Some(path) => {
+                    if let Err(e) = std::fs::write(path, json + "\n") {
+                        eprintln!("Failed to write report to {path}: {e}");
+                        process::exit(1);
+                    }
+                }
+                None => println!("{json}"),
+            }
+
+            if result.files_failed > 0 {
+                process::exit(1);
+            }
+        }
         Ok(result) => {
-            println!("\nProcessing completed:");
-            println!("  Media files processed: {}", result.media_processed);
-            println!("  Documents processed: {}", result.documents_processed);
-            println!("  Files skipped: {}", result.files_skipped);
-            println!("  Files failed: {}", result.files_failed);
-            println!("  Total processed: {}", result.total_processed());
-
-            if !result.errors.is_empty() {
-                println!("\nErrors:");
-                for error in &result.errors {
-                    eprintln!("  {error}");
+            log::info!("\nProcessing completed:");
+            log::info!("  Media files processed: {}", result.media_processed);
+            log::info!("  Documents processed: {}", result.documents_processed);
+            log::info!("  Files skipped: {}", result.files_skipped);
+            log::info!("  Files failed: {}", result.files_failed);
+            log::info!("  Total processed: {}", result.total_processed());
+
+            if result.files_failed > 0 {
+                for failure in result.failures() {
+                    log::error!("{failure}");
                 }
                 process::exit(1);
             }
         }
         Err(e) => {
-            eprintln!("Failed to process directory: {e}");
+            log::error!("Failed to process directory: {e}");
             process::exit(1);
         }
     }
 }
+
+/// Process the inbox, or the export at `import_source`
+fn process<P: UserPrompt + Clone>(
+    life_config: LifeConfig,
+    import_source: Option<&Path>,
+    prompter: P,
+) -> cleanbox::Result<UnifiedProcessingResult> {
+    match import_source {
+        Some(source) => import_export_directory_with_prompt(life_config, source, prompter),
+        None => process_life_directory_unified_with_prompt(life_config, prompter),
+    }
+}
+
+/// Verify the archive against its manifest and return the exit code
+fn verify(life_config: LifeConfig, limit: Option<usize>, sample: Option<usize>) -> i32 {
+    let mut verifier = ArchiveVerifier::new(StdFileManager::new(), life_config);
+    if let Some(limit) = limit {
+        verifier = verifier.with_limit(limit);
+    }
+    if let Some(sample) = sample {
+        verifier = verifier.with_sample(sample);
+    }
+
+    let report = match verifier.verify() {
+        Ok(report) => report,
+        Err(e) => {
+            log::error!("Failed to verify archive: {e}");
+            return 1;
+        }
+    };
+
+    for path in &report.missing {
+        log::error!("Missing: {}", path.display());
+    }
+    for path in &report.untracked {
+        log::warn!("Not in manifest: {}", path.display());
+    }
+
+    log::info!("\nVerification completed:");
+    log::info!("  Files checked: {}", report.checked);
+    log::info!("  Modified: {}", report.modified.len());
+    log::info!("  Missing: {}", report.missing.len());
+    log::info!("  Unreadable: {}", report.unreadable.len());
+    log::info!("  Not in manifest: {}", report.untracked.len());
+
+    if report.is_clean() { 0 } else { 1 }
+}
+
+/// Print the lint fix plan, apply it if `apply` or confirmed when `ask`, and return the exit code
+fn lint(life_config: LifeConfig, apply: bool, ask: bool) -> i32 {
+    let linter = ArchiveLinter::new(RexifParser::new(), StdFileManager::new(), life_config);
+    let report = match linter.lint() {
+        Ok(report) => report,
+        Err(e) => {
+            log::error!("Failed to lint archive: {e}");
+            return 1;
+        }
+    };
+
+    if report.is_clean() {
+        log::info!("Archive layout is clean");
+        return 0;
+    }
+    for issue in &report.issues {
+        log::info!("{issue}");
+    }
+
+    let fixes = report.fixes().count();
+    log::info!(
+        "\n{} issues, {} with an automatic fix",
+        report.issues.len(),
+        fixes
+    );
+    if fixes == 0 {
+        return 1;
+    }
+
+    let confirmed = apply
+        || (ask
+            && ConsolePrompt::new()
+                .prompt_confirmation(&format!("Apply {fixes} fixes?"), false)
+                .unwrap_or(false));
+    if !confirmed {
+        return 1;
+    }
+
+    let results = match linter.apply(&report) {
+        Ok(results) => results,
+        Err(e) => {
+            log::error!("Failed to apply fixes: {e}");
+            return 1;
+        }
+    };
+    let mut moved = 0;
+    for result in &results {
+        match &result.outcome {
+            FileOutcome::Moved(_) => moved += 1,
+            _ => log::error!("{result}"),
+        }
+    }
+    log::info!("Fixed {moved} of {} files", report.issues.len());
+
+    if moved == report.issues.len() { 0 } else { 1 }
+}
+
+fn reorganize(life_config: LifeConfig, dry_run: bool) -> i32 {
+    let layout = life_config.media_layout.clone();
+    let reorganizer =
+        ArchiveReorganizer::new(RexifParser::new(), StdFileManager::new(), life_config);
+    let plan = match reorganizer.plan() {
+        Ok(plan) => plan,
+        Err(e) => {
+            log::error!("Failed to plan reorganization: {e}");
+            return 1;
+        }
+    };
+
+    for result in &plan.unresolved {
+        log::warn!("{result}");
+    }
+    if plan.moves.is_empty() {
+        log::info!("Media already follows the {layout} layout");
+        return if plan.unresolved.is_empty() { 0 } else { 1 };
+    }
+    for planned in &plan.moves {
+        log::info!("{planned}");
+    }
+    if dry_run {
+        log::info!(
+            "\n{} files would move to the {layout} layout",
+            plan.moves.len()
+        );
+        return 0;
+    }
+
+    let results = match reorganizer.apply(&plan) {
+        Ok(results) => results,
+        Err(e) => {
+            log::error!("Failed to reorganize: {e}");
+            return 1;
+        }
+    };
+    let mut moved = 0;
+    for result in &results {
+        match &result.outcome {
+            FileOutcome::Moved(_) => moved += 1,
+            _ => log::error!("{result}"),
+        }
+    }
+    log::info!("Moved {moved} of {} files", plan.moves.len());
+
+    if moved == plan.moves.len() && plan.unresolved.is_empty() {
+        0
+    } else {
+        1
+    }
+}
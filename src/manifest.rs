@@ -0,0 +1,186 @@
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::FileManager;
+use crate::hashing::HashAlgorithm;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+/// Content hashes of archived files, keyed by their path relative to the life directory
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Content hash tagged with its algorithm, such as `blake3:af13...`
+    pub hash: String,
+    pub size: u64,
+    /// When the file was archived
+    pub added: String,
+    /// When the file last matched its hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<String>,
+}
+
+impl ManifestEntry {
+    pub fn new(algorithm: HashAlgorithm, digest: &str, size: u64) -> Self {
+        Self {
+            hash: algorithm.tag(digest),
+            size,
+            added: timestamp(),
+            verified: None,
+        }
+    }
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the manifest, starting an empty one if the file does not exist
+    pub fn load_from_file<P: AsRef<Path>, F: FileManager>(
+        path: P,
+        file_manager: &F,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        if !file_manager.is_file(path) {
+            return Ok(Self::new());
+        }
+
+        let content = file_manager.read_file(path)?;
+        serde_json::from_slice(&content)
+            .map_err(|e| CleanboxError::InvalidManifest(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Write the manifest through a temporary file so a crash never leaves it truncated
+    pub fn save<P: AsRef<Path>, F: FileManager>(&self, path: P, file_manager: &F) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| CleanboxError::InvalidManifest(e.to_string()))?;
+
+        let mut temp_path = path.as_os_str().to_os_string();
+        temp_path.push(".tmp");
+        file_manager.write_file(&temp_path, (json + "\n").as_bytes())?;
+        file_manager.rename_file(&temp_path, path)
+    }
+
+    /// Manifest key of `path`, or None if it is outside `life_path`
+    pub fn key(life_path: &Path, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(life_path).ok()?;
+        let mut parts = Vec::new();
+        for component in relative.components() {
+            match component {
+                Component::Normal(part) => parts.push(part.to_str()?),
+                _ => return None,
+            }
+        }
+        (!parts.is_empty()).then(|| parts.join("/"))
+    }
+
+    /// Absolute path of a manifest key
+    pub fn path(life_path: &Path, key: &str) -> PathBuf {
+        life_path.join(key)
+    }
+
+    pub fn insert(&mut self, key: String, entry: ManifestEntry) {
+        self.files.insert(key, entry);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&ManifestEntry> {
+        self.files.get(key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.files.contains_key(key)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<ManifestEntry> {
+        self.files.remove(key)
+    }
+
+    /// Keep the entry of a file that was moved within the archive
+    pub fn rename(&mut self, from: &str, to: String) {
+        if let Some(entry) = self.files.remove(from) {
+            self.files.insert(to, entry);
+        }
+    }
+
+    /// Record that the file matched its hash just now
+    pub fn mark_verified(&mut self, key: &str) {
+        if let Some(entry) = self.files.get_mut(key) {
+            entry.verified = Some(timestamp());
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &ManifestEntry)> {
+        self.files.iter().map(|(key, entry)| (key.as_str(), entry))
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// UTC time in a form that sorts chronologically as text
+pub(crate) fn timestamp() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::InMemoryFileManager;
+
+    #[test]
+    fn test_key() {
+        let life = Path::new("/life");
+        assert_eq!(
+            Manifest::key(life, Path::new("/life/media/2024/05/a.jpg")).as_deref(),
+            Some("media/2024/05/a.jpg")
+        );
+        assert_eq!(Manifest::key(life, Path::new("/other/a.jpg")), None);
+        assert_eq!(Manifest::key(life, Path::new("/life")), None);
+        assert_eq!(
+            Manifest::path(life, "media/2024/05/a.jpg"),
+            PathBuf::from("/life/media/2024/05/a.jpg")
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_directory("/life");
+        let path = Path::new("/life/manifest.json");
+
+        assert!(
+            Manifest::load_from_file(path, &file_manager)
+                .unwrap()
+                .is_empty()
+        );
+
+        let mut manifest = Manifest::new();
+        manifest.insert(
+            "media/a.jpg".to_string(),
+            ManifestEntry::new(HashAlgorithm::Blake3, "af13", 4),
+        );
+        manifest.mark_verified("media/a.jpg");
+        manifest.save(path, &file_manager).unwrap();
+
+        let loaded = Manifest::load_from_file(path, &file_manager).unwrap();
+        assert_eq!(loaded, manifest);
+        let entry = loaded.get("media/a.jpg").unwrap();
+        assert_eq!(entry.hash, "blake3:af13");
+        assert!(entry.verified.is_some());
+        file_manager.assert_no_file("/life/manifest.json.tmp");
+
+        file_manager.add_file(path, b"{not json");
+        let error = Manifest::load_from_file(path, &file_manager).unwrap_err();
+        assert_eq!(error.kind(), "invalid_manifest");
+    }
+}
@@ -1,7 +1,9 @@
 use crate::error::{CleanboxError, Result};
+use serde::Serialize;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FileType {
     Image,
     Video,
@@ -56,6 +58,36 @@ pub struct FileMetadata {
     pub file_type: FileType,
     pub mime_type: String,
     pub file_hash: Option<String>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    pub serial_number: Option<String>,
+    /// Milliseconds of `datetime_original` as three digits, for ordering bursts
+    pub subsec_time: Option<String>,
+    /// Identifier shared by the photo and video of a Live Photo
+    pub content_id: Option<String>,
+    /// Nearest city to where the file was captured
+    pub city: Option<String>,
+    /// Country of `city`
+    pub country: Option<String>,
+    /// Where the file was captured
+    pub gps: Option<GpsPosition>,
+}
+
+/// A position in decimal degrees, north and east positive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl GpsPosition {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
 }
 
 impl FileMetadata {
@@ -66,6 +98,15 @@ impl FileMetadata {
             file_type,
             mime_type,
             file_hash: None,
+            camera_make: None,
+            camera_model: None,
+            lens_model: None,
+            serial_number: None,
+            subsec_time: None,
+            content_id: None,
+            city: None,
+            country: None,
+            gps: None,
         }
     }
 
@@ -78,6 +119,69 @@ impl FileMetadata {
         self.file_hash = Some(hash);
         self
     }
+
+    pub fn with_camera_make(mut self, camera_make: String) -> Self {
+        self.camera_make = Some(camera_make);
+        self
+    }
+
+    pub fn with_camera_model(mut self, camera_model: String) -> Self {
+        self.camera_model = Some(camera_model);
+        self
+    }
+
+    pub fn with_lens_model(mut self, lens_model: String) -> Self {
+        self.lens_model = Some(lens_model);
+        self
+    }
+
+    pub fn with_serial_number(mut self, serial_number: String) -> Self {
+        self.serial_number = Some(serial_number);
+        self
+    }
+
+    pub fn with_subsec_time(mut self, subsec_time: String) -> Self {
+        self.subsec_time = Some(subsec_time);
+        self
+    }
+
+    /// City and country together, such as `Lisbon, Portugal`
+    pub fn place(&self) -> Option<String> {
+        match (&self.city, &self.country) {
+            (Some(city), Some(country)) => Some(format!("{city}, {country}")),
+            (city, country) => city.clone().or_else(|| country.clone()),
+        }
+    }
+
+    /// Make and model, without repeating a make the model already starts with
+    pub fn camera(&self) -> Option<String> {
+        match (&self.camera_make, &self.camera_model) {
+            (Some(make), Some(model)) => {
+                let make_word = make.split_whitespace().next().unwrap_or(make);
+                if model.to_lowercase().starts_with(&make_word.to_lowercase()) {
+                    Some(model.clone())
+                } else {
+                    Some(format!("{make} {model}"))
+                }
+            }
+            (make, model) => model.clone().or_else(|| make.clone()),
+        }
+    }
+
+    pub fn with_city(mut self, city: String) -> Self {
+        self.city = Some(city);
+        self
+    }
+
+    pub fn with_country(mut self, country: String) -> Self {
+        self.country = Some(country);
+        self
+    }
+
+    pub fn with_gps(mut self, gps: GpsPosition) -> Self {
+        self.gps = Some(gps);
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1,18 +1,135 @@
-use crate::error::{CleanboxError, Result};
-use crate::media::{FileMetadata, FileType};
+use crate::container::{
+    ExifBlock, apple_content_identifier, box_header, exif_blocks, is_movie,
+    quicktime_content_identifier, raw_mime_type,
+};
+use crate::error::{CleanboxError, IoResultExt, Result};
+use crate::filesystem::{FileManager, StdFileManager};
+use crate::hashing::{ContentHasher, HashAlgorithm};
+use crate::media::{FileMetadata, FileType, GpsPosition};
+use std::borrow::Cow;
+use std::io::{self, Read};
 use std::path::Path;
 
+/// Bytes from the start of a file handed to `MetadataParser::parse_metadata_from_bytes`.
+/// MIME signatures and JPEG EXIF segments sit well within this.
+pub const HEAD_SIZE: usize = 256 * 1024;
+/// Largest EXIF block of a HEIF, CR3 or RAF file, or TIFF prefix of a RAW file, read
+/// past the head, so a corrupt offset cannot make us read a whole video
+const MAX_EXIF_BLOCK: u64 = 16 * 1024 * 1024;
+
+// EXIF tags read by number, as rexif only names some of them
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_SUBSEC_TIME_ORIGINAL: u16 = 0x9291;
+const TAG_BODY_SERIAL_NUMBER: u16 = 0xa431;
+const TAG_LENS_MODEL: u16 = 0xa434;
+const TAG_MAKER_NOTE: u16 = 0x927c;
+
+/// Largest `moov` box of a movie read for its Live Photo identifier
+const MAX_MOVIE_BOX: u64 = 16 * 1024 * 1024;
+
 pub trait MetadataParser {
     fn parse_metadata<P: AsRef<Path>>(&self, file_path: P) -> Result<FileMetadata>;
     fn extract_datetime<P: AsRef<Path>>(&self, file_path: P) -> Result<String>;
     fn supports_file_type(&self, file_type: &FileType) -> bool;
+
+    /// Parse metadata from bytes already read from the start of `file_path`, up to
+    /// `HEAD_SIZE` of them; `complete` is true when they are the whole file.
+    ///
+    /// The default ignores the bytes and parses the path.
+    fn parse_metadata_from_bytes<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        _head: &[u8],
+        _complete: bool,
+    ) -> Result<FileMetadata> {
+        self.parse_metadata(file_path)
+    }
 }
 
-pub struct RexifParser;
+impl<T: MetadataParser + ?Sized> MetadataParser for &T {
+    fn parse_metadata<P: AsRef<Path>>(&self, file_path: P) -> Result<FileMetadata> {
+        (**self).parse_metadata(file_path)
+    }
+
+    fn extract_datetime<P: AsRef<Path>>(&self, file_path: P) -> Result<String> {
+        (**self).extract_datetime(file_path)
+    }
+
+    fn supports_file_type(&self, file_type: &FileType) -> bool {
+        (**self).supports_file_type(file_type)
+    }
+
+    fn parse_metadata_from_bytes<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        head: &[u8],
+        complete: bool,
+    ) -> Result<FileMetadata> {
+        (**self).parse_metadata_from_bytes(file_path, head, complete)
+    }
+}
+
+/// Read up to `HEAD_SIZE` bytes, fewer only at the end of the stream
+fn read_head<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(HEAD_SIZE);
+    reader.take(HEAD_SIZE as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Read metadata and, if `should_hash` accepts it, the `algorithm` hash in one pass.
+///
+/// MIME sniffing and EXIF parsing work on the first `HEAD_SIZE` bytes, which are
+/// also the first bytes hashed; the rest of the stream is only read when hashing.
+/// A read error while hashing leaves the metadata without a hash.
+pub fn read_metadata<E, R, H>(
+    metadata_parser: &E,
+    file_path: &Path,
+    mut reader: R,
+    algorithm: HashAlgorithm,
+    should_hash: H,
+) -> Result<FileMetadata>
+where
+    E: MetadataParser + ?Sized,
+    R: Read,
+    H: FnOnce(&FileMetadata) -> bool,
+{
+    let head = read_head(&mut reader).at_path(file_path)?;
+    let complete = head.len() < HEAD_SIZE;
+    let metadata = metadata_parser.parse_metadata_from_bytes(file_path, &head, complete)?;
+
+    if !should_hash(&metadata) {
+        return Ok(metadata);
+    }
+
+    let mut hasher = ContentHasher::new(algorithm);
+    hasher.update(&head);
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                log::debug!("Cannot hash {}: {}", file_path.display(), e);
+                return Ok(metadata);
+            }
+        };
+        hasher.update(&buffer[..n]);
+    }
+
+    Ok(metadata.with_hash(hasher.finish()))
+}
+
+/// Parser for MIME types and EXIF, reading bytes past the head through `F`
+pub struct RexifParser<F: FileManager = StdFileManager> {
+    file_manager: F,
+}
 
 impl RexifParser {
     pub fn new() -> Self {
-        Self
+        Self {
+            file_manager: StdFileManager::new(),
+        }
     }
 
     fn format_datetime(raw_datetime: &str) -> Result<String> {
@@ -27,83 +144,351 @@ impl RexifParser {
         let time = parts[1].replace(":", "-");
         Ok(format!("{date}_{time}"))
     }
-}
 
-impl Default for RexifParser {
-    fn default() -> Self {
-        Self::new()
+    /// Basic detection based on extension, for content `infer` does not recognize
+    fn mime_from_extension(path: &Path) -> String {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("txt") => "text/plain".to_string(),
+            Some("pdf") => "application/pdf".to_string(),
+            Some("doc") => "application/msword".to_string(),
+            Some("docx") => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+                    .to_string()
+            }
+            Some("zip") => "application/zip".to_string(),
+            Some("json") => "application/json".to_string(),
+            Some("csv") => "text/csv".to_string(),
+            Some("xml") => "application/xml".to_string(),
+            Some(_) => "application/octet-stream".to_string(), // Default for unknown extensions
+            None => "".to_string(), // No extension - will be classified as Unknown
+        }
     }
-}
 
-impl MetadataParser for RexifParser {
-    fn parse_metadata<P: AsRef<Path>>(&self, file_path: P) -> Result<FileMetadata> {
-        // First, detect MIME type using infer
-        let mime_type =
-            if let Some(kind) = infer::get_from_path(&file_path).map_err(CleanboxError::Io)? {
-                kind.mime_type().to_string()
-            } else {
-                // Fallback to basic detection based on extension
-                let path = file_path.as_ref();
-                match path.extension().and_then(|ext| ext.to_str()) {
-                    Some("txt") => "text/plain".to_string(),
-                    Some("pdf") => "application/pdf".to_string(),
-                    Some("doc") => "application/msword".to_string(),
-                    Some("docx") => {
-                        "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
-                            .to_string()
-                    }
-                    Some("zip") => "application/zip".to_string(),
-                    Some("json") => "application/json".to_string(),
-                    Some("csv") => "text/csv".to_string(),
-                    Some("xml") => "application/xml".to_string(),
-                    Some(_) => "application/octet-stream".to_string(), // Default for unknown extensions
-                    None => "".to_string(), // No extension - will be classified as Unknown
-                }
-            };
+    fn datetime_from_exif(exif: &rexif::ExifData) -> Result<String> {
+        for entry in &exif.entries {
+            if entry.tag == rexif::ExifTag::DateTimeOriginal {
+                return Self::format_datetime(&entry.value_more_readable);
+            }
+        }
 
-        // Create metadata with detected MIME type
-        let mut metadata = FileMetadata::new(mime_type.clone());
+        Err(CleanboxError::Exif(
+            "DateTimeOriginal tag not found".to_string(),
+        ))
+    }
 
-        // Only attempt EXIF parsing for image/video files
-        if mime_type.starts_with("image/") || mime_type.starts_with("video/") {
-            // Try to extract datetime from EXIF data
-            if let Ok(datetime) = self.extract_datetime(&file_path) {
-                metadata = metadata.with_datetime(datetime);
-            }
+    /// Text of an ASCII entry, read from the raw data so tags rexif does not know work too
+    fn ascii_value(entry: &rexif::ExifEntry) -> Option<String> {
+        if entry.ifd.format != rexif::IfdFormat::Ascii {
+            return None;
         }
+        let text = String::from_utf8_lossy(&entry.ifd.data);
+        let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        (!text.is_empty()).then(|| text.to_string())
+    }
 
-        Ok(metadata)
+    /// Fractional seconds as milliseconds, so `5` (half a second) becomes `500`
+    fn format_subsec(raw_subsec: &str) -> Option<String> {
+        let digits: String = raw_subsec.trim().chars().take(3).collect();
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Some(format!("{digits:0<3}"))
     }
 
-    fn extract_datetime<P: AsRef<Path>>(&self, file_path: P) -> Result<String> {
-        let path_str = file_path
-            .as_ref()
-            .to_str()
-            .ok_or_else(|| CleanboxError::InvalidPath(file_path.as_ref().display().to_string()))?;
+    /// Copy the capture time and camera details from EXIF into `metadata`
+    fn apply_exif(mut metadata: FileMetadata, exif: &rexif::ExifData) -> FileMetadata {
+        if let Ok(datetime) = Self::datetime_from_exif(exif) {
+            metadata.datetime_original = Some(datetime);
+        }
 
-        let exif = rexif::parse_file(path_str)?;
+        metadata.gps = Self::gps_from_exif(exif);
 
         for entry in &exif.entries {
-            if entry.tag == rexif::ExifTag::DateTimeOriginal {
-                return Self::format_datetime(&entry.value_more_readable);
+            if entry.namespace != rexif::Namespace::Standard {
+                continue;
+            }
+            if entry.ifd.tag == TAG_MAKER_NOTE {
+                metadata.content_id = apple_content_identifier(&entry.ifd.data);
+                continue;
+            }
+            let Some(value) = Self::ascii_value(entry) else {
+                continue;
+            };
+            match entry.ifd.tag {
+                TAG_MAKE => metadata.camera_make = Some(value),
+                TAG_MODEL => metadata.camera_model = Some(value),
+                TAG_LENS_MODEL => metadata.lens_model = Some(value),
+                TAG_BODY_SERIAL_NUMBER => metadata.serial_number = Some(value),
+                TAG_SUBSEC_TIME_ORIGINAL => metadata.subsec_time = Self::format_subsec(&value),
+                _ => {}
             }
         }
+        metadata
+    }
 
-        Err(CleanboxError::Exif(
-            "DateTimeOriginal tag not found".to_string(),
+    /// Position from the GPS sub-IFD, which is absent or zeroed on files without a fix
+    fn gps_from_exif(exif: &rexif::ExifData) -> Option<GpsPosition> {
+        let entry = |tag: rexif::ExifTag| exif.entries.iter().find(|entry| entry.tag == tag);
+        let reference = |tag| match &entry(tag)?.value {
+            rexif::TagValue::Ascii(reference) => Some(reference.trim().to_string()),
+            _ => None,
+        };
+        let latitude = Self::gps_coordinate(
+            &entry(rexif::ExifTag::GPSLatitude)?.value,
+            reference(rexif::ExifTag::GPSLatitudeRef).as_deref(),
+        )?;
+        let longitude = Self::gps_coordinate(
+            &entry(rexif::ExifTag::GPSLongitude)?.value,
+            reference(rexif::ExifTag::GPSLongitudeRef).as_deref(),
+        )?;
+        (latitude != 0.0 || longitude != 0.0).then(|| GpsPosition::new(latitude, longitude))
+    }
+
+    /// Decimal degrees from degrees, minutes and seconds, negated for a `S` or `W` reference
+    fn gps_coordinate(value: &rexif::TagValue, reference: Option<&str>) -> Option<f64> {
+        let rexif::TagValue::URational(parts) = value else {
+            return None;
+        };
+        let [degrees, minutes, seconds] = parts.as_slice() else {
+            return None;
+        };
+        if [degrees, minutes, seconds]
+            .iter()
+            .any(|part| part.denominator == 0)
+        {
+            return None;
+        }
+        let magnitude = degrees.value() + minutes.value() / 60.0 + seconds.value() / 3600.0;
+        match reference {
+            Some("S" | "W") => Some(-magnitude),
+            _ => Some(magnitude),
+        }
+    }
+
+    fn is_tiff(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*")
+    }
+
+    fn exif_from_bytes(bytes: &[u8]) -> Result<rexif::ExifData> {
+        let (exif, warnings) = rexif::parse_buffer_quiet(bytes);
+        for warning in warnings {
+            log::trace!("EXIF: {warning}");
+        }
+        Ok(exif?)
+    }
+}
+
+impl<F: FileManager> RexifParser<F> {
+    pub fn with_file_manager<G: FileManager>(self, file_manager: G) -> RexifParser<G> {
+        RexifParser { file_manager }
+    }
+
+    /// EXIF gathered from the blocks of a HEIF, AVIF, CR3 or RAF file
+    fn exif_from_blocks(
+        &self,
+        file_path: &Path,
+        head: &[u8],
+        complete: bool,
+        blocks: &[ExifBlock],
+    ) -> Result<rexif::ExifData> {
+        let mut entries = Vec::new();
+        let mut last_error = None;
+        for block in blocks {
+            let exif = self
+                .read_block(file_path, head, complete, block)
+                .and_then(|bytes| {
+                    let payload = block.payload(&bytes).ok_or_else(|| {
+                        CleanboxError::Exif(format!(
+                            "Truncated EXIF item in {}",
+                            file_path.display()
+                        ))
+                    })?;
+                    RexifParser::exif_from_bytes(payload)
+                });
+            match exif {
+                Ok(exif) => entries.extend(exif.entries),
+                Err(e) => {
+                    log::trace!("EXIF block of {}: {}", file_path.display(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) if entries.is_empty() => Err(e),
+            _ => Ok(rexif::ExifData {
+                mime: "",
+                entries,
+                le: false,
+            }),
+        }
+    }
+
+    /// The bytes of `block`, from the head when they lie within it
+    fn read_block<'a>(
+        &self,
+        file_path: &Path,
+        head: &'a [u8],
+        complete: bool,
+        block: &ExifBlock,
+    ) -> Result<Cow<'a, [u8]>> {
+        let bytes = if block.length <= MAX_EXIF_BLOCK {
+            self.read_bytes(file_path, head, complete, block.offset, block.length)?
+        } else {
+            Cow::Borrowed(&[][..])
+        };
+        if bytes.len() as u64 != block.length {
+            return Err(CleanboxError::Exif(format!(
+                "EXIF block at {} is past the end of {}",
+                block.offset,
+                file_path.display()
+            )));
+        }
+        Ok(bytes)
+    }
+
+    /// Up to `length` bytes from `offset`, from the head when they lie within it;
+    /// fewer at the end of the file
+    fn read_bytes<'a>(
+        &self,
+        file_path: &Path,
+        head: &'a [u8],
+        complete: bool,
+        offset: u64,
+        length: u64,
+    ) -> Result<Cow<'a, [u8]>> {
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        let end = usize::try_from(length)
+            .ok()
+            .and_then(|length| start.checked_add(length))
+            .unwrap_or(usize::MAX);
+        if end <= head.len() || complete {
+            let start = start.min(head.len());
+            return Ok(Cow::Borrowed(&head[start..end.min(head.len())]));
+        }
+        Ok(Cow::Owned(
+            self.file_manager.read_range(file_path, offset, length)?,
         ))
     }
 
+    /// Live Photo identifier of a QuickTime movie, from the `moov` box found by
+    /// walking the top-level boxes, which may lie past the head
+    fn movie_content_identifier(
+        &self,
+        file_path: &Path,
+        head: &[u8],
+        complete: bool,
+    ) -> Option<String> {
+        let mut offset = 0u64;
+        loop {
+            // 16 bytes hold the largest box header
+            let bytes = self
+                .read_bytes(file_path, head, complete, offset, 16)
+                .ok()?;
+            let header = box_header(&bytes)?;
+            let size = header.size?;
+            if &header.kind == b"moov" {
+                let length = size - header.length;
+                if length > MAX_MOVIE_BOX {
+                    return None;
+                }
+                let moov = self
+                    .read_bytes(file_path, head, complete, offset + header.length, length)
+                    .ok()?;
+                return quicktime_content_identifier(&moov);
+            }
+            offset = offset.checked_add(size)?;
+        }
+    }
+
+    /// EXIF of a TIFF-based RAW file whose tags point past the head, parsed from
+    /// the head extended to at most `MAX_EXIF_BLOCK` bytes
+    fn exif_from_tiff_prefix(&self, file_path: &Path, head: &[u8]) -> Result<rexif::ExifData> {
+        let rest = self.file_manager.read_range(
+            file_path,
+            head.len() as u64,
+            MAX_EXIF_BLOCK.saturating_sub(head.len() as u64),
+        )?;
+        RexifParser::exif_from_bytes(&[head, &rest].concat())
+    }
+}
+
+impl Default for RexifParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: FileManager> MetadataParser for RexifParser<F> {
+    fn parse_metadata<P: AsRef<Path>>(&self, file_path: P) -> Result<FileMetadata> {
+        let file_path = file_path.as_ref();
+        let mut file = self.file_manager.open_file(file_path)?;
+        let head = read_head(&mut file).at_path(file_path)?;
+        let complete = head.len() < HEAD_SIZE;
+        self.parse_metadata_from_bytes(file_path, &head, complete)
+    }
+
+    fn extract_datetime<P: AsRef<Path>>(&self, file_path: P) -> Result<String> {
+        self.parse_metadata(file_path)?
+            .datetime_original
+            .ok_or_else(|| CleanboxError::Exif("DateTimeOriginal tag not found".to_string()))
+    }
+
     fn supports_file_type(&self, file_type: &FileType) -> bool {
         matches!(file_type, FileType::Image | FileType::Video)
     }
+
+    fn parse_metadata_from_bytes<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        head: &[u8],
+        complete: bool,
+    ) -> Result<FileMetadata> {
+        // First, detect MIME type, checking for RAW formats infer does not know
+        let mime_type = match (raw_mime_type(head), infer::get(head)) {
+            (Some(mime_type), _) => mime_type.to_string(),
+            (None, Some(kind)) => kind.mime_type().to_string(),
+            (None, None) => RexifParser::mime_from_extension(file_path.as_ref()),
+        };
+
+        // Create metadata with detected MIME type
+        let mut metadata = FileMetadata::new(mime_type.clone());
+
+        // Only attempt EXIF parsing for image/video files
+        if mime_type.starts_with("image/") || mime_type.starts_with("video/") {
+            // EXIF is usually near the start; TIFF-based RAW files can point past the head,
+            // and HEIF, AVIF, CR3 and RAF files keep it in blocks of their own
+            let blocks = exif_blocks(head);
+            let exif = match RexifParser::exif_from_bytes(head) {
+                Ok(exif) if RexifParser::datetime_from_exif(&exif).is_ok() => Ok(exif),
+                _ if !blocks.is_empty() => {
+                    self.exif_from_blocks(file_path.as_ref(), head, complete, &blocks)
+                }
+                _ if !complete && RexifParser::is_tiff(head) => {
+                    self.exif_from_tiff_prefix(file_path.as_ref(), head)
+                }
+                exif => exif,
+            };
+            if let Ok(exif) = exif {
+                metadata = RexifParser::apply_exif(metadata, &exif);
+            }
+            if mime_type.starts_with("video/") && is_movie(head) {
+                metadata.content_id =
+                    self.movie_content_identifier(file_path.as_ref(), head, complete);
+            }
+        }
+
+        Ok(metadata)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::media::FileType;
+    use crate::testing::InMemoryFileManager;
     use std::io::Write;
+    use std::path::PathBuf;
     use tempfile::NamedTempFile;
 
     #[test]
@@ -231,6 +616,355 @@ mod tests {
         assert!(result.datetime_original.is_none()); // Documents don't have EXIF datetime
     }
 
+    /// Reader that counts how many bytes were read from it
+    struct CountingReader<R> {
+        inner: R,
+        read: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read += n;
+            Ok(n)
+        }
+    }
+
+    fn large_jpeg() -> Vec<u8> {
+        let mut contents = vec![0xFF, 0xD8, 0xFF, 0xE0];
+        contents.extend((0..HEAD_SIZE * 2).map(|i| (i % 251) as u8));
+        contents
+    }
+
+    #[test]
+    fn test_read_metadata_hashes_in_one_pass() {
+        let contents = large_jpeg();
+        let mut reader = CountingReader {
+            inner: io::Cursor::new(contents.clone()),
+            read: 0,
+        };
+
+        let metadata = read_metadata(
+            &RexifParser::new(),
+            Path::new("/nonexistent/IMG_0001.jpg"),
+            &mut reader,
+            HashAlgorithm::Blake3,
+            |metadata| metadata.file_type == FileType::Image,
+        )
+        .unwrap();
+
+        assert_eq!(metadata.file_type, FileType::Image);
+        assert_eq!(
+            metadata.file_hash,
+            Some(ContentHasher::hash_bytes(HashAlgorithm::Blake3, &contents))
+        );
+        assert_eq!(reader.read, contents.len());
+    }
+
+    #[test]
+    fn test_read_metadata_without_hash_reads_only_the_head() {
+        let mut reader = CountingReader {
+            inner: io::Cursor::new(large_jpeg()),
+            read: 0,
+        };
+
+        let metadata = read_metadata(
+            &RexifParser::new(),
+            Path::new("/nonexistent/IMG_0001.jpg"),
+            &mut reader,
+            HashAlgorithm::Sha1,
+            |_| false,
+        )
+        .unwrap();
+
+        assert!(metadata.file_hash.is_none());
+        assert_eq!(reader.read, HEAD_SIZE);
+    }
+
+    #[test]
+    fn test_parse_metadata_from_bytes_does_not_open_the_file() {
+        let parser = RexifParser::new();
+        let result = parser
+            .parse_metadata_from_bytes("/nonexistent/statement.pdf", b"%PDF-1.4\n", true)
+            .unwrap();
+
+        assert_eq!(result.file_type, FileType::Document);
+        assert_eq!(result.mime_type, "application/pdf");
+    }
+
+    /// Little-endian TIFF with ASCII entries in IFD0 and in an EXIF sub-IFD
+    fn tiff_with_exif(ifd0: &[(u16, &str)], exif: &[(u16, &str)]) -> Vec<u8> {
+        let ifd_size = |entries: usize| 2 + entries * 12 + 4;
+        let exif_offset = 8 + ifd_size(ifd0.len() + 1);
+        let mut data_offset = exif_offset + ifd_size(exif.len());
+        let mut data = Vec::new();
+
+        let mut ifd = |entries: &[(u16, &str)], exif_pointer: Option<usize>| {
+            let mut bytes = Vec::new();
+            let count = entries.len() + usize::from(exif_pointer.is_some());
+            bytes.extend((count as u16).to_le_bytes());
+            for (tag, value) in entries {
+                let mut value = value.as_bytes().to_vec();
+                value.push(0);
+                bytes.extend(tag.to_le_bytes());
+                bytes.extend(2u16.to_le_bytes());
+                bytes.extend((value.len() as u32).to_le_bytes());
+                if value.len() <= 4 {
+                    value.resize(4, 0);
+                    bytes.extend(value);
+                } else {
+                    bytes.extend((data_offset as u32).to_le_bytes());
+                    data_offset += value.len();
+                    data.extend(value);
+                }
+            }
+            if let Some(offset) = exif_pointer {
+                bytes.extend(0x8769u16.to_le_bytes());
+                bytes.extend(4u16.to_le_bytes());
+                bytes.extend(1u32.to_le_bytes());
+                bytes.extend((offset as u32).to_le_bytes());
+            }
+            bytes.extend(0u32.to_le_bytes());
+            bytes
+        };
+
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend(ifd(ifd0, Some(exif_offset)));
+        tiff.extend(ifd(exif, None));
+        tiff.extend(data);
+        tiff
+    }
+
+    #[test]
+    fn test_parse_camera_details() {
+        let tiff = tiff_with_exif(
+            &[(TAG_MAKE, "Canon"), (TAG_MODEL, "Canon EOS R5")],
+            &[
+                (0x9003, "2024:05:01 10:00:00"),
+                (TAG_SUBSEC_TIME_ORIGINAL, "5"),
+                (TAG_BODY_SERIAL_NUMBER, "012345678901"),
+                (TAG_LENS_MODEL, "RF24-105mm F4 L IS USM"),
+            ],
+        );
+
+        let metadata = RexifParser::new()
+            .parse_metadata_from_bytes("/inbox/IMG_0001.tif", &tiff, true)
+            .unwrap();
+
+        assert_eq!(
+            metadata.datetime_original.as_deref(),
+            Some("2024-05-01_10-00-00")
+        );
+        assert_eq!(metadata.camera_make.as_deref(), Some("Canon"));
+        assert_eq!(metadata.camera().as_deref(), Some("Canon EOS R5"));
+        assert_eq!(
+            metadata.lens_model.as_deref(),
+            Some("RF24-105mm F4 L IS USM")
+        );
+        assert_eq!(metadata.serial_number.as_deref(), Some("012345678901"));
+        assert_eq!(metadata.subsec_time.as_deref(), Some("500"));
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_parse_heif_and_raw_fixtures() {
+        let parser = RexifParser::new();
+        let parse = |name: &str| parser.parse_metadata(fixture(name)).unwrap();
+
+        let heic = parse("IMG_0001.HEIC");
+        assert_eq!(heic.mime_type, "image/heif");
+        assert_eq!(heic.file_type, FileType::Image);
+        assert_eq!(
+            heic.datetime_original.as_deref(),
+            Some("2024-06-29_14-03-27")
+        );
+        assert_eq!(heic.camera().as_deref(), Some("Apple iPhone 15 Pro"));
+        assert_eq!(heic.subsec_time.as_deref(), Some("582"));
+        let gps = heic.gps.unwrap();
+        assert!((gps.latitude - 38.7087).abs() < 1e-3);
+        assert!((gps.longitude + 9.1398).abs() < 1e-3);
+
+        let avif = parse("photo.avif");
+        assert_eq!(avif.mime_type, "image/avif");
+        assert_eq!(
+            avif.datetime_original.as_deref(),
+            Some("2024-05-12_08-30-00")
+        );
+
+        let cr3 = parse("IMG_0002.CR3");
+        assert_eq!(cr3.mime_type, "image/x-canon-cr3");
+        assert_eq!(cr3.file_type, FileType::Image);
+        assert_eq!(
+            cr3.datetime_original.as_deref(),
+            Some("2024-06-29_18-45-12")
+        );
+        assert_eq!(cr3.camera().as_deref(), Some("Canon EOS R6"));
+        assert_eq!(cr3.lens_model.as_deref(), Some("RF24-105mm F4 L IS USM"));
+        assert_eq!(cr3.subsec_time.as_deref(), Some("370"));
+        assert!(cr3.gps.unwrap().longitude < 0.0);
+
+        let raf = parse("DSCF0001.RAF");
+        assert_eq!(raf.mime_type, "image/x-fuji-raf");
+        assert_eq!(
+            raf.datetime_original.as_deref(),
+            Some("2024-07-02_09-15-00")
+        );
+        assert_eq!(raf.camera().as_deref(), Some("FUJIFILM X-T5"));
+
+        let dng = parse("DSC_0001.DNG");
+        assert_eq!(
+            dng.datetime_original.as_deref(),
+            Some("2024-03-17_11-22-33")
+        );
+        assert_eq!(
+            parser.extract_datetime(fixture("IMG_0001.HEIC")).unwrap(),
+            "2024-06-29_14-03-27"
+        );
+    }
+
+    #[test]
+    fn test_exif_item_past_the_head_is_read_from_the_file() {
+        let contents = std::fs::read(fixture("IMG_0001.HEIC")).unwrap();
+        let path = Path::new("/inbox/IMG_0001.HEIC");
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file(path, &contents);
+        // The head ends inside the coded image, before the EXIF item
+        let head = &contents[..contents.len() / 2];
+        let parser = RexifParser::new().with_file_manager(file_manager);
+
+        let metadata = parser.parse_metadata_from_bytes(path, head, false).unwrap();
+        assert_eq!(
+            metadata.datetime_original.as_deref(),
+            Some("2024-06-29_14-03-27")
+        );
+
+        // A head that is the whole file has nothing more to read
+        let metadata = parser.parse_metadata_from_bytes(path, head, true).unwrap();
+        assert!(metadata.datetime_original.is_none());
+    }
+
+    #[test]
+    fn test_tiff_tags_past_the_head_extend_the_head() {
+        let tiff = tiff_with_exif(
+            &[(TAG_MAKE, "Nikon"), (TAG_MODEL, "NIKON Z 6")],
+            &[(0x9003, "2024:03:17 11:22:33")],
+        );
+        let path = Path::new("/inbox/DSC_0001.NEF");
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file(path, &tiff);
+        // The head ends before the tag values
+        let head = &tiff[..tiff.len() - 12];
+        let parser = RexifParser::new().with_file_manager(file_manager);
+
+        let metadata = parser.parse_metadata_from_bytes(path, head, false).unwrap();
+
+        assert_eq!(
+            metadata.datetime_original.as_deref(),
+            Some("2024-03-17_11-22-33")
+        );
+        assert_eq!(metadata.camera().as_deref(), Some("NIKON Z 6"));
+
+        let metadata = parser.parse_metadata_from_bytes(path, head, true).unwrap();
+        assert!(metadata.datetime_original.is_none());
+    }
+
+    #[test]
+    fn test_gps_coordinate() {
+        let dms = |d, m, s| {
+            rexif::TagValue::URational(vec![
+                rexif::URational {
+                    numerator: d,
+                    denominator: 1,
+                },
+                rexif::URational {
+                    numerator: m,
+                    denominator: 1,
+                },
+                rexif::URational {
+                    numerator: s,
+                    denominator: 100,
+                },
+            ])
+        };
+
+        let latitude = RexifParser::gps_coordinate(&dms(38, 42, 3000), Some("N")).unwrap();
+        assert!((latitude - 38.708_333).abs() < 1e-6);
+        let longitude = RexifParser::gps_coordinate(&dms(9, 8, 0), Some("W")).unwrap();
+        assert!((longitude + 9.133_333).abs() < 1e-6);
+
+        let no_fix = rexif::TagValue::URational(vec![
+            rexif::URational {
+                numerator: 0,
+                denominator: 0,
+            };
+            3
+        ]);
+        assert_eq!(RexifParser::gps_coordinate(&no_fix, Some("N")), None);
+    }
+
+    fn iso_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut bytes = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        bytes.extend(kind);
+        bytes.extend(payload);
+        bytes
+    }
+
+    #[test]
+    fn test_content_identifier_in_a_trailing_moov() {
+        let uuid = "4f0b8c1e-2d3a-4b5c-9e8f-0a1b2c3d4e5f";
+        let key = b"com.apple.quicktime.content.identifier";
+        let mut keys = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        keys.extend(((key.len() + 8) as u32).to_be_bytes());
+        keys.extend(b"mdta");
+        keys.extend(key);
+        let mut data = 1u32.to_be_bytes().to_vec();
+        data.extend([0; 4]);
+        data.extend(uuid.as_bytes());
+        let ilst = iso_box(&1u32.to_be_bytes(), &iso_box(b"data", &data));
+        let meta = [iso_box(b"keys", &keys), iso_box(b"ilst", &ilst)].concat();
+        // The key also appears in the coded video, where it is no identifier
+        let mut mdat = vec![0u8; HEAD_SIZE * 2];
+        mdat[1000..1000 + key.len()].copy_from_slice(key);
+        let movie = [
+            iso_box(b"ftyp", b"qt  \0\0\0\0qt  "),
+            iso_box(b"mdat", &mdat),
+            iso_box(b"moov", &iso_box(b"meta", &meta)),
+        ]
+        .concat();
+
+        let path = Path::new("/inbox/IMG_0001.MOV");
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file(path, &movie);
+        let parser = RexifParser::new().with_file_manager(file_manager);
+
+        let metadata = parser
+            .parse_metadata_from_bytes(path, &movie[..HEAD_SIZE], false)
+            .unwrap();
+        assert_eq!(metadata.mime_type, "video/quicktime");
+        assert_eq!(metadata.content_id, Some(uuid.to_uppercase()));
+
+        // Without the moov box there is no identifier
+        let truncated = &movie[..movie.len() - 200];
+        let metadata = parser
+            .parse_metadata_from_bytes(path, truncated, true)
+            .unwrap();
+        assert_eq!(metadata.content_id, None);
+    }
+
+    #[test]
+    fn test_format_subsec() {
+        assert_eq!(RexifParser::format_subsec("12").as_deref(), Some("120"));
+        assert_eq!(RexifParser::format_subsec("04567").as_deref(), Some("045"));
+        assert_eq!(RexifParser::format_subsec(" "), None);
+        assert_eq!(RexifParser::format_subsec("ab"), None);
+    }
+
     #[test]
     fn test_format_datetime_unchanged() {
         let result = RexifParser::format_datetime("2023:07:15 14:30:25").unwrap();
@@ -1,6 +1,11 @@
 use crate::document::DocumentInput;
 use crate::error::{CleanboxError, Result};
 use crate::media::File;
+use crate::template::Template;
+use chrono::NaiveDateTime;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub(crate) const DATETIME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
 
 pub trait NamingStrategy {
     fn generate_name(&self, file: &File) -> Result<String>;
@@ -12,6 +17,27 @@ impl TimestampNamingStrategy {
     pub fn new() -> Self {
         Self
     }
+
+    /// Datetime of a name this strategy generates, allowing a duplicate hash suffix
+    pub fn parse_name(name: &str) -> Option<String> {
+        let (stem, extension) = name.rsplit_once('.')?;
+        if extension.is_empty() {
+            return None;
+        }
+
+        let datetime = stem.get(..19)?;
+        let suffix = &stem[19..];
+        if !suffix.is_empty() {
+            let hash = suffix.strip_prefix('_')?;
+            if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+        }
+
+        // Round-trip to reject unpadded fields that chrono would accept
+        let parsed = NaiveDateTime::parse_from_str(datetime, DATETIME_FORMAT).ok()?;
+        (parsed.format(DATETIME_FORMAT).to_string() == datetime).then(|| datetime.to_string())
+    }
 }
 
 impl Default for TimestampNamingStrategy {
@@ -34,62 +60,33 @@ impl NamingStrategy for TimestampNamingStrategy {
     }
 }
 
+/// Names files from a `Template` such as `{datetime:%Y%m%d}_{camera|unknown}.{ext}`
 pub struct CustomNamingStrategy {
-    pattern: String,
+    template: Template,
+    counter: AtomicU64,
 }
 
 impl CustomNamingStrategy {
-    pub fn new(pattern: String) -> Self {
-        Self { pattern }
-    }
-
-    fn replace_placeholders(&self, file: &File) -> Result<String> {
-        let mut result = self.pattern.clone();
-
-        if let Some(metadata) = &file.metadata {
-            if let Some(datetime) = &metadata.datetime_original {
-                result = result.replace("{datetime}", datetime);
-
-                let parts: Vec<&str> = datetime.split('_').collect();
-                if parts.len() == 2 {
-                    let date_parts: Vec<&str> = parts[0].split('-').collect();
-                    if date_parts.len() == 3 {
-                        result = result.replace("{year}", date_parts[0]);
-                        result = result.replace("{month}", date_parts[1]);
-                        result = result.replace("{day}", date_parts[2]);
-                    }
-
-                    let time_parts: Vec<&str> = parts[1].split('-').collect();
-                    if time_parts.len() == 3 {
-                        result = result.replace("{hour}", time_parts[0]);
-                        result = result.replace("{minute}", time_parts[1]);
-                        result = result.replace("{second}", time_parts[2]);
-                    }
-                }
-            }
-
-            if let Some(hash) = &metadata.file_hash {
-                result = result.replace("{hash}", hash);
-                result = result.replace("{hash6}", &hash[..std::cmp::min(6, hash.len())]);
-            }
-        }
-
-        let original_name = file.file_name()?;
-        result = result.replace("{original}", original_name);
-
-        let stem = file.file_stem()?;
-        result = result.replace("{stem}", stem);
-
-        let extension = file.extension()?;
-        result = result.replace("{ext}", extension);
-
-        Ok(result)
+    /// Parse the template, failing on unknown placeholders or bad formats
+    pub fn new(pattern: String) -> Result<Self> {
+        Ok(Self {
+            template: Template::parse(&pattern)?,
+            counter: AtomicU64::new(0),
+        })
     }
 }
 
 impl NamingStrategy for CustomNamingStrategy {
     fn generate_name(&self, file: &File) -> Result<String> {
-        self.replace_placeholders(file)
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let name = self.template.render(file, counter)?;
+        if name.contains(['/', '\\']) {
+            return Err(CleanboxError::InvalidTemplate(format!(
+                "{}: name {name} contains a path separator",
+                self.template
+            )));
+        }
+        Ok(name)
     }
 }
 
@@ -123,6 +120,27 @@ impl DocumentNamingStrategy {
 
         Ok(filename)
     }
+
+    /// Recover and validate the input from a name made by `generate_name_from_input`
+    pub fn parse_name(name: &str) -> Result<DocumentInput> {
+        let not_a_document_name = || {
+            CleanboxError::InvalidUserInput(format!(
+                "Not a YYYY-MM-DD_description@@tags name: {name}"
+            ))
+        };
+
+        let (stem, _extension) = name.rsplit_once('.').ok_or_else(not_a_document_name)?;
+        let (date, rest) = stem.split_once('_').ok_or_else(not_a_document_name)?;
+        let (description, tags) = rest.split_once("@@").ok_or_else(not_a_document_name)?;
+        let tags = match tags {
+            "" => Vec::new(),
+            tags => tags.split(',').map(str::to_string).collect(),
+        };
+
+        let input = DocumentInput::new(date.to_string(), description.to_string(), tags);
+        input.validate()?;
+        Ok(input)
+    }
 }
 
 impl Default for DocumentNamingStrategy {
@@ -181,7 +199,7 @@ mod tests {
 
     #[test]
     fn test_custom_naming_strategy_datetime_replacement() {
-        let strategy = CustomNamingStrategy::new("{datetime}.{ext}".to_string());
+        let strategy = CustomNamingStrategy::new("{datetime}.{ext}".to_string()).unwrap();
         let file = create_test_file_with_datetime("2023-12-01_14-30-00");
 
         let result = strategy.generate_name(&file).unwrap();
@@ -192,7 +210,8 @@ mod tests {
     fn test_custom_naming_strategy_date_parts() {
         let strategy = CustomNamingStrategy::new(
             "{year}-{month}-{day}_{hour}{minute}{second}.{ext}".to_string(),
-        );
+        )
+        .unwrap();
         let file = create_test_file_with_datetime("2023-12-01_14-30-00");
 
         let result = strategy.generate_name(&file).unwrap();
@@ -201,7 +220,7 @@ mod tests {
 
     #[test]
     fn test_custom_naming_strategy_with_hash() {
-        let strategy = CustomNamingStrategy::new("{datetime}_{hash6}.{ext}".to_string());
+        let strategy = CustomNamingStrategy::new("{datetime}_{hash6}.{ext}".to_string()).unwrap();
         let path = PathBuf::from("/test/image.jpg");
         let metadata = FileMetadata::new("image/jpeg".to_string())
             .with_datetime("2023-12-01_14-30-00".to_string())
@@ -214,7 +233,7 @@ mod tests {
 
     #[test]
     fn test_custom_naming_strategy_original_and_stem() {
-        let strategy = CustomNamingStrategy::new("{stem}_processed.{ext}".to_string());
+        let strategy = CustomNamingStrategy::new("{stem}_processed.{ext}".to_string()).unwrap();
         let path = PathBuf::from("/test/my_image.jpg");
         let metadata = FileMetadata::new("image/jpeg".to_string());
         let file = File::new(&path).with_metadata(metadata);
@@ -225,7 +244,7 @@ mod tests {
 
     #[test]
     fn test_custom_naming_strategy_short_hash() {
-        let strategy = CustomNamingStrategy::new("{hash6}.{ext}".to_string());
+        let strategy = CustomNamingStrategy::new("{hash6}.{ext}".to_string()).unwrap();
         let path = PathBuf::from("/test/image.jpg");
         let metadata = FileMetadata::new("image/jpeg".to_string()).with_hash("abc".to_string()); // Hash shorter than 6 chars
         let file = File::new(&path).with_metadata(metadata);
@@ -236,13 +255,37 @@ mod tests {
 
     #[test]
     fn test_custom_naming_strategy_no_datetime_parts() {
-        let strategy = CustomNamingStrategy::new("{year}.{ext}".to_string());
+        let strategy = CustomNamingStrategy::new("{year}.{ext}".to_string()).unwrap();
         let path = PathBuf::from("/test/image.jpg");
         let metadata = FileMetadata::new("image/jpeg".to_string());
         let file = File::new(&path).with_metadata(metadata);
 
-        let result = strategy.generate_name(&file).unwrap();
-        assert_eq!(result, "{year}.jpg"); // Should leave placeholder as-is
+        let result = strategy.generate_name(&file);
+        assert!(matches!(result.unwrap_err(), CleanboxError::Exif(_)));
+
+        let strategy =
+            CustomNamingStrategy::new("{year|undated}_{stem}.{ext}".to_string()).unwrap();
+        assert_eq!(strategy.generate_name(&file).unwrap(), "undated_image.jpg");
+    }
+
+    #[test]
+    fn test_custom_naming_strategy_counter() {
+        let strategy =
+            CustomNamingStrategy::new("{datetime:%Y%m%d}-{counter:3}.{ext}".to_string()).unwrap();
+        let file = create_test_file_with_datetime("2023-12-01_14-30-00");
+
+        assert_eq!(strategy.generate_name(&file).unwrap(), "20231201-001.jpg");
+        assert_eq!(strategy.generate_name(&file).unwrap(), "20231201-002.jpg");
+    }
+
+    #[test]
+    fn test_custom_naming_strategy_invalid_template() {
+        let result = CustomNamingStrategy::new("{yaer}.{ext}".to_string());
+        assert_eq!(result.err().unwrap().kind(), "invalid_template");
+
+        let strategy = CustomNamingStrategy::new("{datetime:%Y/%m}.{ext}".to_string()).unwrap();
+        let file = create_test_file_with_datetime("2023-12-01_14-30-00");
+        assert!(strategy.generate_name(&file).is_err());
     }
 
     #[test]
@@ -304,4 +347,43 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("user input"));
     }
+
+    #[test]
+    fn test_timestamp_naming_strategy_parse_name() {
+        let parse = TimestampNamingStrategy::parse_name;
+        assert_eq!(
+            parse("2024-05-01_10-00-00.jpg").as_deref(),
+            Some("2024-05-01_10-00-00")
+        );
+        assert_eq!(
+            parse("2024-05-01_10-00-00_a1b2c3.heic").as_deref(),
+            Some("2024-05-01_10-00-00")
+        );
+        assert_eq!(parse("IMG_0001.jpg"), None);
+        assert_eq!(parse("2024-05-01_10-00-00"), None);
+        assert_eq!(parse("2024-05-01_10-00-00_holiday.jpg"), None);
+        assert_eq!(parse("2024-13-01_10-00-00.jpg"), None);
+        assert_eq!(parse("2024-5-01_10-00-00x.jpg"), None);
+    }
+
+    #[test]
+    fn test_document_naming_strategy_parse_name() {
+        let input =
+            DocumentNamingStrategy::parse_name("2024-05-01_bank-statement@@finance,bank.pdf")
+                .unwrap();
+        assert_eq!(
+            input,
+            DocumentInput::new(
+                "2024-05-01".to_string(),
+                "bank-statement".to_string(),
+                vec!["finance".to_string(), "bank".to_string()],
+            )
+        );
+
+        assert!(DocumentNamingStrategy::parse_name("scan 12.pdf").is_err());
+        assert!(
+            DocumentNamingStrategy::parse_name("2024-05-01_Bank Statement@@finance.pdf").is_err()
+        );
+        assert!(DocumentNamingStrategy::parse_name("2024-05-01_statement@@.pdf").is_err());
+    }
 }
@@ -1,10 +1,24 @@
 use crate::document::DocumentInput;
 use crate::error::{CleanboxError, Result};
-use crate::media::File;
+use crate::geocode::distance_km;
+use crate::media::{File, GpsPosition};
+use crate::naming::DATETIME_FORMAT;
+use crate::template::{Field, Template, sanitize};
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 pub trait OrganizationStrategy {
     fn determine_target_directory(&self, file: &File, base_path: &Path) -> Result<PathBuf>;
+
+    /// Look at every file of a run before any is placed, for strategies whose folders
+    /// depend on the other files
+    fn organize_batch(&mut self, _files: &[File]) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub struct MonthlyOrganizer;
@@ -102,46 +116,380 @@ impl OrganizationStrategy for YearlyOrganizer {
     }
 }
 
+/// Places files in folders from a `Template` such as `{year}/{month}/{camera|unknown}`
+#[derive(Debug, Clone, PartialEq)]
 pub struct CustomOrganizer {
-    pattern: String,
+    template: Template,
 }
 
 impl CustomOrganizer {
-    pub fn new(pattern: String) -> Self {
-        Self { pattern }
-    }
-
-    fn replace_placeholders(&self, file: &File, base_path: &Path) -> Result<PathBuf> {
-        let mut result = self.pattern.clone();
-
-        if let Some(metadata) = &file.metadata {
-            if let Some(datetime) = &metadata.datetime_original {
-                result = result.replace("{datetime}", datetime);
-
-                let parts: Vec<&str> = datetime.split('_').collect();
-                if parts.len() == 2 {
-                    let date_parts: Vec<&str> = parts[0].split('-').collect();
-                    if date_parts.len() == 3 {
-                        result = result.replace("{year}", date_parts[0]);
-                        result = result.replace("{month}", date_parts[1]);
-                        result = result.replace("{day}", date_parts[2]);
-                    }
-                }
+    /// Parse the template, failing on unknown placeholders or bad formats
+    pub fn new(pattern: String) -> Result<Self> {
+        let template = Template::parse(&pattern)?;
+        if template.uses(Field::Counter) {
+            return Err(CleanboxError::InvalidTemplate(format!(
+                "{pattern}: {{counter}} is only available in names"
+            )));
+        }
+        Ok(Self { template })
+    }
+
+    pub fn template(&self) -> &Template {
+        &self.template
+    }
+}
+
+impl OrganizationStrategy for CustomOrganizer {
+    fn determine_target_directory(&self, file: &File, base_path: &Path) -> Result<PathBuf> {
+        Ok(base_path.join(self.template.render(file, 0)?))
+    }
+}
+
+/// Hours between two shots after which `EventOrganizer` starts a new event
+pub const DEFAULT_EVENT_GAP_HOURS: u32 = 8;
+/// Name of an event nobody named
+pub const DEFAULT_EVENT_NAME: &str = "event";
+
+/// Media of one batch taken close together, filed in one folder
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub name: String,
+    pub files: Vec<PathBuf>,
+}
+
+impl Event {
+    /// Folder below the media root, such as `2024/2024-06-29_event`
+    pub fn directory(&self) -> PathBuf {
+        event_directory(self.start.date(), &self.name)
+    }
+}
+
+fn event_directory(date: NaiveDate, name: &str) -> PathBuf {
+    PathBuf::from(date.format("%Y").to_string()).join(format!(
+        "{}_{}",
+        date.format("%Y-%m-%d"),
+        name
+    ))
+}
+
+/// Clusters a batch of media into events, starting a new one after a gap of more than
+/// `max_gap_hours` between shots or, if set, a move of more than `max_distance_km`
+/// between GPS positions.
+///
+/// Files outside the batch are each filed as an event of their own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventOrganizer {
+    max_gap_hours: u32,
+    max_distance_km: Option<u32>,
+    events: Vec<Event>,
+    /// Index into `events` of each file of the batch
+    assignments: HashMap<PathBuf, usize>,
+}
+
+impl EventOrganizer {
+    pub fn new() -> Self {
+        Self {
+            max_gap_hours: DEFAULT_EVENT_GAP_HOURS,
+            max_distance_km: None,
+            events: Vec::new(),
+            assignments: HashMap::new(),
+        }
+    }
+
+    pub fn with_max_gap_hours(mut self, hours: u32) -> Self {
+        self.max_gap_hours = hours;
+        self
+    }
+
+    pub fn with_max_distance_km(mut self, km: u32) -> Self {
+        self.max_distance_km = Some(km);
+        self
+    }
+
+    /// Whether GPS positions split events
+    pub fn uses_distance(&self) -> bool {
+        self.max_distance_km.is_some()
+    }
+
+    /// Events of the last batch, in time order
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Rename the event at `index`; an empty name keeps the current one
+    pub fn name_event(&mut self, index: usize, name: &str) {
+        let name = sanitize(name).replace(char::is_whitespace, "-");
+        if name.is_empty() || name == self.events[index].name {
+            return;
+        }
+        self.events[index].name = self.unique_name(index, &name);
+    }
+
+    /// `name`, or `name_2`, `name_3`... if another event of the same day already has it
+    fn unique_name(&self, index: usize, name: &str) -> String {
+        let date = self.events[index].start.date();
+        let taken: HashSet<&str> = self
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(other, event)| *other != index && event.start.date() == date)
+            .map(|(_, event)| event.name.as_str())
+            .collect();
+        let mut candidate = name.to_string();
+        let mut number = 1;
+        while taken.contains(candidate.as_str()) {
+            number += 1;
+            candidate = format!("{name}_{number}");
+        }
+        candidate
+    }
+
+    /// Whether a shot at `datetime` and `position` starts a new event after one ending at
+    /// `previous` whose last known position is `last_position`
+    fn splits(
+        &self,
+        previous: NaiveDateTime,
+        datetime: NaiveDateTime,
+        last_position: Option<GpsPosition>,
+        position: Option<GpsPosition>,
+    ) -> bool {
+        if datetime - previous > TimeDelta::hours(self.max_gap_hours.into()) {
+            return true;
+        }
+        match (self.max_distance_km, last_position, position) {
+            (Some(km), Some(last), Some(position)) => distance_km(last, position) > km.into(),
+            _ => false,
+        }
+    }
+}
+
+impl Default for EventOrganizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The capture time of `file` as a date and time
+fn file_datetime(file: &File) -> Result<NaiveDateTime> {
+    let datetime = file
+        .metadata
+        .as_ref()
+        .and_then(|m| m.datetime_original.as_ref())
+        .ok_or_else(|| CleanboxError::Exif("No datetime available for organization".to_string()))?;
+    NaiveDateTime::parse_from_str(datetime, DATETIME_FORMAT)
+        .map_err(|_| CleanboxError::InvalidDateTime(format!("Invalid datetime format: {datetime}")))
+}
+
+/// The name of the folder all `files` share, if it is an event folder of `date`
+fn existing_event_name(files: &[PathBuf], date: NaiveDate) -> Option<String> {
+    let parent = files.first()?.parent()?;
+    if files.iter().any(|file| file.parent() != Some(parent)) {
+        return None;
+    }
+    let folder = parent.file_name()?.to_str()?;
+    let name = folder.strip_prefix(&format!("{}_", date.format("%Y-%m-%d")))?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+impl OrganizationStrategy for EventOrganizer {
+    fn determine_target_directory(&self, file: &File, base_path: &Path) -> Result<PathBuf> {
+        match self.assignments.get(&file.path) {
+            Some(&index) => Ok(base_path.join(self.events[index].directory())),
+            None => {
+                let date = file_datetime(file)?.date();
+                Ok(base_path.join(event_directory(date, DEFAULT_EVENT_NAME)))
             }
+        }
+    }
 
-            result = result.replace(
-                "{media_type}",
-                &format!("{:?}", metadata.file_type).to_lowercase(),
-            );
+    fn organize_batch(&mut self, files: &[File]) -> Result<()> {
+        let mut dated: Vec<(NaiveDateTime, &File)> = files
+            .iter()
+            .filter_map(|file| Some((file_datetime(file).ok()?, file)))
+            .collect();
+        dated.sort_by(|(a, a_file), (b, b_file)| {
+            a.cmp(b).then_with(|| a_file.path.cmp(&b_file.path))
+        });
+
+        self.events.clear();
+        self.assignments.clear();
+        let mut last_position = None;
+        for (datetime, file) in dated {
+            let position = file.metadata.as_ref().and_then(|m| m.gps);
+            let starts_event = match self.events.last() {
+                Some(event) => self.splits(event.end, datetime, last_position, position),
+                None => true,
+            };
+            if starts_event {
+                self.events.push(Event {
+                    start: datetime,
+                    end: datetime,
+                    // Named below, once all events exist
+                    name: String::new(),
+                    files: Vec::new(),
+                });
+                last_position = None;
+            }
+            let event = self.events.last_mut().expect("an event was just started");
+            event.end = datetime;
+            event.files.push(file.path.clone());
+            last_position = position.or(last_position);
         }
 
-        Ok(base_path.join(result))
+        // Events already filed in a folder of their own keep its name
+        for index in 0..self.events.len() {
+            let event = &self.events[index];
+            let name = existing_event_name(&event.files, event.start.date())
+                .unwrap_or_else(|| DEFAULT_EVENT_NAME.to_string());
+            self.events[index].name = self.unique_name(index, &name);
+            for file in &self.events[index].files {
+                self.assignments.insert(file.clone(), index);
+            }
+        }
+        Ok(())
     }
 }
 
-impl OrganizationStrategy for CustomOrganizer {
+impl fmt::Display for EventOrganizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "events")?;
+        match (self.max_gap_hours, self.max_distance_km) {
+            (DEFAULT_EVENT_GAP_HOURS, None) => Ok(()),
+            (hours, None) => write!(f, ":{hours}h"),
+            (hours, Some(km)) => write!(f, ":{hours}h,{km}km"),
+        }
+    }
+}
+
+impl FromStr for EventOrganizer {
+    type Err = CleanboxError;
+
+    /// `events`, optionally with the gap and distance that split events, as in `events:6h,50km`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            CleanboxError::InvalidSettings(format!(
+                "Invalid event layout '{s}', expected events or events:<hours>h[,<km>km]"
+            ))
+        };
+        let options = match s.trim().strip_prefix("events").ok_or_else(invalid)? {
+            "" => return Ok(Self::new()),
+            rest => rest.strip_prefix(':').ok_or_else(invalid)?,
+        };
+
+        let mut organizer = Self::new();
+        for option in options.split(',').map(str::trim) {
+            if let Some(km) = option.strip_suffix("km") {
+                organizer.max_distance_km = Some(km.parse().map_err(|_| invalid())?);
+            } else if let Some(hours) = option.strip_suffix('h') {
+                organizer.max_gap_hours =
+                    hours.parse().ok().filter(|&h| h > 0).ok_or_else(invalid)?;
+            } else {
+                return Err(invalid());
+            }
+        }
+        Ok(organizer)
+    }
+}
+
+/// Folder layout of `media/`, chosen per life directory
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum MediaLayout {
+    /// `YYYY/MM`, as `MonthlyOrganizer`
+    #[default]
+    Monthly,
+    /// `YYYY`, as `YearlyOrganizer`
+    Yearly,
+    /// Directly in `media/`, as `FlatOrganizer`
+    Flat,
+    /// A `CustomOrganizer` template such as `{year}/{media_type}`
+    Custom(CustomOrganizer),
+    /// `YYYY/YYYY-MM-DD_name` per event, as `EventOrganizer`
+    Events(EventOrganizer),
+}
+
+impl MediaLayout {
+    /// Whether the folder depends on more of the file than its datetime
+    pub fn needs_file_metadata(&self) -> bool {
+        match self {
+            MediaLayout::Custom(organizer) => organizer.template().needs_file_metadata(),
+            MediaLayout::Events(organizer) => organizer.uses_distance(),
+            _ => false,
+        }
+    }
+
+    /// Whether the folder depends on the other files of the batch
+    pub fn groups_files(&self) -> bool {
+        matches!(self, MediaLayout::Events(_))
+    }
+
+    /// Whether the folder depends on where the file was captured
+    pub fn needs_location(&self) -> bool {
+        matches!(self, MediaLayout::Custom(organizer) if organizer.template().needs_location())
+    }
+}
+
+impl fmt::Display for MediaLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaLayout::Monthly => write!(f, "monthly"),
+            MediaLayout::Yearly => write!(f, "yearly"),
+            MediaLayout::Flat => write!(f, "flat"),
+            MediaLayout::Custom(organizer) => write!(f, "{}", organizer.template()),
+            MediaLayout::Events(organizer) => write!(f, "{organizer}"),
+        }
+    }
+}
+
+impl FromStr for MediaLayout {
+    type Err = CleanboxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "monthly" => Ok(MediaLayout::Monthly),
+            "yearly" => Ok(MediaLayout::Yearly),
+            "flat" => Ok(MediaLayout::Flat),
+            events if events.starts_with("events") => Ok(MediaLayout::Events(events.parse()?)),
+            pattern if pattern.contains('{') => Ok(MediaLayout::Custom(CustomOrganizer::new(
+                pattern.to_string(),
+            )?)),
+            other => Err(CleanboxError::InvalidSettings(format!(
+                "Unknown media layout '{other}', expected monthly, yearly, flat, events or a pattern such as {{year}}/{{media_type}}"
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for MediaLayout {
+    type Error = CleanboxError;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl OrganizationStrategy for MediaLayout {
     fn determine_target_directory(&self, file: &File, base_path: &Path) -> Result<PathBuf> {
-        self.replace_placeholders(file, base_path)
+        match self {
+            MediaLayout::Monthly => {
+                MonthlyOrganizer::new().determine_target_directory(file, base_path)
+            }
+            MediaLayout::Yearly => {
+                YearlyOrganizer::new().determine_target_directory(file, base_path)
+            }
+            MediaLayout::Flat => FlatOrganizer::new().determine_target_directory(file, base_path),
+            MediaLayout::Custom(organizer) => organizer.determine_target_directory(file, base_path),
+            MediaLayout::Events(organizer) => organizer.determine_target_directory(file, base_path),
+        }
+    }
+
+    fn organize_batch(&mut self, files: &[File]) -> Result<()> {
+        match self {
+            MediaLayout::Events(organizer) => organizer.organize_batch(files),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -220,7 +568,7 @@ impl OrganizationStrategy for DocumentOrganizer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::media::{File, FileMetadata};
+    use crate::media::{File, FileMetadata, GpsPosition};
     use std::path::PathBuf;
 
     fn create_test_file_with_datetime(datetime: &str, file_type: &str) -> File {
@@ -319,7 +667,7 @@ mod tests {
 
     #[test]
     fn test_custom_organizer_datetime_replacement() {
-        let organizer = CustomOrganizer::new("{year}/{month}/{day}".to_string());
+        let organizer = CustomOrganizer::new("{year}/{month}/{day}".to_string()).unwrap();
         let file = create_test_file_with_datetime("2023-12-01_14-30-00", "image/jpeg");
         let base_path = Path::new("/media");
 
@@ -331,7 +679,7 @@ mod tests {
 
     #[test]
     fn test_custom_organizer_media_type() {
-        let organizer = CustomOrganizer::new("{media_type}/{year}".to_string());
+        let organizer = CustomOrganizer::new("{media_type}/{year}".to_string()).unwrap();
         let file = create_test_file_with_datetime("2023-12-01_14-30-00", "image/jpeg");
         let base_path = Path::new("/media");
 
@@ -343,7 +691,7 @@ mod tests {
 
     #[test]
     fn test_custom_organizer_video_type() {
-        let organizer = CustomOrganizer::new("{media_type}/{year}".to_string());
+        let organizer = CustomOrganizer::new("{media_type}/{year}".to_string()).unwrap();
         let file = create_test_file_with_datetime("2023-12-01_14-30-00", "video/mp4");
         let base_path = Path::new("/media");
 
@@ -457,4 +805,151 @@ mod tests {
                 .contains("datetime information")
         );
     }
+
+    #[test]
+    fn test_media_layout() {
+        let file = create_test_file_with_datetime("2024-05-01_10-00-00", "image/jpeg");
+        let base_path = Path::new("/media");
+        let target = |layout: &str| {
+            layout
+                .parse::<MediaLayout>()
+                .unwrap()
+                .determine_target_directory(&file, base_path)
+                .unwrap()
+        };
+
+        assert_eq!(target("monthly"), PathBuf::from("/media/2024/05"));
+        assert_eq!(target("yearly"), PathBuf::from("/media/2024"));
+        assert_eq!(target("flat"), PathBuf::from("/media"));
+        assert_eq!(
+            target("{year}/{media_type}"),
+            PathBuf::from("/media/2024/image")
+        );
+        assert!("montly".parse::<MediaLayout>().is_err());
+        assert!(
+            "{year}/{media_type}"
+                .parse::<MediaLayout>()
+                .unwrap()
+                .needs_file_metadata()
+        );
+        assert!(!MediaLayout::Yearly.needs_file_metadata());
+        assert_eq!(
+            "{yaer}/{month}".parse::<MediaLayout>().unwrap_err().kind(),
+            "invalid_template"
+        );
+        assert!("{year}/{counter}".parse::<MediaLayout>().is_err());
+
+        let undated =
+            File::new("/test/image.jpg").with_metadata(FileMetadata::new("image/jpeg".to_string()));
+        let custom: MediaLayout = "{year}/{month}".parse().unwrap();
+        assert!(
+            custom
+                .determine_target_directory(&undated, base_path)
+                .is_err()
+        );
+
+        assert_eq!(
+            target("events"),
+            PathBuf::from("/media/2024/2024-05-01_event")
+        );
+        let events: MediaLayout = "events:6h,50km".parse().unwrap();
+        assert_eq!(events.to_string(), "events:6h,50km");
+        assert!(events.needs_file_metadata() && events.groups_files());
+        assert_eq!(
+            "events".parse::<MediaLayout>().unwrap().to_string(),
+            "events"
+        );
+        assert!("events:0h".parse::<MediaLayout>().is_err());
+        assert!("events:far".parse::<MediaLayout>().is_err());
+    }
+
+    fn shot(path: &str, datetime: &str, gps: Option<(f64, f64)>) -> File {
+        let mut metadata =
+            FileMetadata::new("image/jpeg".to_string()).with_datetime(datetime.to_string());
+        if let Some((latitude, longitude)) = gps {
+            metadata = metadata.with_gps(GpsPosition::new(latitude, longitude));
+        }
+        File::new(path).with_metadata(metadata)
+    }
+
+    #[test]
+    fn test_event_organizer_clusters_by_time_gap() {
+        // A weekend trip across a month boundary, then an everyday photo days later
+        let files = vec![
+            shot("/inbox/c.jpg", "2024-07-01_18-00-00", None),
+            shot("/inbox/a.jpg", "2024-06-30_09-00-00", None),
+            shot("/inbox/b.jpg", "2024-06-30_22-00-00", None),
+            shot("/inbox/d.jpg", "2024-07-01_08-30-00", None),
+            shot("/inbox/e.jpg", "2024-07-05_12-00-00", None),
+        ];
+        let mut organizer = EventOrganizer::new().with_max_gap_hours(18);
+        organizer.organize_batch(&files).unwrap();
+
+        let target = |organizer: &EventOrganizer, file: &File| {
+            organizer
+                .determine_target_directory(file, Path::new("/media"))
+                .unwrap()
+        };
+        assert_eq!(organizer.events().len(), 2);
+        for file in &files[..4] {
+            assert_eq!(
+                target(&organizer, file),
+                PathBuf::from("/media/2024/2024-06-30_event")
+            );
+        }
+        assert_eq!(
+            target(&organizer, &files[4]),
+            PathBuf::from("/media/2024/2024-07-05_event")
+        );
+
+        organizer.name_event(0, " Lisbon weekend ");
+        assert_eq!(
+            target(&organizer, &files[2]),
+            PathBuf::from("/media/2024/2024-06-30_Lisbon-weekend")
+        );
+
+        // A file outside the batch is an event of its own
+        let other = shot("/inbox/f.jpg", "2024-08-01_10-00-00", None);
+        assert_eq!(
+            target(&organizer, &other),
+            PathBuf::from("/media/2024/2024-08-01_event")
+        );
+    }
+
+    #[test]
+    fn test_event_organizer_splits_by_distance() {
+        let files = vec![
+            shot("/inbox/a.jpg", "2024-06-29_09-00-00", Some((38.72, -9.14))),
+            shot("/inbox/b.jpg", "2024-06-29_10-00-00", None),
+            // Porto, some 270 km north, the same afternoon
+            shot("/inbox/c.jpg", "2024-06-29_15-00-00", Some((41.15, -8.61))),
+        ];
+        let mut organizer = EventOrganizer::new().with_max_distance_km(50);
+        organizer.organize_batch(&files).unwrap();
+
+        let names: Vec<PathBuf> = organizer.events().iter().map(Event::directory).collect();
+        assert_eq!(
+            names,
+            vec![
+                PathBuf::from("2024/2024-06-29_event"),
+                PathBuf::from("2024/2024-06-29_event_2")
+            ]
+        );
+
+        // Files already in an event folder keep its name
+        let archived = vec![
+            shot(
+                "/media/2024/2024-06-29_Lisbon/a.jpg",
+                "2024-06-29_09-00-00",
+                None,
+            ),
+            shot(
+                "/media/2024/2024-06-29_Lisbon/b.jpg",
+                "2024-06-29_10-00-00",
+                None,
+            ),
+        ];
+        organizer.organize_batch(&archived).unwrap();
+        assert_eq!(organizer.events()[0].name, "Lisbon");
+    }
 }
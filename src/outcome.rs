@@ -0,0 +1,161 @@
+use crate::error::CleanboxError;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Why a file was left in the inbox
+#[derive(Debug)]
+pub enum SkipReason {
+    /// The file type or its metadata is not supported and such files are skipped
+    Unsupported(CleanboxError),
+    /// Neither media nor a document
+    Unrecognized,
+    /// A document without a sidecar or rule in a non-interactive run
+    NeedsInput,
+    /// The user chose to skip the document
+    UserSkipped,
+    /// Processing was cancelled before the file was reached
+    Cancelled,
+    /// A media sidecar stays with its media file, which was not filed
+    PrimaryNotFiled,
+}
+
+impl SkipReason {
+    /// Stable snake_case name of the reason, for machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SkipReason::Unsupported(_) => "unsupported",
+            SkipReason::Unrecognized => "unrecognized",
+            SkipReason::NeedsInput => "needs_input",
+            SkipReason::UserSkipped => "user_skipped",
+            SkipReason::Cancelled => "cancelled",
+            SkipReason::PrimaryNotFiled => "primary_not_filed",
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Unsupported(err) => write!(f, "unsupported ({err})"),
+            SkipReason::Unrecognized => write!(f, "unrecognized file type"),
+            SkipReason::NeedsInput => write!(f, "needs document input"),
+            SkipReason::UserSkipped => write!(f, "skipped by user"),
+            SkipReason::Cancelled => write!(f, "processing cancelled"),
+            SkipReason::PrimaryNotFiled => write!(f, "its media file was not filed"),
+        }
+    }
+}
+
+/// Final outcome of one inbox file
+#[derive(Debug)]
+pub enum FileOutcome {
+    /// Named and moved to the given target path
+    Moved(PathBuf),
+    Skipped(SkipReason),
+    Failed(CleanboxError),
+    /// Deferred by the user and not revisited before processing ended
+    Deferred,
+}
+
+impl fmt::Display for FileOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileOutcome::Moved(target) => write!(f, "moved to {}", target.display()),
+            FileOutcome::Skipped(reason) => write!(f, "skipped: {reason}"),
+            FileOutcome::Failed(err) => write!(f, "failed: {err}"),
+            FileOutcome::Deferred => write!(f, "deferred"),
+        }
+    }
+}
+
+/// Outcome of one inbox file, with its original path
+#[derive(Debug)]
+pub struct FileResult {
+    pub path: PathBuf,
+    pub outcome: FileOutcome,
+}
+
+impl FileResult {
+    pub fn new(path: &Path, outcome: FileOutcome) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            outcome,
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self.outcome, FileOutcome::Failed(_))
+    }
+
+    /// The error that failed or skipped this file, if any
+    pub fn error(&self) -> Option<&CleanboxError> {
+        match &self.outcome {
+            FileOutcome::Failed(err) | FileOutcome::Skipped(SkipReason::Unsupported(err)) => {
+                Some(err)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FileResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_result_display() {
+        let moved = FileResult::new(
+            Path::new("/inbox/a.jpg"),
+            FileOutcome::Moved(PathBuf::from("/media/2024/05/a.jpg")),
+        );
+        assert_eq!(
+            moved.to_string(),
+            "/inbox/a.jpg: moved to /media/2024/05/a.jpg"
+        );
+
+        let skipped = FileResult::new(
+            Path::new("/inbox/b.pdf"),
+            FileOutcome::Skipped(SkipReason::NeedsInput),
+        );
+        assert_eq!(
+            skipped.to_string(),
+            "/inbox/b.pdf: skipped: needs document input"
+        );
+
+        let failed = FileResult::new(
+            Path::new("/inbox/c.jpg"),
+            FileOutcome::Failed(CleanboxError::InvalidPath("/x".to_string())),
+        );
+        assert_eq!(failed.to_string(), "/inbox/c.jpg: failed: Invalid path: /x");
+    }
+
+    #[test]
+    fn test_file_result_error() {
+        let failed = FileResult::new(
+            Path::new("/inbox/a.jpg"),
+            FileOutcome::Failed(CleanboxError::Exif("broken".to_string())),
+        );
+        assert!(failed.is_failed());
+        assert!(matches!(failed.error(), Some(CleanboxError::Exif(_))));
+
+        let unsupported = FileResult::new(
+            Path::new("/inbox/b.txt"),
+            FileOutcome::Skipped(SkipReason::Unsupported(CleanboxError::UnsupportedFileType(
+                "text/plain".to_string(),
+            ))),
+        );
+        assert!(!unsupported.is_failed());
+        assert!(unsupported.error().is_some());
+
+        let deferred = FileResult::new(Path::new("/inbox/c.pdf"), FileOutcome::Deferred);
+        assert!(deferred.error().is_none());
+        assert_eq!(SkipReason::UserSkipped.kind(), "user_skipped");
+        assert_eq!(SkipReason::PrimaryNotFiled.kind(), "primary_not_filed");
+    }
+}
@@ -96,6 +96,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::default_constructed_unit_structs)]
     fn test_life_directory_resolver_default() {
         let resolver = LifeDirectoryResolver::default();
         let config = ProcessingConfig::new(PathBuf::from("/inbox"), PathBuf::from("/test/media"));
@@ -0,0 +1,212 @@
+use crate::error::Result;
+use crate::filesystem::FileManager;
+use crate::hashing::HashAlgorithm;
+use crate::media::FileMetadata;
+use crate::metadata::{MetadataParser, read_metadata};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Metadata, hash and size of an inbox file, read ahead of naming and moving
+#[derive(Debug)]
+pub struct PreparedFile {
+    pub path: PathBuf,
+    /// Parsed metadata, with `file_hash` set if the file was hashed successfully
+    pub metadata: Result<FileMetadata>,
+    pub size: u64,
+}
+
+/// Default worker count: one per available CPU
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Parse metadata and hash the files selected by `should_hash` with `algorithm` on
+/// `workers` threads, reading each file once.
+///
+/// Only reading happens here; nothing is renamed or moved, so the caller can name and
+/// move the files one at a time in a deterministic order. Results come back in the
+/// order of `paths`, and `on_prepared` sees each one as soon as it is ready.
+pub fn prepare_files<E, F, H, C>(
+    paths: &[PathBuf],
+    metadata_parser: &E,
+    file_manager: &F,
+    workers: usize,
+    algorithm: HashAlgorithm,
+    should_hash: H,
+    mut on_prepared: C,
+) -> Vec<PreparedFile>
+where
+    E: MetadataParser + Sync,
+    F: FileManager + Sync,
+    H: Fn(&FileMetadata) -> bool + Sync,
+    C: FnMut(&PreparedFile),
+{
+    let prepare = |path: &Path| -> PreparedFile {
+        let size = file_manager.file_size(path).unwrap_or(0);
+        let metadata = match file_manager.open_file(path) {
+            Ok(reader) => read_metadata(metadata_parser, path, reader, algorithm, &should_hash),
+            // Without a hash the file is hashed again when it is needed, which
+            // reports the error against the file
+            Err(e) => {
+                log::debug!("Cannot read {}: {}", path.display(), e);
+                metadata_parser.parse_metadata(path)
+            }
+        };
+
+        PreparedFile {
+            path: path.to_path_buf(),
+            metadata,
+            size,
+        }
+    };
+
+    let workers = workers.clamp(1, paths.len().max(1));
+    if workers == 1 {
+        return paths
+            .iter()
+            .map(|path| {
+                let prepared = prepare(path);
+                on_prepared(&prepared);
+                prepared
+            })
+            .collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut slots: Vec<Option<PreparedFile>> = paths.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (next, prepare) = (&next, &prepare);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        break;
+                    };
+                    if sender.send((index, prepare(path))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // The loop below ends once every worker has dropped its sender
+        drop(sender);
+
+        for (index, prepared) in receiver {
+            on_prepared(&prepared);
+            slots[index] = Some(prepared);
+        }
+    });
+
+    slots
+        .into_iter()
+        .map(|slot| slot.expect("every path is prepared by a worker"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::FileType;
+    use crate::testing::{InMemoryFileManager, StaticMetadataParser};
+
+    fn inbox_with_files(count: usize) -> (InMemoryFileManager, Vec<PathBuf>) {
+        let file_manager = InMemoryFileManager::new();
+        let paths: Vec<PathBuf> = (0..count)
+            .map(|i| {
+                let extension = if i % 3 == 0 { "pdf" } else { "jpg" };
+                PathBuf::from(format!("/inbox/file{i}.{extension}"))
+            })
+            .collect();
+        for (i, path) in paths.iter().enumerate() {
+            file_manager.add_file(path, format!("content {i}").as_bytes());
+        }
+        (file_manager, paths)
+    }
+
+    #[test]
+    fn test_results_keep_input_order() {
+        let (file_manager, paths) = inbox_with_files(40);
+        let parser = StaticMetadataParser::new();
+
+        let mut seen = 0;
+        let prepared = prepare_files(
+            &paths,
+            &parser,
+            &file_manager,
+            4,
+            HashAlgorithm::Blake3,
+            |metadata| metadata.file_type == FileType::Image,
+            |_| seen += 1,
+        );
+
+        assert_eq!(seen, 40);
+        let prepared_paths: Vec<&PathBuf> = prepared.iter().map(|p| &p.path).collect();
+        assert_eq!(prepared_paths, paths.iter().collect::<Vec<_>>());
+
+        // Only images are hashed
+        let pdf = prepared[0].metadata.as_ref().unwrap();
+        assert!(pdf.file_hash.is_none());
+        let jpg = prepared[1].metadata.as_ref().unwrap();
+        assert_eq!(
+            jpg.file_hash,
+            Some(
+                file_manager
+                    .hash_file(&paths[1], HashAlgorithm::Blake3)
+                    .unwrap()
+            )
+        );
+        assert_eq!(prepared[1].size, "content 1".len() as u64);
+    }
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        let (file_manager, paths) = inbox_with_files(25);
+        let parser = StaticMetadataParser::new();
+        let hashes = |workers| -> Vec<Option<String>> {
+            prepare_files(
+                &paths,
+                &parser,
+                &file_manager,
+                workers,
+                HashAlgorithm::Xxh3,
+                |_| true,
+                |_| {},
+            )
+            .into_iter()
+            .map(|prepared| prepared.metadata.unwrap().file_hash)
+            .collect()
+        };
+
+        assert_eq!(hashes(1), hashes(8));
+    }
+
+    #[test]
+    fn test_unreadable_file_keeps_metadata_without_hash() {
+        let (file_manager, mut paths) = inbox_with_files(2);
+        paths.insert(1, PathBuf::from("/inbox/missing.jpg"));
+        let parser = StaticMetadataParser::new();
+
+        let prepared = prepare_files(
+            &paths,
+            &parser,
+            &file_manager,
+            3,
+            HashAlgorithm::default(),
+            |_| true,
+            |_| {},
+        );
+
+        assert!(prepared[0].metadata.as_ref().unwrap().file_hash.is_some());
+        let missing = prepared[1].metadata.as_ref().unwrap();
+        assert_eq!(missing.file_type, FileType::Image);
+        assert!(missing.file_hash.is_none());
+        assert_eq!(prepared[1].size, 0);
+        assert!(prepared[2].metadata.as_ref().unwrap().file_hash.is_some());
+    }
+}
@@ -1,13 +1,34 @@
+use crate::companions::{group_companions, merge_metadata};
 use crate::config::{DuplicateHandling, LifeConfig, ProcessingConfig};
+use crate::document::DocumentInput;
 use crate::error::{CleanboxError, Result};
-use crate::filesystem::{FileHasher, FileManager, StdFileManager};
-use crate::interactive::{DocumentInputCollector, UserPrompt};
-use crate::media::{File, FileType};
-use crate::metadata::{MetadataParser, RexifParser};
+use crate::export::ExportMetadata;
+use crate::filesystem::{FileHasher, FileManager, walk_files};
+use crate::geocode::Geocoder;
+use crate::hashing::HashAlgorithm;
+use crate::interactive::{
+    DocumentAction, DocumentActionPrompt, DocumentInputCollector, ProgressIndicator, UserPrompt,
+    open_in_viewer,
+};
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::media::{File, FileMetadata, FileType};
+use crate::metadata::MetadataParser;
 use crate::naming::{DocumentNamingStrategy, NamingStrategy, TimestampNamingStrategy};
-use crate::organization::{DocumentOrganizer, MonthlyOrganizer, OrganizationStrategy};
+use crate::organization::{
+    DEFAULT_EVENT_NAME, DocumentOrganizer, EventOrganizer, MediaLayout, OrganizationStrategy,
+};
+use crate::outcome::{FileOutcome, FileResult, SkipReason};
 use crate::paths::{BasePathResolver, LifeDirectoryResolver};
+use crate::pipeline::prepare_files;
+use crate::report::{DatetimeSource, FileRecord, RunReport};
+use crate::rules::{DocumentRules, RuleCandidate};
+use crate::sidecar::{
+    DocumentSidecar, MediaSidecar, document_sidecar, find_sidecar, is_media_only_sidecar_file,
+    is_sidecar_file, match_media_sidecars, sidecar_document_path,
+};
 use crate::tags::TagDictionary;
+use crate::xmp::MetadataWriter;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 
 pub struct FileProcessor<E, F, N, O, R>
@@ -31,7 +52,8 @@ pub struct ProcessingResult {
     pub processed_files: usize,
     pub skipped_files: usize,
     pub failed_files: usize,
-    pub errors: Vec<String>,
+    /// Outcome of every file in the inbox, in processing order
+    pub outcomes: Vec<FileResult>,
 }
 
 impl ProcessingResult {
@@ -40,21 +62,21 @@ impl ProcessingResult {
             processed_files: 0,
             skipped_files: 0,
             failed_files: 0,
-            errors: Vec::new(),
+            outcomes: Vec::new(),
         }
     }
 
-    fn add_error(&mut self, error: String) {
-        self.errors.push(error);
-        self.failed_files += 1;
-    }
-
-    fn skip_file(&mut self) {
-        self.skipped_files += 1;
+    fn add_outcome(&mut self, result: FileResult) {
+        match result.outcome {
+            FileOutcome::Moved(_) => self.processed_files += 1,
+            FileOutcome::Skipped(_) | FileOutcome::Deferred => self.skipped_files += 1,
+            FileOutcome::Failed(_) => self.failed_files += 1,
+        }
+        self.outcomes.push(result);
     }
 
-    fn process_file(&mut self) {
-        self.processed_files += 1;
+    pub fn failures(&self) -> impl Iterator<Item = &FileResult> {
+        self.outcomes.iter().filter(|result| result.is_failed())
     }
 }
 
@@ -98,29 +120,55 @@ where
                 continue;
             }
 
-            match self.process_single_file(&file_path) {
-                Ok(()) => result.process_file(),
-                Err(e) => {
-                    let error_msg = format!("{}: {}", file_path.display(), e);
-                    if self.should_skip_error(&e) {
-                        result.skip_file();
-                        if !self.config.skip_unsupported_files {
-                            result.add_error(error_msg);
-                        }
-                    } else {
-                        result.add_error(error_msg);
-                    }
+            let outcome = match self.process_single_file(&file_path) {
+                Ok(target_path) => FileOutcome::Moved(target_path),
+                Err(e) if self.should_skip_error(&e) && self.config.skip_unsupported_files => {
+                    FileOutcome::Skipped(SkipReason::Unsupported(e))
                 }
-            }
+                Err(e) => FileOutcome::Failed(e),
+            };
+            result.add_outcome(FileResult::new(&file_path, outcome));
         }
 
         Ok(result)
     }
 
-    fn process_single_file(&self, file_path: &Path) -> Result<()> {
-        let mut file = File::new(file_path);
+    fn process_single_file(&self, file_path: &Path) -> Result<PathBuf> {
+        let mut record = FileRecord::new(file_path.to_path_buf());
+        self.process_single_file_recorded(file_path, &mut record)
+    }
 
+    /// Process one file, filling `record` with what was detected and decided.
+    ///
+    /// Returns the path the file was moved to.
+    pub fn process_single_file_recorded(
+        &self,
+        file_path: &Path,
+        record: &mut FileRecord,
+    ) -> Result<PathBuf> {
         let metadata = self.exif_parser.parse_metadata(file_path)?;
+        self.process_prepared_file(file_path, metadata, record)
+    }
+
+    /// Process one file whose metadata was read ahead, for example by
+    /// `pipeline::prepare_files`. A `file_hash` in the metadata is reused for duplicates.
+    pub fn process_prepared_file(
+        &self,
+        file_path: &Path,
+        metadata: FileMetadata,
+        record: &mut FileRecord,
+    ) -> Result<PathBuf> {
+        let mut file = File::new(file_path);
+
+        record.set_metadata(&metadata);
+        if let Some(hash) = &metadata.file_hash {
+            record.set_hash(self.config.hash_algorithm, hash);
+        }
+        if let Some(datetime) = &metadata.datetime_original {
+            // A datetime taken from an export is recorded before the file gets here
+            let source = record.datetime_source.unwrap_or(DatetimeSource::Exif);
+            record.set_datetime(datetime, source);
+        }
 
         // Use processing behavior methods for intelligent routing
         if metadata.file_type.should_skip() {
@@ -147,10 +195,15 @@ where
         file = file.with_metadata(metadata);
 
         let new_name = self.naming_strategy.generate_name(&file)?;
-        let temp_path = file_path.with_file_name(&new_name);
+        let mut temp_path = file_path.with_file_name(&new_name);
 
+        // Never rename over another inbox file that already has the new name
         if file_path != temp_path {
-            self.file_manager.rename_file(file_path, &temp_path)?;
+            if self.file_manager.file_exists(&temp_path) {
+                temp_path = file_path.to_path_buf();
+            } else {
+                self.file_manager.rename_file(file_path, &temp_path)?;
+            }
         }
 
         // Use BasePathResolver to determine correct base path (media/ vs documents/)
@@ -164,19 +217,96 @@ where
         let mut target_path = target_dir.join(&new_name);
 
         if self.file_manager.file_exists(&target_path) {
-            target_path = self.handle_duplicate(&temp_path, &target_path)?;
+            record.duplicate_action = Some(self.config.handle_duplicates.clone());
+            target_path =
+                self.handle_duplicate(&temp_path, &target_path, file.metadata.as_ref())?;
         }
 
+        record.target_path = Some(target_path.clone());
         self.file_manager.move_file(&temp_path, &target_path)?;
 
-        println!("Moved to {}", target_path.display());
-        Ok(())
+        log::debug!("Moved {} to {}", file_path.display(), target_path.display());
+        Ok(target_path)
     }
 
+    /// File a companion of a file already moved to `primary_target` under the same
+    /// name with its own extension, so the pair stays together
+    pub fn process_companion(
+        &self,
+        file_path: &Path,
+        metadata: FileMetadata,
+        primary_target: &Path,
+        record: &mut FileRecord,
+    ) -> Result<PathBuf> {
+        record.set_metadata(&metadata);
+        if let Some(hash) = &metadata.file_hash {
+            record.set_hash(self.config.hash_algorithm, hash);
+        }
+        if let Some(datetime) = &metadata.datetime_original {
+            record.set_datetime(datetime, DatetimeSource::Companion);
+        }
+
+        let extension = File::new(file_path).extension()?.to_string();
+        let stem = primary_target
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| CleanboxError::InvalidFileStem(primary_target.display().to_string()))?;
+        let target_path = primary_target.with_file_name(format!("{stem}.{extension}"));
+
+        if self.file_manager.file_exists(&target_path) {
+            record.duplicate_action = Some(self.config.handle_duplicates.clone());
+            if self.config.handle_duplicates != DuplicateHandling::Overwrite {
+                return Err(CleanboxError::FileAlreadyExists(
+                    target_path.display().to_string(),
+                ));
+            }
+        }
+
+        record.target_path = Some(target_path.clone());
+        self.file_manager.move_file(file_path, &target_path)?;
+
+        log::debug!("Moved {} to {}", file_path.display(), target_path.display());
+        Ok(target_path)
+    }
+
+    /// Move a sidecar next to its media file, already moved to `primary_target`, and
+    /// rename it to match
+    pub fn process_sidecar(
+        &self,
+        sidecar: &MediaSidecar,
+        primary_target: &Path,
+        record: &mut FileRecord,
+    ) -> Result<PathBuf> {
+        let target_path = sidecar.target(primary_target)?;
+
+        if self.file_manager.file_exists(&target_path) {
+            record.duplicate_action = Some(self.config.handle_duplicates.clone());
+            if self.config.handle_duplicates != DuplicateHandling::Overwrite {
+                return Err(CleanboxError::FileAlreadyExists(
+                    target_path.display().to_string(),
+                ));
+            }
+        }
+
+        record.target_path = Some(target_path.clone());
+        self.file_manager.move_file(&sidecar.path, &target_path)?;
+
+        log::debug!(
+            "Moved {} to {}",
+            sidecar.path.display(),
+            target_path.display()
+        );
+        Ok(target_path)
+    }
+
+    /// Resolve a target that already exists. Appending prefers the subsecond time, which
+    /// keeps a burst in shooting order, and a `file_hash` in `metadata` saves reading the
+    /// source again.
     fn handle_duplicate(
         &self,
         source_path: &Path,
         target_path: &Path,
+        metadata: Option<&FileMetadata>,
     ) -> Result<std::path::PathBuf> {
         match self.config.handle_duplicates {
             DuplicateHandling::Skip => Err(CleanboxError::FileAlreadyExists(
@@ -186,15 +316,35 @@ where
             DuplicateHandling::Error => Err(CleanboxError::FileAlreadyExists(
                 target_path.display().to_string(),
             )),
+            DuplicateHandling::Sequence => sequence_target(
+                &self.file_manager,
+                source_path,
+                target_path,
+                metadata.and_then(|m| m.file_hash.as_deref()),
+                self.config.hash_algorithm,
+            ),
             DuplicateHandling::AppendHash => {
-                let hash = self.file_manager.calculate_file_hash(source_path)?;
-                let hash_suffix = FileHasher::generate_hash_suffix(&hash, self.config.hash_length);
-
                 let original_name = target_path
                     .file_name()
                     .and_then(|n| n.to_str())
                     .ok_or_else(|| CleanboxError::InvalidPath(target_path.display().to_string()))?;
 
+                if let Some(subsec) = metadata.and_then(|m| m.subsec_time.as_deref()) {
+                    let new_name = FileHasher::append_hash_to_filename(original_name, subsec)?;
+                    let subsec_path = target_path.with_file_name(new_name);
+                    if !self.file_manager.file_exists(&subsec_path) {
+                        return Ok(subsec_path);
+                    }
+                }
+
+                let hash = match metadata.and_then(|m| m.file_hash.as_deref()) {
+                    Some(hash) => hash.to_string(),
+                    None => self
+                        .file_manager
+                        .hash_file(source_path, self.config.hash_algorithm)?,
+                };
+                let hash_suffix = FileHasher::generate_hash_suffix(&hash, self.config.hash_length);
+
                 let new_name = FileHasher::append_hash_to_filename(original_name, &hash_suffix)?;
                 Ok(target_path.with_file_name(new_name))
             }
@@ -209,12 +359,76 @@ where
     }
 }
 
+/// Next free name among `target_path`, `_01`, `_02`..., failing if the source is
+/// already archived under one of them so a re-run never adds a second copy
+fn sequence_target<F: FileManager>(
+    file_manager: &F,
+    source_path: &Path,
+    target_path: &Path,
+    known_hash: Option<&str>,
+    algorithm: HashAlgorithm,
+) -> Result<PathBuf> {
+    let original_name = target_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| CleanboxError::InvalidPath(target_path.display().to_string()))?;
+    let source_size = file_manager.file_size(source_path)?;
+    let mut source_hash = known_hash.map(str::to_string);
+
+    let mut candidate = target_path.to_path_buf();
+    let mut number = 0;
+    while file_manager.file_exists(&candidate) {
+        if file_manager.file_size(&candidate)? == source_size {
+            let hash = match &source_hash {
+                Some(hash) => hash.clone(),
+                None => source_hash
+                    .insert(file_manager.hash_file(source_path, algorithm)?)
+                    .clone(),
+            };
+            if file_manager.hash_file(&candidate, algorithm)? == hash {
+                return Err(CleanboxError::FileAlreadyExists(
+                    candidate.display().to_string(),
+                ));
+            }
+        }
+
+        number += 1;
+        let new_name = FileHasher::append_hash_to_filename(original_name, &format!("{number:02}"))?;
+        candidate = target_path.with_file_name(new_name);
+    }
+    Ok(candidate)
+}
+
+/// Sort key for shooting order: datetime, subsecond time, then the counter in the
+/// original name (`IMG_0042.jpg` is 42)
+fn shooting_order(
+    path: &Path,
+    metadata: Option<&FileMetadata>,
+) -> (Option<String>, Option<String>, Option<u64>, PathBuf) {
+    let counter = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| {
+            let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+            stem[stem.len() - digits..].parse().ok()
+        });
+
+    (
+        metadata.and_then(|m| m.datetime_original.clone()),
+        metadata.and_then(|m| m.subsec_time.clone()),
+        counter,
+        path.to_path_buf(),
+    )
+}
+
 /// Categorized files from inbox scan
 #[derive(Debug)]
 pub struct CategorizedFiles {
     pub media_files: Vec<PathBuf>,
     pub document_files: Vec<PathBuf>,
     pub unknown_files: Vec<PathBuf>,
+    /// XMP, AAE and JSON sidecars, keyed by the media file they follow
+    pub media_sidecars: HashMap<PathBuf, Vec<MediaSidecar>>,
 }
 
 impl Default for CategorizedFiles {
@@ -229,11 +443,15 @@ impl CategorizedFiles {
             media_files: Vec::new(),
             document_files: Vec::new(),
             unknown_files: Vec::new(),
+            media_sidecars: HashMap::new(),
         }
     }
 
     pub fn total_count(&self) -> usize {
-        self.media_files.len() + self.document_files.len() + self.unknown_files.len()
+        self.media_files.len()
+            + self.document_files.len()
+            + self.unknown_files.len()
+            + self.media_sidecars.values().map(Vec::len).sum::<usize>()
     }
 }
 
@@ -244,7 +462,10 @@ pub struct UnifiedProcessingResult {
     pub documents_processed: usize,
     pub files_skipped: usize,
     pub files_failed: usize,
-    pub errors: Vec<String>,
+    /// Outcome of every inbox file, in processing order
+    pub outcomes: Vec<FileResult>,
+    /// Per-file details of the run
+    pub report: RunReport,
 }
 
 impl Default for UnifiedProcessingResult {
@@ -260,13 +481,51 @@ impl UnifiedProcessingResult {
             documents_processed: 0,
             files_skipped: 0,
             files_failed: 0,
-            errors: Vec::new(),
+            outcomes: Vec::new(),
+            report: RunReport::new(),
         }
     }
 
     pub fn total_processed(&self) -> usize {
         self.media_processed + self.documents_processed
     }
+
+    pub fn failures(&self) -> impl Iterator<Item = &FileResult> {
+        self.outcomes.iter().filter(|result| result.is_failed())
+    }
+
+    /// Record the outcome of a file in the counters and the report.
+    ///
+    /// Moved files are counted by the caller, which knows whether it was media or a document.
+    fn add_outcome(&mut self, file_path: &Path, outcome: FileOutcome) {
+        let record = self.report.record(file_path);
+        match &outcome {
+            FileOutcome::Moved(_) => record.mark_processed(),
+            FileOutcome::Skipped(reason) => {
+                record.mark_skipped(reason);
+                self.files_skipped += 1;
+            }
+            FileOutcome::Failed(error) => {
+                record.mark_failed(error);
+                self.files_failed += 1;
+            }
+            FileOutcome::Deferred => {
+                record.mark_deferred();
+                self.files_skipped += 1;
+            }
+        }
+        self.outcomes.push(FileResult::new(file_path, outcome));
+    }
+}
+
+/// Document input found without running the interactive prompts
+enum AutomaticInput {
+    /// From a sidecar file, filed next to the document
+    Sidecar(DocumentInput, PathBuf),
+    /// From a rule that files documents unattended
+    Rule(DocumentInput),
+    /// From a rule whose proposal the user confirms first
+    Proposal(DocumentInput),
 }
 
 /// Unified processor that handles both media and documents intelligently
@@ -284,8 +543,8 @@ where
 
 impl<E, F, P> UnifiedProcessor<E, F, P>
 where
-    E: MetadataParser,
-    F: FileManager + Clone,
+    E: MetadataParser + Sync,
+    F: FileManager + Clone + Sync,
     P: UserPrompt + Clone,
 {
     pub fn new(metadata_parser: E, file_manager: F, prompter: P, life_config: LifeConfig) -> Self {
@@ -299,36 +558,74 @@ where
 
     /// Process all files in the life directory inbox with unified workflow
     pub fn process_life_directory(&self) -> Result<UnifiedProcessingResult> {
-        println!("Scanning inbox...");
+        log::info!("Scanning inbox...");
+
+        let file_paths = self
+            .file_manager
+            .read_directory(self.life_config.inbox_path())?;
+        self.process_files(file_paths, &ExportMetadata::new())
+    }
+
+    /// Import a Google Takeout or iCloud Photos export, naming media by the capture times
+    /// the export keeps in its JSON and CSV files when their EXIF has none
+    pub fn import_directory(&self, source: &Path) -> Result<UnifiedProcessingResult> {
+        log::info!("Scanning {}...", source.display());
+
+        let file_paths: Vec<PathBuf> = walk_files(&self.file_manager, source)?
+            .into_iter()
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| !name.starts_with('.'))
+            })
+            .collect();
+        let export = ExportMetadata::scan(&self.file_manager, &file_paths);
+        self.process_files(file_paths, &export)
+    }
+
+    fn process_files(
+        &self,
+        file_paths: Vec<PathBuf>,
+        export: &ExportMetadata,
+    ) -> Result<UnifiedProcessingResult> {
+        let mut result = UnifiedProcessingResult::new();
 
         // Step 1: Scan and categorize files
-        let categorized = self.categorize_files()?;
+        let (categorized, media_metadata) =
+            self.categorize_files(file_paths, export, &mut result.report)?;
 
-        println!(
+        log::info!(
             "Found {} media files, {} documents, {} unrecognized files",
             categorized.media_files.len(),
             categorized.document_files.len(),
             categorized.unknown_files.len()
         );
 
-        let mut result = UnifiedProcessingResult::new();
-
         // Step 2: Process media files automatically
         if !categorized.media_files.is_empty() {
-            println!("\nProcessing media files...");
-            self.process_media_files(&categorized.media_files, &mut result)?;
+            log::info!("\nProcessing media files...");
+            self.process_media_files(
+                &categorized.media_files,
+                media_metadata,
+                &categorized.media_sidecars,
+                &mut result,
+            )?;
         }
 
         // Step 3: Process documents interactively
         if !categorized.document_files.is_empty() {
-            println!("\nProcessing documents:");
+            log::info!("\nProcessing documents:");
             self.process_document_files(&categorized.document_files, &mut result)?;
         }
 
+        self.update_manifest(&result);
+
         // Step 4: Report results
-        result.files_skipped = categorized.unknown_files.len();
+        for file_path in &categorized.unknown_files {
+            result.add_outcome(file_path, FileOutcome::Skipped(SkipReason::Unrecognized));
+        }
         if !categorized.unknown_files.is_empty() {
-            println!(
+            log::info!(
                 "\n{} unrecognized files remain in inbox.",
                 categorized.unknown_files.len()
             );
@@ -337,96 +634,477 @@ where
         Ok(result)
     }
 
-    /// Scan inbox and categorize files by type
-    fn categorize_files(&self) -> Result<CategorizedFiles> {
-        let mut categorized = CategorizedFiles::new();
-        let inbox_path = self.life_config.inbox_path();
+    /// Add the files moved into the archive to the manifest.
+    ///
+    /// The run has already succeeded, so a manifest that cannot be updated is only a warning.
+    fn update_manifest(&self, result: &UnifiedProcessingResult) {
+        let manifest_file = self.life_config.manifest_file();
+        let mut manifest = match Manifest::load_from_file(&manifest_file, &self.file_manager) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                log::warn!("Manifest not updated: {e}");
+                return;
+            }
+        };
+
+        let algorithm = self.life_config.hash_algorithm;
+        let mut archived = Vec::new();
+        for file_result in &result.outcomes {
+            let FileOutcome::Moved(target_path) = &file_result.outcome else {
+                continue;
+            };
+            let record = result.report.get(&file_result.path);
+            let metadata_path = record.and_then(|record| record.metadata_path.as_ref());
+
+            // Media hashed while reading keep that hash unless XMP was written into them;
+            // documents are hashed now
+            let known_hash = record
+                .filter(|_| metadata_path != Some(target_path))
+                .and_then(|record| record.hash.as_deref())
+                .and_then(|tagged| HashAlgorithm::parse_tagged(tagged).ok())
+                .filter(|(known, _)| *known == algorithm)
+                .map(|(_, digest)| digest.to_string());
+            archived.push((target_path, known_hash));
+            if let Some(metadata_path) = metadata_path.filter(|path| *path != target_path) {
+                archived.push((metadata_path, None));
+            }
+        }
 
-        let file_paths = self.file_manager.read_directory(&inbox_path)?;
+        let mut added = 0;
+        for (target_path, known_hash) in archived {
+            let Some(key) = Manifest::key(&self.life_config.life_path, target_path) else {
+                continue;
+            };
+            let entry = known_hash
+                .map_or_else(|| self.file_manager.hash_file(target_path, algorithm), Ok)
+                .and_then(|digest| {
+                    let size = self.file_manager.file_size(target_path)?;
+                    Ok(ManifestEntry::new(algorithm, &digest, size))
+                });
+
+            match entry {
+                Ok(entry) => {
+                    manifest.insert(key, entry);
+                    added += 1;
+                }
+                Err(e) => log::warn!(
+                    "Cannot add {} to the manifest: {}",
+                    target_path.display(),
+                    e
+                ),
+            }
+        }
 
+        if added == 0 {
+            return;
+        }
+        match manifest.save(&manifest_file, &self.file_manager) {
+            Ok(()) => log::debug!("Added {added} files to the manifest"),
+            Err(e) => log::warn!("Manifest not updated: {e}"),
+        }
+    }
+
+    /// Sort the inbox into media, documents and unrecognized files.
+    ///
+    /// Metadata is read and media files are hashed on the configured number of
+    /// workers; the returned map holds the metadata of each media file.
+    fn categorize_files(
+        &self,
+        file_paths: Vec<PathBuf>,
+        export: &ExportMetadata,
+        report: &mut RunReport,
+    ) -> Result<(CategorizedFiles, HashMap<PathBuf, FileMetadata>)> {
+        let mut categorized = CategorizedFiles::new();
+        let mut media_metadata = HashMap::new();
+        let geocoder = Geocoder::for_life(&self.life_config, &self.file_manager)?;
+
+        let mut candidates = Vec::new();
         for file_path in file_paths {
             if !self.file_manager.is_file(&file_path) {
                 continue;
             }
 
-            // Try to parse metadata to determine file type
-            match self.metadata_parser.parse_metadata(&file_path) {
-                Ok(metadata) => match metadata.file_type {
-                    FileType::Image | FileType::Video => {
-                        categorized.media_files.push(file_path);
-                    }
-                    FileType::Document => {
-                        categorized.document_files.push(file_path);
-                    }
-                    FileType::Unknown => {
-                        categorized.unknown_files.push(file_path);
+            // An export's JSON and CSV files are read already, and the JSON follows its media
+            if export.is_metadata_file(&file_path) {
+                continue;
+            }
+
+            // Sidecars are filed together with their document
+            if is_sidecar_file(&file_path) {
+                let has_document = sidecar_document_path(&file_path)
+                    .is_some_and(|document| self.file_manager.is_file(document));
+                if !has_document {
+                    report.record(&file_path);
+                    categorized.unknown_files.push(file_path);
+                }
+                continue;
+            }
+
+            report.record(&file_path);
+            candidates.push(file_path);
+        }
+
+        // Media sidecars follow their media file and are never prepared on their own
+        let (unmatched, mut media_sidecars) = match_media_sidecars(&candidates);
+        // An XMP or AAE file without its media file is still no document
+        let (orphaned_sidecars, to_prepare): (Vec<PathBuf>, Vec<PathBuf>) = unmatched
+            .into_iter()
+            .partition(|path| is_media_only_sidecar_file(path));
+        categorized.unknown_files.extend(orphaned_sidecars);
+
+        let total_bytes = to_prepare
+            .iter()
+            .map(|path| self.file_manager.file_size(path).unwrap_or(0))
+            .sum();
+        let mut progress = self.progress_indicator(to_prepare.len(), "Reading", total_bytes);
+        let hash_media = matches!(
+            self.life_config.handle_duplicates,
+            DuplicateHandling::AppendHash | DuplicateHandling::Sequence
+        );
+        let prepared_files = prepare_files(
+            &to_prepare,
+            &self.metadata_parser,
+            &self.file_manager,
+            self.life_config.workers,
+            self.life_config.hash_algorithm,
+            |metadata| hash_media && metadata.file_type.is_auto_processable(),
+            |prepared| progress.advance(prepared.size),
+        );
+        progress.finish();
+
+        // Categorize in inbox order, whatever order the workers finished in
+        for prepared in prepared_files {
+            let file_path = prepared.path;
+            let record = report.record(&file_path);
+            match prepared.metadata {
+                Ok(mut metadata) => {
+                    log::trace!("{}: {:?}", file_path.display(), metadata);
+                    record.set_metadata(&metadata);
+                    match metadata.file_type {
+                        FileType::Image | FileType::Video => {
+                            if export.apply(&file_path, &mut metadata)
+                                && let Some(datetime) = &metadata.datetime_original
+                            {
+                                record.set_datetime(datetime, DatetimeSource::Export);
+                            }
+                            if let Some(geocoder) = &geocoder {
+                                geocoder.locate(&mut metadata);
+                            }
+                            media_metadata.insert(file_path.clone(), metadata);
+                            categorized.media_files.push(file_path);
+                        }
+                        FileType::Document => {
+                            categorized.document_files.push(file_path);
+                        }
+                        FileType::Unknown => {
+                            categorized.unknown_files.push(file_path);
+                        }
                     }
-                },
-                Err(_) => {
+                }
+                Err(e) => {
                     // If we can't parse metadata, treat as unknown
+                    log::debug!("Cannot read metadata of {}: {}", file_path.display(), e);
                     categorized.unknown_files.push(file_path);
                 }
             }
         }
 
-        Ok(categorized)
+        // A sidecar of anything but media has nothing to follow
+        let orphaned: HashSet<PathBuf> = media_sidecars
+            .iter()
+            .filter(|(primary, _)| !media_metadata.contains_key(*primary))
+            .flat_map(|(_, sidecars)| sidecars.iter().map(|sidecar| sidecar.path.clone()))
+            .collect();
+        media_sidecars.retain(|primary, _| media_metadata.contains_key(primary));
+        for (primary, sidecars) in export.sidecars() {
+            if media_metadata.contains_key(primary) {
+                for sidecar in sidecars {
+                    report.record(&sidecar.path);
+                }
+                media_sidecars
+                    .entry(primary.clone())
+                    .or_default()
+                    .extend(sidecars.iter().cloned());
+            }
+        }
+        categorized.unknown_files.extend(
+            candidates
+                .into_iter()
+                .filter(|path| orphaned.contains(path)),
+        );
+        categorized.media_sidecars = media_sidecars;
+
+        Ok((categorized, media_metadata))
+    }
+
+    /// Progress over `total` files for the console verbosity
+    fn progress_indicator(
+        &self,
+        total: usize,
+        task_name: &str,
+        total_bytes: u64,
+    ) -> ProgressIndicator {
+        ProgressIndicator::new(total, task_name.to_string())
+            .with_total_bytes(total_bytes)
+            .follow_console()
     }
 
     /// Process media files using the standard media processing pipeline
     fn process_media_files(
         &self,
         media_files: &[PathBuf],
+        mut media_metadata: HashMap<PathBuf, FileMetadata>,
+        media_sidecars: &HashMap<PathBuf, Vec<MediaSidecar>>,
         result: &mut UnifiedProcessingResult,
     ) -> Result<()> {
         if media_files.is_empty() {
             return Ok(());
         }
 
-        // Create a FileProcessor with appropriate strategies for media processing
-        // Note: We create new instances since FileProcessor takes ownership
+        // Layouts that group files see the whole batch before any file is placed
+        let mut media_layout = self.life_config.media_layout.clone();
+        if media_layout.groups_files() {
+            let files: Vec<File> = media_files
+                .iter()
+                .filter_map(|path| {
+                    let metadata = media_metadata.get(path)?.clone();
+                    Some(File::new(path).with_metadata(metadata))
+                })
+                .collect();
+            media_layout.organize_batch(&files)?;
+            if let MediaLayout::Events(organizer) = &mut media_layout
+                && self.life_config.name_events
+                && self.life_config.interactive
+            {
+                self.name_events(organizer)?;
+            }
+        }
+
+        // Create a FileProcessor with appropriate strategies for media processing,
+        // sharing this processor's parser and file manager
         let media_processor = FileProcessor::new(
-            RexifParser::new(),
-            StdFileManager::new(),
+            &self.metadata_parser,
+            self.file_manager.clone(),
             TimestampNamingStrategy::new(),
-            MonthlyOrganizer::new(),
+            media_layout,
             LifeDirectoryResolver::new(),
             self.life_config.to_processing_config(),
         );
 
-        // Process each media file through the standard pipeline
-        for (i, file_path) in media_files.iter().enumerate() {
-            print!(
-                "\r  Processing media file {} of {}...",
-                i + 1,
-                media_files.len()
-            );
+        // RAW+JPEG pairs and Live Photos are named and filed as one unit
+        let mut groups = group_companions(media_files, &media_metadata);
+        // Sequence numbers follow the order files are filed in, so file in shooting order
+        if self.life_config.handle_duplicates == DuplicateHandling::Sequence {
+            groups.sort_by_cached_key(|group| {
+                shooting_order(&group.primary, media_metadata.get(&group.primary))
+            });
+        }
 
-            match media_processor.process_single_file(file_path) {
-                Ok(()) => {
-                    result.media_processed += 1;
+        let sizes: HashMap<&PathBuf, u64> = media_files
+            .iter()
+            .map(|path| (path, self.file_manager.file_size(path).unwrap_or(0)))
+            .collect();
+        let mut progress =
+            self.progress_indicator(media_files.len(), "Media", sizes.values().sum());
+        let metadata_writer =
+            MetadataWriter::new(self.file_manager.clone(), self.life_config.write_metadata);
+
+        // Name and move one group at a time, in inbox order, so target names are
+        // claimed deterministically and never by two files at once
+        for group in &groups {
+            let primary_metadata = media_metadata.get(&group.primary).cloned();
+            let record = result.report.record(&group.primary);
+            let outcome = match media_metadata.remove(&group.primary) {
+                Some(metadata) => {
+                    media_processor.process_prepared_file(&group.primary, metadata, record)
                 }
-                Err(e) => {
-                    result.files_failed += 1;
-                    let error_msg = format!("{}: {}", file_path.display(), e);
-                    result.errors.push(error_msg.clone());
+                None => media_processor.process_single_file_recorded(&group.primary, record),
+            };
+            let primary_target = outcome.as_ref().ok().cloned();
+            self.record_media_outcome(&group.primary, outcome, result, &mut progress);
+            progress.advance(sizes[&group.primary]);
+            self.process_media_sidecars(
+                &media_processor,
+                media_sidecars.get(&group.primary),
+                primary_target.as_deref(),
+                result,
+                &mut progress,
+            );
+            self.write_media_metadata(
+                &metadata_writer,
+                &group.primary,
+                primary_target.as_deref(),
+                result,
+                &mut progress,
+            );
 
-                    // Only log error if it's not something we should skip
-                    if !self.should_skip_media_error(&e) {
-                        eprintln!("\n  Error processing {}: {}", file_path.display(), e);
+            for companion in &group.companions {
+                let record = result.report.record(companion);
+                let outcome = match (media_metadata.remove(companion), &primary_target) {
+                    (Some(metadata), Some(primary_target)) => {
+                        let metadata = match &primary_metadata {
+                            Some(primary) => merge_metadata(metadata, primary),
+                            None => metadata,
+                        };
+                        media_processor.process_companion(
+                            companion,
+                            metadata,
+                            primary_target,
+                            record,
+                        )
                     }
-                }
+                    // Without a filed primary the companion is filed on its own
+                    (Some(metadata), None) => {
+                        media_processor.process_prepared_file(companion, metadata, record)
+                    }
+                    (None, _) => media_processor.process_single_file_recorded(companion, record),
+                };
+                let companion_target = outcome.as_ref().ok().cloned();
+                self.record_media_outcome(companion, outcome, result, &mut progress);
+                progress.advance(sizes[companion]);
+                self.process_media_sidecars(
+                    &media_processor,
+                    media_sidecars.get(companion),
+                    companion_target.as_deref(),
+                    result,
+                    &mut progress,
+                );
+                self.write_media_metadata(
+                    &metadata_writer,
+                    companion,
+                    companion_target.as_deref(),
+                    result,
+                    &mut progress,
+                );
             }
         }
+        progress.finish();
 
-        println!("\r  ✓ Processed {} media files", result.media_processed);
+        log::info!("  ✓ Processed {} media files", result.media_processed);
 
         if result.files_failed > 0 {
-            println!("  {} files failed to process", result.files_failed);
+            log::warn!("{} files failed to process", result.files_failed);
         }
 
         Ok(())
     }
 
+    /// Ask for the name of each event; cancelling keeps the remaining default names
+    fn name_events(&self, organizer: &mut EventOrganizer) -> Result<()> {
+        for index in 0..organizer.events().len() {
+            let event = &organizer.events()[index];
+            let (start, end) = (event.start.format("%Y-%m-%d"), event.end.format("%Y-%m-%d"));
+            let days = if event.start.date() == event.end.date() {
+                start.to_string()
+            } else {
+                format!("{start} to {end}")
+            };
+            let message = format!("Name for the {} files of {}", event.files.len(), days);
+            match self
+                .prompter
+                .prompt_string(&message, Some(DEFAULT_EVENT_NAME))
+            {
+                Ok(name) => organizer.name_event(index, &name),
+                Err(CleanboxError::UserCancelled) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the sidecars of a media file after it, or leave them in the inbox if it
+    /// was not filed
+    fn process_media_sidecars<N, O, R>(
+        &self,
+        media_processor: &FileProcessor<&E, F, N, O, R>,
+        sidecars: Option<&Vec<MediaSidecar>>,
+        primary_target: Option<&Path>,
+        result: &mut UnifiedProcessingResult,
+        progress: &mut ProgressIndicator,
+    ) where
+        N: NamingStrategy,
+        O: OrganizationStrategy,
+        R: BasePathResolver,
+    {
+        for sidecar in sidecars.into_iter().flatten() {
+            let Some(primary_target) = primary_target else {
+                result.add_outcome(
+                    &sidecar.path,
+                    FileOutcome::Skipped(SkipReason::PrimaryNotFiled),
+                );
+                continue;
+            };
+
+            let record = result.report.record(&sidecar.path);
+            let outcome = match media_processor.process_sidecar(sidecar, primary_target, record) {
+                Ok(target_path) => FileOutcome::Moved(target_path),
+                Err(e) => {
+                    progress.clear();
+                    log::error!("Cannot move sidecar {}: {}", sidecar.path.display(), e);
+                    FileOutcome::Failed(e)
+                }
+            };
+            result.add_outcome(&sidecar.path, outcome);
+        }
+    }
+
+    /// Write the capture time of filed media back as XMP when it came from a companion or
+    /// an export rather than the file's own EXIF. A failed write only warns.
+    fn write_media_metadata(
+        &self,
+        metadata_writer: &MetadataWriter<F>,
+        file_path: &Path,
+        target_path: Option<&Path>,
+        result: &mut UnifiedProcessingResult,
+        progress: &mut ProgressIndicator,
+    ) {
+        let Some(target_path) = target_path else {
+            return;
+        };
+        let record = result.report.record(file_path);
+        let Some(datetime) = record.datetime.clone().filter(|_| {
+            matches!(
+                record.datetime_source,
+                Some(DatetimeSource::Companion | DatetimeSource::Export)
+            )
+        }) else {
+            return;
+        };
+
+        match metadata_writer.write_media(target_path, &datetime) {
+            Ok(metadata_path) => record.metadata_path = metadata_path,
+            Err(e) => {
+                progress.clear();
+                log::warn!("Metadata not written for {}: {}", target_path.display(), e);
+            }
+        }
+    }
+
+    /// Count a media file as processed, skipped or failed and add it to the report
+    fn record_media_outcome(
+        &self,
+        file_path: &Path,
+        outcome: Result<PathBuf>,
+        result: &mut UnifiedProcessingResult,
+        progress: &mut ProgressIndicator,
+    ) {
+        match outcome {
+            Ok(target_path) => {
+                result.media_processed += 1;
+                result.add_outcome(file_path, FileOutcome::Moved(target_path));
+            }
+            Err(e) if self.should_skip_media_error(&e) => {
+                log::debug!("Skipped {}: {}", file_path.display(), e);
+                result.add_outcome(file_path, FileOutcome::Skipped(SkipReason::Unsupported(e)));
+            }
+            Err(e) => {
+                progress.clear();
+                log::error!("Cannot process {}: {}", file_path.display(), e);
+                result.add_outcome(file_path, FileOutcome::Failed(e));
+            }
+        }
+    }
+
     /// Check if media processing error should be skipped (not logged as error)
     fn should_skip_media_error(&self, error: &CleanboxError) -> bool {
         matches!(
@@ -436,6 +1114,10 @@ where
     }
 
     /// Process document files using interactive workflow
+    ///
+    /// Each document is offered a per-document action first. Filing is delayed by
+    /// one document so that "back" can still revise the previous answers before
+    /// the file is moved.
     fn process_document_files(
         &self,
         document_files: &[PathBuf],
@@ -445,7 +1127,32 @@ where
         let document_organizer = DocumentOrganizer::new();
 
         // Load tag dictionary
-        let tag_dict = TagDictionary::load_from_file(self.life_config.tags_file())?;
+        let mut tag_dict = self.load_tag_dictionary()?;
+        let rules =
+            DocumentRules::load_from_file(self.life_config.rules_file(), &self.file_manager)?;
+
+        let interactive_files = self.process_automatic_documents(
+            document_files,
+            &rules,
+            &mut tag_dict,
+            &document_naming,
+            &document_organizer,
+            result,
+        );
+
+        if interactive_files.is_empty() || !self.life_config.interactive {
+            if !interactive_files.is_empty() {
+                log::info!(
+                    "\n{} documents need input and remain in inbox.",
+                    interactive_files.len()
+                );
+                for (file_path, _) in &interactive_files {
+                    result.add_outcome(file_path, FileOutcome::Skipped(SkipReason::NeedsInput));
+                }
+            }
+            self.save_tag_dictionary(&tag_dict)?;
+            return Ok(());
+        }
 
         // Create document input collector
         let mut document_collector = DocumentInputCollector::new(
@@ -454,63 +1161,392 @@ where
             self.file_manager.clone(),
             self.life_config.tags_file().to_path_buf(),
         );
+        let action_prompt = DocumentActionPrompt::new(self.prompter.clone());
 
-        for file_path in document_files.iter() {
-            println!(
-                "\nFile: {}",
-                file_path.file_name().unwrap_or_default().to_string_lossy()
-            );
+        let mut proposals: HashMap<PathBuf, DocumentInput> = HashMap::new();
+        let mut queue: VecDeque<PathBuf> = VecDeque::new();
+        for (file_path, proposal) in interactive_files {
+            if let Some(proposal) = proposal {
+                proposals.insert(file_path.clone(), proposal);
+            }
+            queue.push_back(file_path);
+        }
+        let mut pending: Option<(PathBuf, DocumentInput, DatetimeSource)> = None;
+        let mut deferred: HashSet<PathBuf> = HashSet::new();
+        // Answers of documents the user went back to, offered again as defaults
+        let mut revisions: HashMap<PathBuf, DocumentInput> = HashMap::new();
 
-            // Get document input from user
+        while let Some(file_path) = queue.pop_front() {
             let filename = file_path.file_name().unwrap_or_default().to_string_lossy();
-            let document_input = match document_collector.collect_input(&filename) {
+            self.prompter.show(&format!("\nFile: {filename}"));
+
+            // A rule proposal only needs a single keypress to accept
+            // The proposal is kept until accepted so a deferred document is offered it again
+            if let Some(proposal) = proposals.get(&file_path).cloned() {
+                self.prompter
+                    .show(&format!("  Rule proposal: {}", proposal.to_filename_stem()));
+                match self.prompter.prompt_confirmation("Accept proposal?", true) {
+                    Ok(true) => {
+                        proposals.remove(&file_path);
+                        if let Err(e) = document_collector.record_tags(&proposal.tags) {
+                            log::warn!("Failed to record tags: {e}");
+                        }
+                        if let Some((previous_path, previous_input, source)) = pending.take() {
+                            self.commit_document(
+                                &previous_path,
+                                &previous_input,
+                                source,
+                                &document_naming,
+                                &document_organizer,
+                                result,
+                            );
+                        }
+                        pending = Some((file_path, proposal, DatetimeSource::Rule));
+                        continue;
+                    }
+                    Ok(false) => {}
+                    Err(CleanboxError::UserCancelled) => {
+                        log::info!("Processing cancelled by user.");
+                        queue.push_front(file_path);
+                        break;
+                    }
+                    Err(e) => {
+                        result.add_outcome(&file_path, FileOutcome::Failed(e));
+                        break;
+                    }
+                }
+            }
+
+            let action = match action_prompt.prompt_action(pending.is_some()) {
+                Ok(action) => action,
+                Err(CleanboxError::UserCancelled) => {
+                    log::info!("Processing cancelled by user.");
+                    queue.push_front(file_path);
+                    break;
+                }
+                Err(e) => {
+                    result.add_outcome(&file_path, FileOutcome::Failed(e));
+                    break;
+                }
+            };
+
+            match action {
+                DocumentAction::Process => {}
+                DocumentAction::Skip => {
+                    self.prompter.show("  Skipped, left in inbox.");
+                    result.add_outcome(&file_path, FileOutcome::Skipped(SkipReason::UserSkipped));
+                    continue;
+                }
+                DocumentAction::Defer => {
+                    self.prompter.show("  Deferred to the end of the queue.");
+                    deferred.insert(file_path.clone());
+                    queue.push_back(file_path);
+                    continue;
+                }
+                DocumentAction::Back => {
+                    if let Some((previous_path, previous_input, _)) = pending.take() {
+                        self.prompter.show(&format!(
+                            "  Previous answer: {}",
+                            previous_input.to_filename_stem()
+                        ));
+                        queue.push_front(file_path);
+                        queue.push_front(previous_path.clone());
+                        revisions.insert(previous_path, previous_input);
+                    }
+                    continue;
+                }
+                DocumentAction::Open => {
+                    if let Err(e) =
+                        open_in_viewer(&file_path, self.life_config.viewer_command.as_deref())
+                    {
+                        log::warn!("{e}");
+                    }
+                    queue.push_front(file_path);
+                    continue;
+                }
+            }
+
+            // Get document input from user
+            let collected = match revisions.remove(&file_path) {
+                Some(previous) => document_collector.revise_input(&filename, &previous),
+                None => document_collector.collect_input(&filename),
+            };
+            let document_input = match collected {
                 Ok(input) => input,
                 Err(CleanboxError::UserCancelled) => {
-                    println!("Processing cancelled by user.");
+                    log::info!("Processing cancelled by user.");
+                    queue.push_front(file_path);
                     break;
                 }
                 Err(e) => {
-                    result.files_failed += 1;
-                    result
-                        .errors
-                        .push(format!("{}: {}", file_path.display(), e));
+                    result.add_outcome(&file_path, FileOutcome::Failed(e));
                     continue;
                 }
             };
 
-            // Process the document
-            match self.process_single_document(
-                file_path,
-                &document_input,
+            // Commit the previous document now that it can no longer be revised
+            if let Some((previous_path, previous_input, source)) = pending.take() {
+                self.commit_document(
+                    &previous_path,
+                    &previous_input,
+                    source,
+                    &document_naming,
+                    &document_organizer,
+                    result,
+                );
+            }
+            pending = Some((file_path, document_input, DatetimeSource::User));
+        }
+
+        if let Some((previous_path, previous_input, source)) = pending.take() {
+            self.commit_document(
+                &previous_path,
+                &previous_input,
+                source,
                 &document_naming,
                 &document_organizer,
-            ) {
-                Ok(()) => {
-                    result.documents_processed += 1;
+                result,
+            );
+        }
+
+        // Whatever is still queued stays in the inbox
+        for file_path in queue {
+            let outcome = if deferred.contains(&file_path) {
+                FileOutcome::Deferred
+            } else {
+                FileOutcome::Skipped(SkipReason::Cancelled)
+            };
+            result.add_outcome(&file_path, outcome);
+        }
+
+        // Save updated tag dictionary after processing all documents
+        self.save_tag_dictionary(document_collector.tag_dictionary())?;
+
+        Ok(())
+    }
+
+    fn load_tag_dictionary(&self) -> Result<TagDictionary> {
+        let tags_file = self.life_config.tags_file();
+        let content = self
+            .file_manager
+            .read_file(&tags_file)
+            .and_then(|bytes| {
+                String::from_utf8(bytes)
+                    .map_err(|e| CleanboxError::TagDictionaryCorrupted(e.to_string()))
+            })
+            .map_err(|e| {
+                CleanboxError::TagDictionaryCorrupted(format!(
+                    "Cannot read tags file at {}: {}",
+                    tags_file.display(),
+                    e
+                ))
+            })?;
+
+        TagDictionary::parse(&content)
+    }
+
+    fn save_tag_dictionary(&self, tag_dict: &TagDictionary) -> Result<()> {
+        tag_dict.save_to_file(self.life_config.tags_file(), &self.file_manager)
+    }
+
+    /// File documents that have a sidecar or an unattended rule without prompting.
+    ///
+    /// Returns the documents that still need the user, each with the proposal of a
+    /// matching confirm rule if there is one.
+    fn process_automatic_documents(
+        &self,
+        document_files: &[PathBuf],
+        rules: &DocumentRules,
+        tag_dict: &mut TagDictionary,
+        naming_strategy: &DocumentNamingStrategy,
+        organizer: &DocumentOrganizer,
+        result: &mut UnifiedProcessingResult,
+    ) -> Vec<(PathBuf, Option<DocumentInput>)> {
+        let mut interactive_files = Vec::new();
+
+        for file_path in document_files {
+            // The MIME type was read while sorting the inbox
+            let mime_type = result
+                .report
+                .get(file_path)
+                .and_then(|record| record.mime_type.clone())
+                .unwrap_or_default();
+            let automatic = self.automatic_document_input(file_path, &mime_type, rules);
+            let (document_input, sidecar) = match automatic {
+                Ok(Some(AutomaticInput::Sidecar(input, sidecar))) => (input, Some(sidecar)),
+                Ok(Some(AutomaticInput::Rule(input))) => (input, None),
+                Ok(Some(AutomaticInput::Proposal(input))) => {
+                    interactive_files.push((file_path.clone(), Some(input)));
+                    continue;
+                }
+                Ok(None) => {
+                    interactive_files.push((file_path.clone(), None));
+                    continue;
                 }
                 Err(e) => {
-                    result.files_failed += 1;
-                    result
-                        .errors
-                        .push(format!("{}: {}", file_path.display(), e));
+                    result.add_outcome(file_path, FileOutcome::Failed(e));
+                    continue;
+                }
+            };
+
+            log::info!(
+                "\nFile: {} (automatic)",
+                file_path.file_name().unwrap_or_default().to_string_lossy()
+            );
+
+            let source = if sidecar.is_some() {
+                DatetimeSource::Sidecar
+            } else {
+                DatetimeSource::Rule
+            };
+            let Some(target_path) = self.commit_document(
+                file_path,
+                &document_input,
+                source,
+                naming_strategy,
+                organizer,
+                result,
+            ) else {
+                continue;
+            };
+
+            for tag in &document_input.tags {
+                if let Err(e) = tag_dict.add_tag(tag.clone()) {
+                    log::warn!("Failed to record tag '{tag}': {e}");
+                }
+            }
+
+            if let Some(sidecar) = sidecar {
+                self.file_document_sidecar(&sidecar, &target_path, result);
+            }
+        }
+
+        interactive_files
+    }
+
+    /// Move the sidecar that answered for a document next to the filed document, so the
+    /// answers are kept with it
+    fn file_document_sidecar(
+        &self,
+        sidecar_path: &Path,
+        document_target: &Path,
+        result: &mut UnifiedProcessingResult,
+    ) {
+        let record = result.report.record(sidecar_path);
+        let moved = document_sidecar(sidecar_path)
+            .ok_or_else(|| CleanboxError::InvalidPath(sidecar_path.display().to_string()))
+            .and_then(|sidecar| sidecar.target(document_target))
+            .and_then(|target_path| {
+                if self.file_manager.file_exists(&target_path) {
+                    return Err(CleanboxError::FileAlreadyExists(
+                        target_path.display().to_string(),
+                    ));
                 }
+                record.target_path = Some(target_path.clone());
+                self.file_manager.move_file(sidecar_path, &target_path)?;
+                Ok(target_path)
+            });
+
+        let outcome = match moved {
+            Ok(target_path) => FileOutcome::Moved(target_path),
+            Err(e) => {
+                log::error!("Cannot move sidecar {}: {}", sidecar_path.display(), e);
+                FileOutcome::Failed(e)
             }
+        };
+        result.add_outcome(sidecar_path, outcome);
+    }
+
+    /// Look up document input from a sidecar file or, failing that, the first matching rule
+    fn automatic_document_input(
+        &self,
+        file_path: &Path,
+        mime_type: &str,
+        rules: &DocumentRules,
+    ) -> Result<Option<AutomaticInput>> {
+        if let Some(sidecar_path) = find_sidecar(file_path, &self.file_manager) {
+            let sidecar = DocumentSidecar::load_from_file(&sidecar_path, &self.file_manager)?;
+            let input = sidecar.to_document_input(file_path, &self.file_manager)?;
+            return Ok(Some(AutomaticInput::Sidecar(input, sidecar_path)));
         }
 
-        // Save updated tag dictionary after processing all documents
-        document_collector.save_tag_dictionary(&self.life_config.tags_file())?;
+        if rules.is_empty() {
+            return Ok(None);
+        }
 
-        Ok(())
+        let filename = file_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let candidate = RuleCandidate {
+            filename,
+            mime_type,
+            size: self.file_manager.file_size(file_path)?,
+        };
+
+        match rules.find_match(&candidate) {
+            Some(rule) => {
+                let input = rule.propose(&candidate, file_path, &self.file_manager)?;
+                if rule.confirm {
+                    Ok(Some(AutomaticInput::Proposal(input)))
+                } else {
+                    Ok(Some(AutomaticInput::Rule(input)))
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// File a document whose input has been confirmed and record the outcome, returning
+    /// where it was filed
+    fn commit_document(
+        &self,
+        file_path: &Path,
+        document_input: &DocumentInput,
+        source: DatetimeSource,
+        naming_strategy: &DocumentNamingStrategy,
+        organizer: &DocumentOrganizer,
+        result: &mut UnifiedProcessingResult,
+    ) -> Option<PathBuf> {
+        let record = result.report.record(file_path);
+        record.set_datetime(&document_input.date, source);
+
+        match self.process_single_document(
+            file_path,
+            document_input,
+            naming_strategy,
+            organizer,
+            record,
+        ) {
+            Ok(target_path) => {
+                let metadata_writer =
+                    MetadataWriter::new(self.file_manager.clone(), self.life_config.write_metadata);
+                match metadata_writer.write_document(&target_path, document_input) {
+                    Ok(metadata_path) => record.metadata_path = metadata_path,
+                    Err(e) => {
+                        log::warn!("Metadata not written for {}: {}", target_path.display(), e)
+                    }
+                }
+                result.documents_processed += 1;
+                result.add_outcome(file_path, FileOutcome::Moved(target_path.clone()));
+                Some(target_path)
+            }
+            Err(e) => {
+                result.add_outcome(file_path, FileOutcome::Failed(e));
+                None
+            }
+        }
     }
 
     /// Process a single document file
     fn process_single_document(
         &self,
         file_path: &Path,
-        document_input: &crate::document::DocumentInput,
+        document_input: &DocumentInput,
         naming_strategy: &DocumentNamingStrategy,
         organizer: &DocumentOrganizer,
-    ) -> Result<()> {
+        record: &mut FileRecord,
+    ) -> Result<PathBuf> {
         // Get file extension
         let extension = file_path
             .extension()
@@ -537,14 +1573,16 @@ where
 
         // Handle duplicates if file already exists
         if self.file_manager.file_exists(&target_path) {
+            record.duplicate_action = Some(self.life_config.handle_duplicates.clone());
             target_path = self.handle_document_duplicate(file_path, &target_path)?;
         }
+        record.target_path = Some(target_path.clone());
 
         // Move the file
         self.file_manager.move_file(file_path, &target_path)?;
-        println!("  → {}", target_path.display());
+        log::info!("  → {}", target_path.display());
 
-        Ok(())
+        Ok(target_path)
     }
 
     /// Handle duplicate document files by appending hash
@@ -557,8 +1595,17 @@ where
             DuplicateHandling::Error => Err(CleanboxError::FileAlreadyExists(
                 target_path.display().to_string(),
             )),
+            DuplicateHandling::Sequence => sequence_target(
+                &self.file_manager,
+                source_path,
+                target_path,
+                None,
+                self.life_config.hash_algorithm,
+            ),
             DuplicateHandling::AppendHash => {
-                let hash = self.file_manager.calculate_file_hash(source_path)?;
+                let hash = self
+                    .file_manager
+                    .hash_file(source_path, self.life_config.hash_algorithm)?;
                 let hash_suffix =
                     FileHasher::generate_hash_suffix(&hash, self.life_config.hash_length);
 
@@ -579,7 +1626,15 @@ mod tests {
     use super::*;
     use crate::config::DuplicateHandling;
     use crate::filesystem::MockFileManager;
-    use crate::media::{File, FileMetadata};
+    use crate::filesystem::StdFileManager;
+    use crate::hashing::ContentHasher;
+    use crate::json_prompt::JsonLinesPrompt;
+    use crate::media::{File, FileMetadata, GpsPosition};
+    use crate::metadata::RexifParser;
+    use crate::report::RecordStatus;
+    use crate::testing::{InMemoryFileManager, ScriptedPrompt, StaticMetadataParser};
+    use crate::verify::ArchiveVerifier;
+    use crate::xmp::MetadataWriting;
     use std::collections::HashMap;
     use std::path::{Path, PathBuf};
 
@@ -685,31 +1740,47 @@ mod tests {
         assert_eq!(result.processed_files, 0);
         assert_eq!(result.skipped_files, 0);
         assert_eq!(result.failed_files, 0);
-        assert!(result.errors.is_empty());
+        assert!(result.outcomes.is_empty());
     }
 
     #[test]
-    fn test_processing_result_add_error() {
+    fn test_processing_result_add_failure() {
         let mut result = ProcessingResult::new();
-        result.add_error("Test error".to_string());
+        result.add_outcome(FileResult::new(
+            Path::new("/inbox/a.jpg"),
+            FileOutcome::Failed(CleanboxError::Exif("Test error".to_string())),
+        ));
 
         assert_eq!(result.failed_files, 1);
-        assert_eq!(result.errors.len(), 1);
-        assert_eq!(result.errors[0], "Test error");
+        let failures: Vec<_> = result.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].path, PathBuf::from("/inbox/a.jpg"));
+        assert!(matches!(failures[0].error(), Some(CleanboxError::Exif(_))));
     }
 
     #[test]
     fn test_processing_result_skip_file() {
         let mut result = ProcessingResult::new();
-        result.skip_file();
+        result.add_outcome(FileResult::new(
+            Path::new("/inbox/a.txt"),
+            FileOutcome::Skipped(SkipReason::Unrecognized),
+        ));
+        result.add_outcome(FileResult::new(
+            Path::new("/inbox/b.pdf"),
+            FileOutcome::Deferred,
+        ));
 
-        assert_eq!(result.skipped_files, 1);
+        assert_eq!(result.skipped_files, 2);
+        assert_eq!(result.failures().count(), 0);
     }
 
     #[test]
     fn test_processing_result_process_file() {
         let mut result = ProcessingResult::new();
-        result.process_file();
+        result.add_outcome(FileResult::new(
+            Path::new("/inbox/a.jpg"),
+            FileOutcome::Moved(PathBuf::from("/media/a.jpg")),
+        ));
 
         assert_eq!(result.processed_files, 1);
     }
@@ -731,12 +1802,7 @@ mod tests {
             ))
         );
         assert!(processor.should_skip_error(&CleanboxError::Exif("No EXIF data".to_string())));
-        assert!(
-            !processor.should_skip_error(&CleanboxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "test"
-            )))
-        );
+        assert!(!processor.should_skip_error(&CleanboxError::Io(std::io::Error::other("test"))));
         assert!(!processor.should_skip_error(&CleanboxError::InvalidPath("/invalid".to_string())));
     }
 
@@ -756,7 +1822,7 @@ mod tests {
 
         let source = Path::new("/temp/file.jpg");
         let target = Path::new("/media/target.jpg");
-        let result = processor.handle_duplicate(source, target);
+        let result = processor.handle_duplicate(source, target, None);
 
         assert!(result.is_err());
         assert!(matches!(
@@ -781,7 +1847,7 @@ mod tests {
 
         let source = Path::new("/temp/file.jpg");
         let target = Path::new("/media/target.jpg");
-        let result = processor.handle_duplicate(source, target).unwrap();
+        let result = processor.handle_duplicate(source, target, None).unwrap();
 
         assert_eq!(result, target);
     }
@@ -806,11 +1872,827 @@ mod tests {
 
         let source = Path::new("/temp/file.jpg");
         let target = Path::new("/media/target.jpg");
-        let result = processor.handle_duplicate(source, target).unwrap();
+        let result = processor.handle_duplicate(source, target, None).unwrap();
 
         let filename = result.file_name().unwrap().to_str().unwrap();
         assert!(filename.starts_with("target_"));
         assert!(filename.ends_with(".jpg"));
         assert!(filename.len() > "target.jpg".len()); // Should have hash appended
     }
+
+    // Prompt that fails on use, for workflows that must not ask the user anything
+    #[derive(Clone)]
+    struct UnusedPrompt;
+
+    impl UserPrompt for UnusedPrompt {
+        fn prompt_string(&self, message: &str, _default: Option<&str>) -> Result<String> {
+            Err(CleanboxError::InvalidUserInput(format!(
+                "Unexpected prompt: {message}"
+            )))
+        }
+
+        fn prompt_confirmation(&self, message: &str, _default: bool) -> Result<bool> {
+            Err(CleanboxError::InvalidUserInput(format!(
+                "Unexpected prompt: {message}"
+            )))
+        }
+
+        fn prompt_selection(&self, message: &str, _options: &[&str]) -> Result<usize> {
+            Err(CleanboxError::InvalidUserInput(format!(
+                "Unexpected prompt: {message}"
+            )))
+        }
+    }
+
+    fn create_life_directory() -> tempfile::TempDir {
+        let life_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(life_dir.path().join("inbox")).unwrap();
+        std::fs::create_dir_all(life_dir.path().join("documents")).unwrap();
+        std::fs::write(life_dir.path().join("documents/tags.txt"), "finance\n").unwrap();
+        life_dir
+    }
+
+    fn create_unattended_processor(
+        life_config: LifeConfig,
+    ) -> UnifiedProcessor<RexifParser, StdFileManager, UnusedPrompt> {
+        UnifiedProcessor::new(
+            RexifParser::new(),
+            StdFileManager::new(),
+            UnusedPrompt,
+            life_config.with_interactive(false),
+        )
+    }
+
+    fn run_burst(workers: usize) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        let file_manager = InMemoryFileManager::new();
+        let mut parser = StaticMetadataParser::new();
+        // A burst of photos taken within the same second
+        for i in 0..12 {
+            let path = format!("/life/inbox/IMG_{i:04}.jpg");
+            file_manager.add_file(&path, format!("photo {i}").as_bytes());
+            parser = parser.with_datetime(&path, "2024-05-01_10-00-00");
+        }
+
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            LifeConfig::new(PathBuf::from("/life")).with_workers(workers),
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.media_processed, 12);
+
+        let targets = result
+            .report
+            .files()
+            .iter()
+            .map(|record| record.target_path.clone().unwrap())
+            .collect();
+        (targets, file_manager.files_under("/life/media"))
+    }
+
+    #[test]
+    fn test_parallel_media_processing_is_deterministic() {
+        let (serial_targets, serial_tree) = run_burst(1);
+        let (parallel_targets, parallel_tree) = run_burst(4);
+
+        assert_eq!(serial_targets, parallel_targets);
+        assert_eq!(serial_tree, parallel_tree);
+        assert_eq!(parallel_tree.len(), 12);
+        // The first file in inbox order claims the plain name
+        assert_eq!(
+            parallel_targets[0],
+            PathBuf::from("/life/media/2024/05/2024-05-01_10-00-00.jpg")
+        );
+    }
+
+    #[test]
+    fn test_media_without_datetime_is_skipped() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/inbox/IMG_0001.jpg", b"no exif");
+        let processor = UnifiedProcessor::new(
+            StaticMetadataParser::new(),
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            LifeConfig::new(PathBuf::from("/life")),
+        );
+
+        let result = processor.process_life_directory().unwrap();
+
+        assert_eq!(result.files_skipped, 1);
+        assert_eq!(result.files_failed, 0);
+        let record = result
+            .report
+            .get(Path::new("/life/inbox/IMG_0001.jpg"))
+            .unwrap();
+        assert_eq!(record.status, RecordStatus::Skipped);
+        assert_eq!(record.skip_reason, Some("unsupported"));
+        file_manager.assert_file("/life/inbox/IMG_0001.jpg");
+    }
+
+    #[test]
+    fn test_burst_is_ordered_by_subsecond_time() {
+        let file_manager = InMemoryFileManager::new();
+        let mut parser = StaticMetadataParser::new();
+        for (i, subsec) in ["120", "480", "480"].iter().enumerate() {
+            let path = format!("/life/inbox/IMG_{i:04}.jpg");
+            file_manager.add_file(&path, format!("photo {i}").as_bytes());
+            let metadata = FileMetadata::new("image/jpeg".to_string())
+                .with_datetime("2024-05-01_10-00-00".to_string())
+                .with_subsec_time(subsec.to_string());
+            parser = parser.with_metadata(&path, metadata);
+        }
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.jpg", b"earlier");
+
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            LifeConfig::new(PathBuf::from("/life")),
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.media_processed, 3);
+
+        let hash = ContentHasher::hash_bytes(HashAlgorithm::Sha1, b"photo 2");
+        let mut expected = vec![
+            "2024-05-01_10-00-00.jpg".to_string(),
+            "2024-05-01_10-00-00_120.jpg".to_string(),
+            "2024-05-01_10-00-00_480.jpg".to_string(),
+            // Same subsecond time from a second camera falls back to the hash
+            format!("2024-05-01_10-00-00_{}.jpg", &hash[..6]),
+        ];
+        expected.sort();
+        let names: Vec<String> = file_manager
+            .files_under("/life/media/2024/05")
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn test_sequence_numbers_follow_shooting_order() {
+        let file_manager = InMemoryFileManager::new();
+        let burst = [
+            ("IMG_0010.jpg", Some("400")),
+            ("IMG_0009.jpg", Some("100")),
+            ("IMG_0012.jpg", None),
+            ("IMG_0011.jpg", None),
+        ];
+        let mut parser = StaticMetadataParser::new();
+        for (name, subsec) in burst {
+            let path = format!("/life/inbox/{name}");
+            file_manager.add_file(&path, name.as_bytes());
+            let mut metadata = FileMetadata::new("image/jpeg".to_string())
+                .with_datetime("2024-05-01_10-00-00".to_string());
+            if let Some(subsec) = subsec {
+                metadata = metadata.with_subsec_time(subsec.to_string());
+            }
+            parser = parser.with_metadata(&path, metadata);
+        }
+        let life_config = LifeConfig::new(PathBuf::from("/life"))
+            .with_duplicate_handling(DuplicateHandling::Sequence);
+
+        let processor = UnifiedProcessor::new(
+            parser.clone(),
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            life_config.clone(),
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.media_processed, 4);
+
+        let archived = |name: &str| {
+            file_manager
+                .read_file(format!("/life/media/2024/05/2024-05-01_10-00-00{name}.jpg"))
+                .unwrap()
+        };
+        // Files without a subsecond time sort first, by the counter in their name
+        assert_eq!(archived(""), b"IMG_0011.jpg");
+        assert_eq!(archived("_01"), b"IMG_0012.jpg");
+        assert_eq!(archived("_02"), b"IMG_0009.jpg");
+        assert_eq!(archived("_03"), b"IMG_0010.jpg");
+
+        // Importing the same photos again adds nothing
+        file_manager.add_file("/life/inbox/IMG_0009.jpg", b"IMG_0009.jpg");
+        file_manager.add_file("/life/inbox/IMG_0013.jpg", b"IMG_0013.jpg");
+        let parser = parser.with_datetime("/life/inbox/IMG_0013.jpg", "2024-05-01_10-00-00");
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            life_config,
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.media_processed, 1);
+        assert_eq!(archived("_04"), b"IMG_0013.jpg");
+        assert_eq!(file_manager.files_under("/life/media").len(), 5);
+    }
+
+    #[test]
+    fn test_companions_are_filed_with_their_primary() {
+        let file_manager = InMemoryFileManager::new();
+        for name in [
+            "IMG_0001.HEIC",
+            "IMG_0001.MOV",
+            "DSC_0002.CR2",
+            "DSC_0002.JPG",
+        ] {
+            file_manager.add_file(format!("/life/inbox/{name}"), name.as_bytes());
+        }
+        // Only the photo of the Live Photo and the RAW file carry a datetime
+        let parser = StaticMetadataParser::new()
+            .with_datetime("/life/inbox/IMG_0001.HEIC", "2024-05-31_23-59-59")
+            .with_datetime("/life/inbox/DSC_0002.CR2", "2024-06-01_08-00-00");
+
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            LifeConfig::new(PathBuf::from("/life")),
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.media_processed, 4);
+
+        file_manager.assert_tree(
+            "/life/media",
+            &[
+                "2024/05/2024-05-31_23-59-59.HEIC",
+                "2024/05/2024-05-31_23-59-59.MOV",
+                "2024/06/2024-06-01_08-00-00.CR2",
+                "2024/06/2024-06-01_08-00-00.JPG",
+            ],
+        );
+        let video = result
+            .report
+            .files()
+            .iter()
+            .find(|record| record.path.ends_with("IMG_0001.MOV"))
+            .unwrap();
+        assert_eq!(video.datetime_source, Some(DatetimeSource::Companion));
+    }
+
+    #[test]
+    fn test_media_sidecars_follow_their_media_file() {
+        let file_manager = InMemoryFileManager::new();
+        for name in [
+            "IMG_0001.JPG",
+            "IMG_0001.xmp",
+            "IMG_0001.JPG.json",
+            "IMG_0001.AAE",
+            "IMG_0002.JPG",
+            "IMG_0002.xmp",
+            "notes.xmp",
+        ] {
+            file_manager.add_file(format!("/life/inbox/{name}"), name.as_bytes());
+        }
+        // IMG_0002.JPG has no datetime and stays in the inbox with its sidecar
+        let parser = StaticMetadataParser::new()
+            .with_datetime("/life/inbox/IMG_0001.JPG", "2024-05-01_10-00-00");
+
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            LifeConfig::new(PathBuf::from("/life")),
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.media_processed, 1);
+
+        file_manager.assert_tree(
+            "/life/media",
+            &[
+                "2024/05/2024-05-01_10-00-00.AAE",
+                "2024/05/2024-05-01_10-00-00.JPG",
+                "2024/05/2024-05-01_10-00-00.JPG.json",
+                "2024/05/2024-05-01_10-00-00.xmp",
+            ],
+        );
+        file_manager.assert_file("/life/inbox/IMG_0002.xmp");
+        file_manager.assert_file("/life/inbox/notes.xmp");
+        let outcome = |name: &str| {
+            result
+                .outcomes
+                .iter()
+                .find(|outcome| outcome.path.ends_with(name))
+                .map(|outcome| outcome.outcome.to_string())
+                .unwrap()
+        };
+        assert_eq!(
+            outcome("IMG_0002.xmp"),
+            "skipped: its media file was not filed"
+        );
+        assert_eq!(outcome("notes.xmp"), "skipped: unrecognized file type");
+    }
+
+    #[test]
+    fn test_import_dates_takeout_media_by_their_json() {
+        let file_manager = InMemoryFileManager::new();
+        let album = "/export/Takeout/Google Photos/Photos from 2024";
+        file_manager.add_file(format!("{album}/IMG_0001.JPG"), b"photo");
+        file_manager.add_file(
+            format!("{album}/IMG_0001.JPG.supplemental-metadata.json"),
+            r#"{"title": "IMG_0001.JPG", "photoTakenTime": {"timestamp": "1715774400"}}"#,
+        );
+        file_manager.add_file(format!("{album}/metadata.json"), r#"{"title": "2024"}"#);
+
+        let processor = UnifiedProcessor::new(
+            StaticMetadataParser::new(),
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            LifeConfig::new(PathBuf::from("/life")),
+        );
+        let result = processor.import_directory(Path::new("/export")).unwrap();
+        assert_eq!(result.media_processed, 1);
+
+        let taken = chrono::DateTime::from_timestamp(1715774400, 0)
+            .unwrap()
+            .with_timezone(&chrono::Local);
+        let name = taken.format("%Y-%m-%d_%H-%M-%S");
+        file_manager.assert_tree(
+            "/life/media",
+            &[
+                &format!("2024/05/{name}.JPG"),
+                &format!("2024/05/{name}.JPG.json"),
+            ],
+        );
+        file_manager.assert_file(format!("{album}/metadata.json"));
+        let photo = result
+            .report
+            .files()
+            .iter()
+            .find(|record| record.path.ends_with("IMG_0001.JPG"))
+            .unwrap();
+        assert_eq!(photo.datetime_source, Some(DatetimeSource::Export));
+    }
+
+    #[test]
+    fn test_media_is_filed_by_the_city_of_its_gps_position() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/inbox/IMG_0001.jpg", b"tram");
+        file_manager.add_file("/life/inbox/IMG_0002.jpg", b"sea");
+        let parser = StaticMetadataParser::new()
+            .with_metadata(
+                "/life/inbox/IMG_0001.jpg",
+                FileMetadata::new("image/jpeg".to_string())
+                    .with_datetime("2024-07-03_18-00-00".to_string())
+                    .with_gps(GpsPosition::new(38.7139, -9.1334)),
+            )
+            .with_datetime("/life/inbox/IMG_0002.jpg", "2024-07-04_09-00-00");
+        let layout = "{year}/{year}-{month} {city|Elsewhere}".parse().unwrap();
+
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            LifeConfig::new(PathBuf::from("/life")).with_media_layout(layout),
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.media_processed, 2);
+
+        file_manager.assert_tree(
+            "/life/media",
+            &[
+                "2024/2024-07 Elsewhere/2024-07-04_09-00-00.jpg",
+                "2024/2024-07 Lisbon/2024-07-03_18-00-00.jpg",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_events_are_filed_together_and_named_by_the_user() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/inbox/IMG_0001.jpg", b"arrival");
+        file_manager.add_file("/life/inbox/IMG_0002.jpg", b"beach");
+        file_manager.add_file("/life/inbox/IMG_0003.jpg", b"groceries");
+        let parser = StaticMetadataParser::new()
+            .with_datetime("/life/inbox/IMG_0001.jpg", "2024-06-30_17-00-00")
+            .with_datetime("/life/inbox/IMG_0002.jpg", "2024-07-01_10-00-00")
+            .with_datetime("/life/inbox/IMG_0003.jpg", "2024-07-09_12-00-00");
+        let config = LifeConfig::new(PathBuf::from("/life"))
+            .with_media_layout("events:24h".parse().unwrap())
+            .with_name_events(true);
+
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            ScriptedPrompt::new()
+                .with_text("Weekend away")
+                .with_default(),
+            config,
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.media_processed, 3);
+
+        file_manager.assert_tree(
+            "/life/media",
+            &[
+                "2024/2024-06-30_Weekend-away/2024-06-30_17-00-00.jpg",
+                "2024/2024-06-30_Weekend-away/2024-07-01_10-00-00.jpg",
+                "2024/2024-07-09_event/2024-07-09_12-00-00.jpg",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_archived_files_are_added_to_the_manifest() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/inbox/IMG_0001.jpg", b"photo");
+        file_manager.add_file("/life/inbox/statement.pdf", b"%PDF-1.4 statement");
+        file_manager.add_file("/life/documents/tags.txt", b"bank\n");
+        file_manager.add_file(
+            "/life/inbox/statement.pdf.cleanbox.toml",
+            "date = \"2024-05-01\"\ndescription = \"statement\"\ntags = [\"bank\"]\n",
+        );
+        let parser = StaticMetadataParser::new()
+            .with_datetime("/life/inbox/IMG_0001.jpg", "2024-05-01_10-00-00");
+        let life_config = LifeConfig::new(PathBuf::from("/life"))
+            .with_hash_algorithm(HashAlgorithm::Xxh3)
+            .with_interactive(false);
+
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            life_config.clone(),
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.total_processed(), 2);
+
+        let manifest =
+            Manifest::load_from_file(life_config.manifest_file(), &file_manager).unwrap();
+        assert_eq!(manifest.len(), 3);
+        let photo = manifest
+            .get("media/2024/05/2024-05-01_10-00-00.jpg")
+            .unwrap();
+        assert_eq!(
+            photo.hash,
+            HashAlgorithm::Xxh3.tag(&ContentHasher::hash_bytes(HashAlgorithm::Xxh3, b"photo"))
+        );
+        assert_eq!(photo.size, 5);
+        assert!(
+            manifest
+                .get("documents/2024/05/2024-05-01_statement@@bank.pdf")
+                .is_some()
+        );
+        assert!(
+            manifest
+                .get("documents/2024/05/2024-05-01_statement@@bank.pdf.cleanbox.toml")
+                .is_some()
+        );
+
+        let report = ArchiveVerifier::new(file_manager, life_config)
+            .verify()
+            .unwrap();
+        assert_eq!(report.checked, 3);
+        assert!(report.is_clean());
+        assert!(report.untracked.is_empty());
+    }
+
+    #[test]
+    fn test_resolved_metadata_is_written_back_as_xmp() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/inbox/IMG_0001.HEIC", b"photo");
+        file_manager.add_file("/life/inbox/IMG_0001.MOV", b"video");
+        file_manager.add_file("/life/inbox/DSC_0002.CR2", b"raw");
+        // A JPEG with only a start of scan, dated by its RAW file
+        file_manager.add_file(
+            "/life/inbox/DSC_0002.JPG",
+            [0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9],
+        );
+        file_manager.add_file("/life/inbox/statement.pdf", b"%PDF-1.4 statement");
+        file_manager.add_file("/life/documents/tags.txt", b"bank\n");
+        file_manager.add_file(
+            "/life/inbox/statement.pdf.cleanbox.toml",
+            "date = \"2024-05-01\"\ndescription = \"statement\"\ntags = [\"bank\"]\n",
+        );
+        let parser = StaticMetadataParser::new()
+            .with_datetime("/life/inbox/IMG_0001.HEIC", "2024-05-31_23-59-59")
+            .with_datetime("/life/inbox/DSC_0002.CR2", "2024-06-01_08-00-00");
+        let life_config = LifeConfig::new(PathBuf::from("/life"))
+            .with_write_metadata(MetadataWriting::InPlace)
+            .with_interactive(false);
+
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            ScriptedPrompt::new(),
+            life_config.clone(),
+        );
+        let result = processor.process_life_directory().unwrap();
+        assert_eq!(result.total_processed(), 5);
+
+        // Files dated by their own EXIF are left alone
+        file_manager.assert_tree(
+            "/life/media",
+            &[
+                "2024/05/2024-05-31_23-59-59.HEIC",
+                "2024/05/2024-05-31_23-59-59.MOV",
+                "2024/05/2024-05-31_23-59-59.MOV.xmp",
+                "2024/06/2024-06-01_08-00-00.CR2",
+                "2024/06/2024-06-01_08-00-00.JPG",
+            ],
+        );
+        let sidecar = file_manager
+            .read_file("/life/media/2024/05/2024-05-31_23-59-59.MOV.xmp")
+            .unwrap();
+        assert!(
+            String::from_utf8(sidecar)
+                .unwrap()
+                .contains("exif:DateTimeOriginal=\"2024-05-31T23:59:59")
+        );
+        let jpeg = file_manager
+            .read_file("/life/media/2024/06/2024-06-01_08-00-00.JPG")
+            .unwrap();
+        assert_eq!(&jpeg[2..4], &[0xFF, 0xE1]);
+
+        let document = "/life/documents/2024/05/2024-05-01_statement@@bank.pdf";
+        let xmp = file_manager.read_file(format!("{document}.xmp")).unwrap();
+        assert!(
+            String::from_utf8(xmp)
+                .unwrap()
+                .contains("<rdf:li>bank</rdf:li>")
+        );
+
+        // The manifest has the rewritten JPEG and every sidecar
+        let report = ArchiveVerifier::new(file_manager, life_config)
+            .verify()
+            .unwrap();
+        assert_eq!(report.checked, 8);
+        assert!(report.is_clean());
+        assert!(report.untracked.is_empty());
+    }
+
+    #[test]
+    fn test_sidecar_document_is_filed_without_prompting() {
+        let life_dir = create_life_directory();
+        let inbox = life_dir.path().join("inbox");
+        std::fs::write(inbox.join("statement.pdf"), b"%PDF-1.4 statement").unwrap();
+        std::fs::write(
+            inbox.join("statement.pdf.cleanbox.toml"),
+            "date = \"2024-05-01\"\ndescription = \"bank-statement\"\ntags = [\"finance\", \"bank\"]\n",
+        )
+        .unwrap();
+
+        let processor = create_unattended_processor(LifeConfig::new(life_dir.path().into()));
+        let result = processor.process_life_directory().unwrap();
+
+        assert_eq!(result.documents_processed, 1);
+        assert_eq!(result.failures().count(), 0);
+        assert!(
+            life_dir
+                .path()
+                .join("documents/2024/05/2024-05-01_bank-statement@@finance,bank.pdf")
+                .is_file()
+        );
+        // The sidecar is filed next to the document and new tags are recorded
+        assert!(!inbox.join("statement.pdf.cleanbox.toml").exists());
+        assert!(
+            life_dir
+                .path()
+                .join("documents/2024/05/2024-05-01_bank-statement@@finance,bank.pdf.cleanbox.toml")
+                .is_file()
+        );
+        let tags =
+            TagDictionary::load_from_file(life_dir.path().join("documents/tags.txt")).unwrap();
+        assert!(tags.contains("bank"));
+    }
+
+    #[test]
+    fn test_run_report_records_every_inbox_file() {
+        let life_dir = create_life_directory();
+        let inbox = life_dir.path().join("inbox");
+        std::fs::write(inbox.join("statement.pdf"), b"%PDF-1.4 statement").unwrap();
+        std::fs::write(
+            inbox.join("statement.pdf.cleanbox.toml"),
+            "date = \"2024-05-01\"\ndescription = \"bank-statement\"\ntags = [\"finance\"]\n",
+        )
+        .unwrap();
+        std::fs::write(inbox.join("letter.pdf"), b"%PDF-1.4 letter").unwrap();
+        std::fs::write(inbox.join("notes"), b"no extension").unwrap();
+
+        let processor = create_unattended_processor(LifeConfig::new(life_dir.path().into()));
+        let result = processor.process_life_directory().unwrap();
+        let report = &result.report;
+
+        // The sidecar is filed along with its document
+        assert_eq!(report.files().len(), 4);
+        let sidecar = report
+            .get(&inbox.join("statement.pdf.cleanbox.toml"))
+            .unwrap();
+        assert_eq!(sidecar.status, RecordStatus::Processed);
+
+        let statement = report.get(&inbox.join("statement.pdf")).unwrap();
+        assert_eq!(statement.status, RecordStatus::Processed);
+        assert_eq!(statement.mime_type.as_deref(), Some("application/pdf"));
+        assert_eq!(statement.file_type, Some(FileType::Document));
+        assert_eq!(statement.datetime.as_deref(), Some("2024-05-01"));
+        assert_eq!(statement.datetime_source, Some(DatetimeSource::Sidecar));
+        assert_eq!(
+            statement.target_path,
+            Some(
+                life_dir
+                    .path()
+                    .join("documents/2024/05/2024-05-01_bank-statement@@finance.pdf")
+            )
+        );
+
+        let letter = report.get(&inbox.join("letter.pdf")).unwrap();
+        assert_eq!(letter.status, RecordStatus::Skipped);
+        assert!(letter.target_path.is_none());
+
+        let notes = report.get(&inbox.join("notes")).unwrap();
+        assert_eq!(notes.status, RecordStatus::Skipped);
+        assert_eq!(notes.file_type, Some(FileType::Unknown));
+    }
+
+    #[test]
+    fn test_json_lines_prompt_drives_document_workflow() {
+        let life_dir = create_life_directory();
+        let inbox = life_dir.path().join("inbox");
+        std::fs::write(inbox.join("statement_20240501.pdf"), b"%PDF-1.4 statement").unwrap();
+
+        // Action, date, description, tags, "Add more tags?"
+        let answers = "{}\n{}\n{\"answer\": \"bank-statement\"}\n{\"answer\": \"finance\"}\n{\"answer\": false}\n";
+        let prompter = JsonLinesPrompt::new(std::io::Cursor::new(answers), std::io::sink());
+        let processor = UnifiedProcessor::new(
+            RexifParser::new(),
+            StdFileManager::new(),
+            prompter,
+            LifeConfig::new(life_dir.path().into()),
+        );
+        let result = processor.process_life_directory().unwrap();
+
+        assert_eq!(result.documents_processed, 1);
+        assert_eq!(result.failures().count(), 0);
+        assert!(
+            life_dir
+                .path()
+                .join("documents/2024/05/2024-05-01_bank-statement@@finance.pdf")
+                .is_file()
+        );
+    }
+
+    #[test]
+    fn test_back_revises_the_previous_document_with_its_answers() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/inbox/a.pdf", b"%PDF-1.4 a");
+        file_manager.add_file("/life/inbox/b.pdf", b"%PDF-1.4 b");
+        file_manager.add_file("/life/documents/tags.txt", b"finance\n");
+
+        let prompter = ScriptedPrompt::new()
+            // a.pdf
+            .with_text("p")
+            .with_text("2024-01-01")
+            .with_text("statement")
+            .with_text("finance")
+            .with_confirmation(false)
+            // b.pdf: go back to a.pdf, keep its date and tags and fix the description
+            .with_text("b")
+            .with_text("p")
+            .with_default()
+            .with_text("bank-statement")
+            .with_default()
+            .with_confirmation(false)
+            // b.pdf again
+            .with_text("s");
+        let processor = UnifiedProcessor::new(
+            StaticMetadataParser::new(),
+            file_manager.clone(),
+            prompter.clone(),
+            LifeConfig::new(PathBuf::from("/life")),
+        );
+        let result = processor.process_life_directory().unwrap();
+
+        prompter.assert_finished();
+        assert_eq!(result.documents_processed, 1);
+        file_manager.assert_file("/life/documents/2024/01/2024-01-01_bank-statement@@finance.pdf");
+        file_manager.assert_file("/life/inbox/b.pdf");
+    }
+
+    #[test]
+    fn test_deferred_document_is_offered_its_rule_proposal_again() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file(
+            "/life/rules.toml",
+            b"[[rule]]\npattern = \"^payslip\"\ndescription = \"payslip\"\ntags = [\"finance\"]\n",
+        );
+        file_manager.add_file("/life/inbox/payslip_2024-06-30.pdf", b"%PDF-1.4 payslip");
+        file_manager.add_file("/life/documents/tags.txt", b"finance\n");
+
+        let prompter = ScriptedPrompt::new()
+            // Decline the proposal and defer the document
+            .with_confirmation(false)
+            .with_text("d")
+            // Accept the proposal on the second visit
+            .with_confirmation(true);
+        let processor = UnifiedProcessor::new(
+            StaticMetadataParser::new(),
+            file_manager.clone(),
+            prompter.clone(),
+            LifeConfig::new(PathBuf::from("/life")),
+        );
+        let result = processor.process_life_directory().unwrap();
+
+        prompter.assert_finished();
+        assert_eq!(result.documents_processed, 1);
+        file_manager.assert_file("/life/documents/2024/06/2024-06-30_payslip@@finance.pdf");
+    }
+
+    #[test]
+    fn test_cancelled_session_reports_deferred_and_remaining_documents() {
+        let life_dir = create_life_directory();
+        let inbox = life_dir.path().join("inbox");
+        std::fs::write(inbox.join("a.pdf"), b"%PDF-1.4 a").unwrap();
+        std::fs::write(inbox.join("b.pdf"), b"%PDF-1.4 b").unwrap();
+
+        // Defer the first document, then the input ends on the second
+        let answers = "{\"answer\": \"d\"}\n";
+        let prompter = JsonLinesPrompt::new(std::io::Cursor::new(answers), std::io::sink());
+        let processor = UnifiedProcessor::new(
+            RexifParser::new(),
+            StdFileManager::new(),
+            prompter,
+            LifeConfig::new(life_dir.path().into()),
+        );
+        let result = processor.process_life_directory().unwrap();
+
+        assert_eq!(result.files_skipped, 2);
+        assert_eq!(result.outcomes.len(), 2);
+        let deferred: Vec<_> = result
+            .outcomes
+            .iter()
+            .filter(|outcome| matches!(outcome.outcome, FileOutcome::Deferred))
+            .collect();
+        assert_eq!(deferred.len(), 1);
+        assert!(
+            result.outcomes.iter().any(|outcome| matches!(
+                outcome.outcome,
+                FileOutcome::Skipped(SkipReason::Cancelled)
+            ))
+        );
+        assert_eq!(
+            result.report.get(&deferred[0].path).unwrap().status,
+            RecordStatus::Deferred
+        );
+    }
+
+    #[test]
+    fn test_rule_document_is_filed_without_prompting() {
+        let life_dir = create_life_directory();
+        std::fs::write(
+            life_dir.path().join("rules.toml"),
+            "[[rule]]\npattern = \"^payslip\"\ndescription = \"payslip\"\ntags = [\"finance\"]\nconfirm = false\n",
+        )
+        .unwrap();
+        let inbox = life_dir.path().join("inbox");
+        std::fs::write(inbox.join("payslip_2024-06-30.pdf"), b"%PDF-1.4 payslip").unwrap();
+
+        let processor = create_unattended_processor(LifeConfig::new(life_dir.path().into()));
+        let result = processor.process_life_directory().unwrap();
+
+        assert_eq!(result.documents_processed, 1);
+        assert!(
+            life_dir
+                .path()
+                .join("documents/2024/06/2024-06-30_payslip@@finance.pdf")
+                .is_file()
+        );
+    }
+
+    #[test]
+    fn test_non_interactive_leaves_rule_proposals_in_inbox() {
+        let life_dir = create_life_directory();
+        std::fs::write(
+            life_dir.path().join("rules.toml"),
+            "[[rule]]\npattern = \"^payslip\"\ndescription = \"payslip\"\ntags = [\"finance\"]\n",
+        )
+        .unwrap();
+        let inbox = life_dir.path().join("inbox");
+        std::fs::write(inbox.join("payslip_2024-06-30.pdf"), b"%PDF-1.4 payslip").unwrap();
+
+        let processor = create_unattended_processor(LifeConfig::new(life_dir.path().into()));
+        let result = processor.process_life_directory().unwrap();
+
+        // Rules confirm by default, which needs an interactive session
+        assert_eq!(result.documents_processed, 0);
+        assert_eq!(result.files_skipped, 1);
+        assert!(inbox.join("payslip_2024-06-30.pdf").is_file());
+    }
+
+    #[test]
+    fn test_non_interactive_leaves_unmatched_documents_in_inbox() {
+        let life_dir = create_life_directory();
+        let inbox = life_dir.path().join("inbox");
+        std::fs::write(inbox.join("letter.pdf"), b"%PDF-1.4 letter").unwrap();
+        std::fs::write(inbox.join("orphan.pdf.cleanbox.json"), b"{}").unwrap();
+
+        let processor = create_unattended_processor(LifeConfig::new(life_dir.path().into()));
+        let result = processor.process_life_directory().unwrap();
+
+        assert_eq!(result.documents_processed, 0);
+        // The letter needs input and the orphaned sidecar is unrecognized
+        assert_eq!(result.files_skipped, 2);
+        assert!(inbox.join("letter.pdf").is_file());
+    }
 }
@@ -0,0 +1,521 @@
+use crate::config::{DuplicateHandling, LifeConfig};
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::{FileHasher, FileManager, walk_files};
+use crate::geocode::Geocoder;
+use crate::manifest::Manifest;
+use crate::media::{File, FileMetadata};
+use crate::metadata::MetadataParser;
+use crate::naming::TimestampNamingStrategy;
+use crate::organization::{MediaLayout, OrganizationStrategy};
+use crate::outcome::{FileOutcome, FileResult};
+use crate::sidecar::match_media_sidecars;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A move of one archived file
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedMove {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl fmt::Display for PlannedMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.from.display(), self.to.display())
+    }
+}
+
+/// Moves that bring `media/` into the configured layout
+#[derive(Debug, Default)]
+pub struct ReorganizePlan {
+    pub moves: Vec<PlannedMove>,
+    /// Files that stay where they are, such as those without a datetime
+    pub unresolved: Vec<FileResult>,
+}
+
+impl ReorganizePlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Metadata for placing an archived media file: the datetime from a timestamp name,
+/// or from the file itself if the name has none or the layout needs more than the datetime.
+/// A `geocoder` names the place of the file's GPS position.
+pub fn archived_media_metadata<E: MetadataParser + ?Sized>(
+    metadata_parser: &E,
+    path: &Path,
+    layout: &MediaLayout,
+    geocoder: Option<&Geocoder>,
+) -> Result<FileMetadata> {
+    let name_datetime = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(TimestampNamingStrategy::parse_name);
+
+    match name_datetime {
+        Some(datetime) if !layout.needs_file_metadata() => {
+            Ok(FileMetadata::new(String::new()).with_datetime(datetime))
+        }
+        name_datetime => {
+            let mut metadata = metadata_parser.parse_metadata(path)?;
+            if let Some(datetime) = name_datetime {
+                metadata.datetime_original = Some(datetime);
+            }
+            if let Some(geocoder) = geocoder {
+                geocoder.locate(&mut metadata);
+            }
+            Ok(metadata)
+        }
+    }
+}
+
+/// The media layout to place archived `files` with, having seen all of them if the
+/// layout groups files. Files whose metadata cannot be read are left out of the batch.
+pub fn archived_media_layout<E: MetadataParser + ?Sized>(
+    metadata_parser: &E,
+    files: &[PathBuf],
+    layout: &MediaLayout,
+    geocoder: Option<&Geocoder>,
+) -> Result<MediaLayout> {
+    let mut layout = layout.clone();
+    if layout.groups_files() {
+        let batch: Vec<File> = files
+            .iter()
+            .filter_map(|path| {
+                let metadata =
+                    archived_media_metadata(metadata_parser, path, &layout, geocoder).ok()?;
+                Some(File::new(path).with_metadata(metadata))
+            })
+            .collect();
+        layout.organize_batch(&batch)?;
+    }
+    Ok(layout)
+}
+
+/// Move archived files, carrying their manifest entries along, and remove the
+/// directories left empty.
+///
+/// An existing target fails the move unless `overwrite` is set.
+pub fn move_archived_files<F: FileManager>(
+    file_manager: &F,
+    life_config: &LifeConfig,
+    moves: &[PlannedMove],
+    overwrite: bool,
+) -> Result<Vec<FileResult>> {
+    let life_path = &life_config.life_path;
+    let manifest_file = life_config.manifest_file();
+    let mut manifest = Manifest::load_from_file(&manifest_file, file_manager)?;
+    let mut results = Vec::new();
+    let mut emptied = BTreeSet::new();
+
+    for planned in moves {
+        let outcome = if !overwrite && file_manager.file_exists(&planned.to) {
+            FileOutcome::Failed(CleanboxError::FileAlreadyExists(
+                planned.to.display().to_string(),
+            ))
+        } else {
+            match file_manager.move_file(&planned.from, &planned.to) {
+                Ok(()) => {
+                    if let (Some(from), Some(to)) = (
+                        Manifest::key(life_path, &planned.from),
+                        Manifest::key(life_path, &planned.to),
+                    ) {
+                        manifest.rename(&from, to);
+                    }
+                    if let Some(parent) = planned.from.parent() {
+                        emptied.insert(parent.to_path_buf());
+                    }
+                    FileOutcome::Moved(planned.to.clone())
+                }
+                Err(e) => FileOutcome::Failed(e),
+            }
+        };
+        results.push(FileResult::new(&planned.from, outcome));
+    }
+
+    if !manifest.is_empty() {
+        manifest.save(&manifest_file, file_manager)?;
+    }
+
+    let roots = [life_config.media_root(), life_config.documents_root()];
+    // Deepest first, so a parent is only tried once its children are gone
+    for directory in emptied.iter().rev() {
+        remove_empty_directories(file_manager, directory, &roots);
+    }
+
+    Ok(results)
+}
+
+/// Remove `directory` and then its parents while they are empty, stopping at `roots`
+fn remove_empty_directories<F: FileManager>(file_manager: &F, directory: &Path, roots: &[PathBuf]) {
+    for ancestor in directory.ancestors() {
+        let inside_root = roots
+            .iter()
+            .any(|root| ancestor.starts_with(root) && ancestor != root);
+        let is_empty = file_manager
+            .read_directory(ancestor)
+            .is_ok_and(|entries| entries.is_empty());
+        if !inside_root || !is_empty || file_manager.remove_directory(ancestor).is_err() {
+            break;
+        }
+        log::debug!("Removed empty directory {}", ancestor.display());
+    }
+}
+
+/// Re-derives where every archived media file belongs under the configured
+/// `MediaLayout` and moves it there.
+pub struct ArchiveReorganizer<E: MetadataParser, F: FileManager> {
+    metadata_parser: E,
+    file_manager: F,
+    life_config: LifeConfig,
+}
+
+impl<E: MetadataParser, F: FileManager> ArchiveReorganizer<E, F> {
+    pub fn new(metadata_parser: E, file_manager: F, life_config: LifeConfig) -> Self {
+        Self {
+            metadata_parser,
+            file_manager,
+            life_config,
+        }
+    }
+
+    /// Work out the moves without touching any file
+    pub fn plan(&self) -> Result<ReorganizePlan> {
+        let media_root = self.life_config.media_root();
+        let mut plan = ReorganizePlan::new();
+        let mut claimed = HashSet::new();
+
+        let files: Vec<PathBuf> = walk_files(&self.file_manager, &media_root)?
+            .into_iter()
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| !name.starts_with('.'))
+            })
+            .collect();
+        // Sidecars keep following their media file
+        let (files, media_sidecars) = match_media_sidecars(&files);
+        let geocoder = Geocoder::for_life(&self.life_config, &self.file_manager)?;
+        let layout = &archived_media_layout(
+            &self.metadata_parser,
+            &files,
+            &self.life_config.media_layout,
+            geocoder.as_ref(),
+        )?;
+
+        for path in files {
+            let target =
+                archived_media_metadata(&self.metadata_parser, &path, layout, geocoder.as_ref())
+                    .and_then(|metadata| {
+                        let file = File::new(&path).with_metadata(metadata);
+                        let directory = layout.determine_target_directory(&file, &media_root)?;
+                        Ok(directory.join(file.file_name()?))
+                    })
+                    .and_then(|target| self.resolve_duplicate(&path, target, &claimed));
+
+            match target {
+                Ok(target) if target == path => {}
+                Ok(target) => {
+                    for sidecar in media_sidecars.get(&path).into_iter().flatten() {
+                        // A renamed sidecar would lose its media file, so a taken name is left alone
+                        let sidecar_target = sidecar.target(&target).and_then(|sidecar_target| {
+                            if claimed.contains(&sidecar_target)
+                                || self.file_manager.file_exists(&sidecar_target)
+                            {
+                                return Err(CleanboxError::FileAlreadyExists(
+                                    sidecar_target.display().to_string(),
+                                ));
+                            }
+                            Ok(sidecar_target)
+                        });
+                        match sidecar_target {
+                            Ok(sidecar_target) => {
+                                claimed.insert(sidecar_target.clone());
+                                plan.moves.push(PlannedMove {
+                                    from: sidecar.path.clone(),
+                                    to: sidecar_target,
+                                });
+                            }
+                            Err(e) => plan
+                                .unresolved
+                                .push(FileResult::new(&sidecar.path, FileOutcome::Failed(e))),
+                        }
+                    }
+                    claimed.insert(target.clone());
+                    plan.moves.push(PlannedMove {
+                        from: path,
+                        to: target,
+                    });
+                }
+                Err(e) => plan
+                    .unresolved
+                    .push(FileResult::new(&path, FileOutcome::Failed(e))),
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Carry out the plan
+    pub fn apply(&self, plan: &ReorganizePlan) -> Result<Vec<FileResult>> {
+        let overwrite = self.life_config.handle_duplicates == DuplicateHandling::Overwrite;
+        move_archived_files(
+            &self.file_manager,
+            &self.life_config,
+            &plan.moves,
+            overwrite,
+        )
+    }
+
+    /// Apply the duplicate handling if `target` is taken by another file
+    fn resolve_duplicate(
+        &self,
+        source: &Path,
+        target: PathBuf,
+        claimed: &HashSet<PathBuf>,
+    ) -> Result<PathBuf> {
+        let taken = |target: &Path| {
+            target != source && (claimed.contains(target) || self.file_manager.file_exists(target))
+        };
+        if !taken(&target) {
+            return Ok(target);
+        }
+
+        match self.life_config.handle_duplicates {
+            DuplicateHandling::Overwrite if !claimed.contains(&target) => Ok(target),
+            DuplicateHandling::AppendHash => {
+                let hash = self
+                    .file_manager
+                    .hash_file(source, self.life_config.hash_algorithm)?;
+                let suffix = FileHasher::generate_hash_suffix(&hash, self.life_config.hash_length);
+                let name = target
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .ok_or_else(|| CleanboxError::InvalidPath(target.display().to_string()))?;
+                let renamed =
+                    target.with_file_name(FileHasher::append_hash_to_filename(name, &suffix)?);
+                if taken(&renamed) {
+                    return Err(CleanboxError::FileAlreadyExists(
+                        renamed.display().to_string(),
+                    ));
+                }
+                Ok(renamed)
+            }
+            DuplicateHandling::Sequence => {
+                let name = target
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .ok_or_else(|| CleanboxError::InvalidPath(target.display().to_string()))?;
+                // Number from the plain timestamp name, not on top of an earlier number
+                let base = match (
+                    TimestampNamingStrategy::parse_name(name),
+                    name.rsplit_once('.'),
+                ) {
+                    (Some(datetime), Some((_, extension))) => format!("{datetime}.{extension}"),
+                    _ => name.to_string(),
+                };
+                let mut number = 1;
+                loop {
+                    let numbered =
+                        FileHasher::append_hash_to_filename(&base, &format!("{number:02}"))?;
+                    let renamed = target.with_file_name(numbered);
+                    if !taken(&renamed) {
+                        return Ok(renamed);
+                    }
+                    number += 1;
+                }
+            }
+            _ => Err(CleanboxError::FileAlreadyExists(
+                target.display().to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::{ContentHasher, HashAlgorithm};
+    use crate::manifest::ManifestEntry;
+    use crate::testing::{InMemoryFileManager, StaticMetadataParser};
+
+    fn reorganizer(
+        file_manager: &InMemoryFileManager,
+        parser: StaticMetadataParser,
+        layout: &str,
+    ) -> ArchiveReorganizer<StaticMetadataParser, InMemoryFileManager> {
+        let life_config =
+            LifeConfig::new(PathBuf::from("/life")).with_media_layout(layout.parse().unwrap());
+        ArchiveReorganizer::new(parser, file_manager.clone(), life_config)
+    }
+
+    #[test]
+    fn test_monthly_to_yearly() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.jpg", b"a");
+        file_manager.add_file("/life/media/2024/06/2024-06-01_10-00-00.jpg", b"b");
+        file_manager.add_file("/life/media/2023/12/IMG_0001.jpg", b"c");
+        file_manager.add_file("/life/media/2023/12/IMG_0002.jpg", b"d");
+        let parser = StaticMetadataParser::new()
+            .with_datetime("/life/media/2023/12/IMG_0001.jpg", "2023-12-24_18-00-00");
+        let mut manifest = Manifest::new();
+        manifest.insert(
+            "media/2024/05/2024-05-01_10-00-00.jpg".to_string(),
+            ManifestEntry::new(HashAlgorithm::Sha1, "00", 1),
+        );
+        manifest.save("/life/manifest.json", &file_manager).unwrap();
+
+        let reorganizer = reorganizer(&file_manager, parser, "yearly");
+        let plan = reorganizer.plan().unwrap();
+
+        // Planning moves nothing
+        file_manager.assert_file("/life/media/2024/05/2024-05-01_10-00-00.jpg");
+        assert_eq!(plan.moves.len(), 3);
+        assert_eq!(plan.unresolved.len(), 1);
+        assert_eq!(
+            plan.unresolved[0].path,
+            PathBuf::from("/life/media/2023/12/IMG_0002.jpg")
+        );
+
+        let results = reorganizer.apply(&plan).unwrap();
+        assert!(results.iter().all(|result| !result.is_failed()));
+        file_manager.assert_tree(
+            "/life/media",
+            &[
+                "2023/12/IMG_0002.jpg",
+                "2023/IMG_0001.jpg",
+                "2024/2024-05-01_10-00-00.jpg",
+                "2024/2024-06-01_10-00-00.jpg",
+            ],
+        );
+        // Emptied month folders are removed, the one still holding a file stays
+        assert!(!file_manager.file_exists("/life/media/2024/05"));
+        assert!(!file_manager.file_exists("/life/media/2024/06"));
+        assert!(file_manager.file_exists("/life/media/2023/12"));
+
+        let manifest = Manifest::load_from_file("/life/manifest.json", &file_manager).unwrap();
+        assert!(manifest.contains("media/2024/2024-05-01_10-00-00.jpg"));
+        assert!(reorganizer.plan().unwrap().moves.is_empty());
+    }
+
+    #[test]
+    fn test_monthly_to_events_keeps_named_event_folders() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/media/2024/06/2024-06-30_17-00-00.jpg", b"a");
+        file_manager.add_file("/life/media/2024/07/2024-07-01_10-00-00.jpg", b"b");
+        file_manager.add_file(
+            "/life/media/2024/2024-07-09_Garden/2024-07-09_12-00-00.jpg",
+            b"c",
+        );
+
+        let reorganizer = reorganizer(&file_manager, StaticMetadataParser::new(), "events:24h");
+        let plan = reorganizer.plan().unwrap();
+        assert_eq!(plan.moves.len(), 2);
+
+        reorganizer.apply(&plan).unwrap();
+        file_manager.assert_tree(
+            "/life/media",
+            &[
+                "2024/2024-06-30_event/2024-06-30_17-00-00.jpg",
+                "2024/2024-06-30_event/2024-07-01_10-00-00.jpg",
+                "2024/2024-07-09_Garden/2024-07-09_12-00-00.jpg",
+            ],
+        );
+        assert!(reorganizer.plan().unwrap().moves.is_empty());
+    }
+
+    #[test]
+    fn test_sidecars_move_with_their_media_file() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.jpg", b"a");
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.xmp", b"b");
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.jpg.json", b"c");
+
+        let reorganizer = reorganizer(&file_manager, StaticMetadataParser::new(), "yearly");
+        let plan = reorganizer.plan().unwrap();
+        assert_eq!(plan.moves.len(), 3);
+        assert!(plan.unresolved.is_empty());
+
+        reorganizer.apply(&plan).unwrap();
+        file_manager.assert_tree(
+            "/life/media",
+            &[
+                "2024/2024-05-01_10-00-00.jpg",
+                "2024/2024-05-01_10-00-00.jpg.json",
+                "2024/2024-05-01_10-00-00.xmp",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_custom_layout_parses_the_file_type() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.mp4", b"video");
+
+        let plan = reorganizer(
+            &file_manager,
+            StaticMetadataParser::new(),
+            "{year}/{media_type}",
+        )
+        .plan()
+        .unwrap();
+
+        assert_eq!(
+            plan.moves,
+            vec![PlannedMove {
+                from: PathBuf::from("/life/media/2024/05/2024-05-01_10-00-00.mp4"),
+                to: PathBuf::from("/life/media/2024/video/2024-05-01_10-00-00.mp4"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_colliding_targets_get_hash_suffix() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.jpg", b"first");
+        file_manager.add_file("/life/media/2024/06/2024-05-01_10-00-00.jpg", b"second");
+
+        let plan = reorganizer(&file_manager, StaticMetadataParser::new(), "yearly")
+            .plan()
+            .unwrap();
+
+        let hash = ContentHasher::hash_bytes(HashAlgorithm::default(), b"second");
+        let targets: Vec<&Path> = plan.moves.iter().map(|m| m.to.as_path()).collect();
+        assert_eq!(
+            targets,
+            vec![
+                PathBuf::from("/life/media/2024/2024-05-01_10-00-00.jpg"),
+                PathBuf::from(format!(
+                    "/life/media/2024/2024-05-01_10-00-00_{}.jpg",
+                    &hash[..6]
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collisions_are_unresolved_when_skipping_duplicates() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/media/2024/05/2024-05-01_10-00-00.jpg", b"first");
+        file_manager.add_file("/life/media/2024/06/2024-05-01_10-00-00.jpg", b"second");
+        let life_config = LifeConfig::new(PathBuf::from("/life"))
+            .with_media_layout(MediaLayout::Yearly)
+            .with_duplicate_handling(DuplicateHandling::Skip);
+
+        let plan = ArchiveReorganizer::new(
+            StaticMetadataParser::new(),
+            file_manager.clone(),
+            life_config,
+        )
+        .plan()
+        .unwrap();
+
+        assert_eq!(plan.moves.len(), 1);
+        assert_eq!(
+            plan.unresolved[0].error().unwrap().kind(),
+            "file_already_exists"
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use crate::config::DuplicateHandling;
+use crate::error::{CleanboxError, Result};
+use crate::hashing::HashAlgorithm;
+use crate::media::{FileMetadata, FileType};
+use crate::outcome::SkipReason;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Where the datetime used to name and file a file came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatetimeSource {
+    Exif,
+    /// Taken from the file it was captured with, as for the video of a Live Photo
+    Companion,
+    Sidecar,
+    /// Read from the JSON or CSV files of a Google Takeout or iCloud export
+    Export,
+    Rule,
+    User,
+}
+
+/// What happened to a file during a run
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordStatus {
+    /// Named and moved into the archive
+    Processed,
+    /// Left in the inbox
+    Skipped,
+    Failed,
+    /// Deferred by the user and left in the inbox
+    Deferred,
+}
+
+/// A processing error with a stable kind for machines and a message for people
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportError {
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl From<&CleanboxError> for ReportError {
+    fn from(error: &CleanboxError) -> Self {
+        Self {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Everything known about one inbox file after a run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileRecord {
+    pub path: PathBuf,
+    pub status: RecordStatus,
+    pub mime_type: Option<String>,
+    pub file_type: Option<FileType>,
+    pub datetime: Option<String>,
+    pub datetime_source: Option<DatetimeSource>,
+    /// Content hash tagged with its algorithm, such as `blake3:af13...`
+    pub hash: Option<String>,
+    pub target_path: Option<PathBuf>,
+    /// Duplicate handling applied because the target already existed
+    pub duplicate_action: Option<DuplicateHandling>,
+    /// XMP sidecar written next to the target, or the target itself if written in place
+    pub metadata_path: Option<PathBuf>,
+    /// Why the file was left in the inbox, see `SkipReason::kind`
+    pub skip_reason: Option<&'static str>,
+    pub error: Option<ReportError>,
+}
+
+impl FileRecord {
+    /// A record for a file that has not been processed, so it stays in the inbox
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            status: RecordStatus::Skipped,
+            mime_type: None,
+            file_type: None,
+            datetime: None,
+            datetime_source: None,
+            hash: None,
+            target_path: None,
+            duplicate_action: None,
+            metadata_path: None,
+            skip_reason: None,
+            error: None,
+        }
+    }
+
+    pub fn set_metadata(&mut self, metadata: &FileMetadata) {
+        self.mime_type = Some(metadata.mime_type.clone());
+        self.file_type = Some(metadata.file_type.clone());
+    }
+
+    pub fn set_hash(&mut self, algorithm: HashAlgorithm, digest: &str) {
+        self.hash = Some(algorithm.tag(digest));
+    }
+
+    pub fn set_datetime(&mut self, datetime: &str, source: DatetimeSource) {
+        self.datetime = Some(datetime.to_string());
+        self.datetime_source = Some(source);
+    }
+
+    pub fn mark_processed(&mut self) {
+        self.status = RecordStatus::Processed;
+        self.error = None;
+    }
+
+    pub fn mark_skipped(&mut self, reason: &SkipReason) {
+        self.status = RecordStatus::Skipped;
+        self.skip_reason = Some(reason.kind());
+        if let SkipReason::Unsupported(error) = reason {
+            self.error = Some(error.into());
+        }
+    }
+
+    pub fn mark_deferred(&mut self) {
+        self.status = RecordStatus::Deferred;
+    }
+
+    pub fn mark_failed(&mut self, error: &CleanboxError) {
+        self.status = RecordStatus::Failed;
+        self.error = Some(error.into());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ReportSummary {
+    processed: usize,
+    skipped: usize,
+    failed: usize,
+    deferred: usize,
+}
+
+#[derive(Serialize)]
+struct ReportDocument<'a> {
+    summary: ReportSummary,
+    files: &'a [FileRecord],
+}
+
+/// Per-file report of a processing run, in inbox order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunReport {
+    files: Vec<FileRecord>,
+    /// Position of each path in `files`
+    index: HashMap<PathBuf, usize>,
+}
+
+impl RunReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The record for `path`, created on first use
+    pub fn record(&mut self, path: &Path) -> &mut FileRecord {
+        let index = match self.index.get(path) {
+            Some(&index) => index,
+            None => {
+                self.files.push(FileRecord::new(path.to_path_buf()));
+                self.index.insert(path.to_path_buf(), self.files.len() - 1);
+                self.files.len() - 1
+            }
+        };
+        &mut self.files[index]
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&FileRecord> {
+        self.index.get(path).map(|&index| &self.files[index])
+    }
+
+    pub fn files(&self) -> &[FileRecord] {
+        &self.files
+    }
+
+    pub fn count(&self, status: RecordStatus) -> usize {
+        self.files
+            .iter()
+            .filter(|record| record.status == status)
+            .count()
+    }
+
+    /// Serialize as pretty-printed JSON with a summary and one entry per file
+    pub fn to_json(&self) -> Result<String> {
+        let document = ReportDocument {
+            summary: ReportSummary {
+                processed: self.count(RecordStatus::Processed),
+                skipped: self.count(RecordStatus::Skipped),
+                failed: self.count(RecordStatus::Failed),
+                deferred: self.count(RecordStatus::Deferred),
+            },
+            files: &self.files,
+        };
+
+        serde_json::to_string_pretty(&document)
+            .map_err(|e| CleanboxError::Serialization(format!("Cannot encode report: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_is_created_once() {
+        let mut report = RunReport::new();
+        report.record(Path::new("/inbox/a.jpg")).mark_processed();
+        report.record(Path::new("/inbox/b.pdf"));
+        report
+            .record(Path::new("/inbox/a.jpg"))
+            .set_datetime("2024-05-01_10-00-00", DatetimeSource::Exif);
+
+        assert_eq!(report.files().len(), 2);
+        let record = report.get(Path::new("/inbox/a.jpg")).unwrap();
+        assert_eq!(record.status, RecordStatus::Processed);
+        assert_eq!(record.datetime_source, Some(DatetimeSource::Exif));
+        assert_eq!(report.count(RecordStatus::Skipped), 1);
+    }
+
+    #[test]
+    fn test_report_json() {
+        let mut report = RunReport::new();
+        let record = report.record(Path::new("/inbox/a.jpg"));
+        record.set_metadata(&FileMetadata::new("image/jpeg".to_string()));
+        record.set_datetime("2024-05-01_10-00-00", DatetimeSource::Exif);
+        record.set_hash(HashAlgorithm::Xxh3, "00ff00ff00ff00ff");
+        record.target_path = Some(PathBuf::from("/media/2024/05/a.jpg"));
+        record.duplicate_action = Some(DuplicateHandling::AppendHash);
+        record.mark_processed();
+        report
+            .record(Path::new("/inbox/b.txt"))
+            .mark_failed(&CleanboxError::UnsupportedFileType(
+                "text/plain".to_string(),
+            ));
+        report
+            .record(Path::new("/inbox/c.pdf"))
+            .mark_skipped(&SkipReason::NeedsInput);
+        report.record(Path::new("/inbox/d.pdf")).mark_deferred();
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+
+        assert_eq!(json["summary"]["processed"], 1);
+        assert_eq!(json["summary"]["failed"], 1);
+        assert_eq!(json["summary"]["skipped"], 1);
+        assert_eq!(json["summary"]["deferred"], 1);
+        assert_eq!(json["files"][0]["status"], "processed");
+        assert_eq!(json["files"][0]["file_type"], "image");
+        assert_eq!(json["files"][0]["datetime_source"], "exif");
+        assert_eq!(json["files"][0]["hash"], "xxh3:00ff00ff00ff00ff");
+        assert_eq!(json["files"][0]["duplicate_action"], "append_hash");
+        assert_eq!(json["files"][0]["error"], serde_json::Value::Null);
+        assert_eq!(json["files"][1]["error"]["kind"], "unsupported_file_type");
+        assert_eq!(json["files"][2]["skip_reason"], "needs_input");
+        assert_eq!(json["files"][3]["status"], "deferred");
+    }
+}
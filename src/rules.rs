@@ -0,0 +1,456 @@
+use crate::document::{DocumentInput, extract_date_from_text};
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::FileManager;
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RawRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    pattern: Option<String>,
+    mime: Option<String>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    date: Option<String>,
+    date_capture: Option<CaptureRef>,
+    date_format: Option<String>,
+    description: String,
+    tags: Vec<String>,
+    #[serde(default = "default_confirm")]
+    confirm: bool,
+}
+
+fn default_confirm() -> bool {
+    true
+}
+
+/// Reference to a capture group, by index (`1`) or by name (`"date"`)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum CaptureRef {
+    Index(usize),
+    Name(String),
+}
+
+/// What a rule is matched against
+#[derive(Debug, Clone, Copy)]
+pub struct RuleCandidate<'a> {
+    pub filename: &'a str,
+    pub mime_type: &'a str,
+    pub size: u64,
+}
+
+/// A declarative rule that classifies matching documents
+#[derive(Debug, Clone)]
+pub struct DocumentRule {
+    pub pattern: Option<Regex>,
+    pub mime: Option<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub date: Option<String>,
+    pub date_capture: Option<CaptureRef>,
+    pub date_format: Option<String>,
+    /// Description, may reference captures as `$1` or `${name}`
+    pub description: String,
+    pub tags: Vec<String>,
+    /// Ask the user to accept the proposal instead of filing it unattended
+    pub confirm: bool,
+}
+
+impl DocumentRule {
+    pub fn matches(&self, candidate: &RuleCandidate) -> bool {
+        if let Some(pattern) = &self.pattern
+            && !pattern.is_match(candidate.filename)
+        {
+            return false;
+        }
+
+        if let Some(mime) = &self.mime
+            && !Self::mime_matches(mime, candidate.mime_type)
+        {
+            return false;
+        }
+
+        if self.min_size.is_some_and(|min| candidate.size < min) {
+            return false;
+        }
+
+        if self.max_size.is_some_and(|max| candidate.size > max) {
+            return false;
+        }
+
+        true
+    }
+
+    /// Build the proposed DocumentInput for a matching candidate.
+    ///
+    /// The date comes from the literal `date`, then `date_capture`, then the usual
+    /// filename/filesystem suggestion. The result is validated before returning.
+    pub fn propose<P: AsRef<Path>, F: FileManager>(
+        &self,
+        candidate: &RuleCandidate,
+        document_path: P,
+        file_manager: &F,
+    ) -> Result<DocumentInput> {
+        let captures = self
+            .pattern
+            .as_ref()
+            .and_then(|pattern| pattern.captures(candidate.filename));
+
+        let mut description = self.description.clone();
+        if let Some(captures) = &captures {
+            description.clear();
+            captures.expand(&self.description, &mut description);
+        }
+
+        let date = match (&self.date, &self.date_capture) {
+            (Some(date), _) => Some(date.clone()),
+            (None, Some(capture_ref)) => {
+                let text = captures
+                    .as_ref()
+                    .and_then(|captures| match capture_ref {
+                        CaptureRef::Index(index) => captures.get(*index),
+                        CaptureRef::Name(name) => captures.name(name),
+                    })
+                    .map(|m| m.as_str())
+                    .ok_or_else(|| {
+                        CleanboxError::InvalidRule(format!(
+                            "Date capture {capture_ref:?} did not match in {}",
+                            candidate.filename
+                        ))
+                    })?;
+                Some(self.parse_captured_date(text)?)
+            }
+            (None, None) => None,
+        };
+
+        DocumentInput::for_document(
+            date,
+            description,
+            self.tags.clone(),
+            document_path,
+            file_manager,
+        )
+    }
+
+    fn parse_captured_date(&self, text: &str) -> Result<String> {
+        let date = match &self.date_format {
+            Some(format) => NaiveDate::parse_from_str(text, format)
+                .ok()
+                .map(|date| date.format("%Y-%m-%d").to_string()),
+            None => extract_date_from_text(text),
+        };
+
+        date.ok_or_else(|| CleanboxError::InvalidDateTime(format!("Cannot parse date: {text}")))
+    }
+
+    fn mime_matches(pattern: &str, mime_type: &str) -> bool {
+        match pattern.strip_suffix("/*") {
+            Some(prefix) => mime_type
+                .split('/')
+                .next()
+                .is_some_and(|kind| kind.eq_ignore_ascii_case(prefix)),
+            None => pattern.eq_ignore_ascii_case(mime_type),
+        }
+    }
+}
+
+/// Ordered list of document rules loaded from the life directory
+#[derive(Debug, Clone, Default)]
+pub struct DocumentRules {
+    rules: Vec<DocumentRule>,
+}
+
+impl DocumentRules {
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Load rules from a TOML file. A missing file yields an empty rule set.
+    ///
+    /// ```toml
+    /// [[rule]]
+    /// pattern = "^Rechnung_(\\d{8})"
+    /// mime = "application/pdf"
+    /// date_capture = 1
+    /// description = "invoice"
+    /// tags = ["invoices", "telekom"]
+    /// confirm = true
+    /// ```
+    pub fn load_from_file<P: AsRef<Path>, F: FileManager>(
+        path: P,
+        file_manager: &F,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        if !file_manager.is_file(path) {
+            return Ok(Self::new());
+        }
+
+        let content = file_manager
+            .read_file(path)
+            .and_then(|bytes| {
+                String::from_utf8(bytes).map_err(|e| CleanboxError::InvalidRule(e.to_string()))
+            })
+            .map_err(|e| {
+                CleanboxError::InvalidRule(format!("Cannot read {}: {}", path.display(), e))
+            })?;
+
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let file: RulesFile =
+            toml::from_str(content).map_err(|e| CleanboxError::InvalidRule(e.to_string()))?;
+
+        let mut rules = Vec::new();
+        for raw in file.rules {
+            rules.push(Self::compile(raw)?);
+        }
+
+        Ok(Self { rules })
+    }
+
+    fn compile(raw: RawRule) -> Result<DocumentRule> {
+        if raw.pattern.is_none()
+            && raw.mime.is_none()
+            && raw.min_size.is_none()
+            && raw.max_size.is_none()
+        {
+            return Err(CleanboxError::InvalidRule(format!(
+                "Rule '{}' needs a pattern, mime or size condition",
+                raw.description
+            )));
+        }
+
+        let pattern = raw
+            .pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| CleanboxError::InvalidRule(format!("Invalid pattern: {e}")))?;
+
+        if raw.date_capture.is_some() && pattern.is_none() {
+            return Err(CleanboxError::InvalidRule(format!(
+                "Rule '{}' uses date_capture without a pattern",
+                raw.description
+            )));
+        }
+
+        // Literal dates are checked up front; captured ones when the rule is applied
+        if let Some(date) = &raw.date {
+            DocumentInput::new(date.clone(), "rule".to_string(), vec!["rule".to_string()])
+                .validate_date()?;
+        }
+
+        Ok(DocumentRule {
+            pattern,
+            mime: raw.mime,
+            min_size: raw.min_size,
+            max_size: raw.max_size,
+            date: raw.date,
+            date_capture: raw.date_capture,
+            date_format: raw.date_format,
+            description: raw.description,
+            tags: raw.tags,
+            confirm: raw.confirm,
+        })
+    }
+
+    /// Returns the first rule matching the candidate.
+    pub fn find_match(&self, candidate: &RuleCandidate) -> Option<&DocumentRule> {
+        self.rules.iter().find(|rule| rule.matches(candidate))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MockFileManager;
+
+    const RULES: &str = r#"
+[[rule]]
+pattern = "^Rechnung_(\\d{8})"
+mime = "application/pdf"
+date_capture = 1
+description = "invoice"
+tags = ["invoices", "telekom"]
+
+[[rule]]
+pattern = "^statement_.*\\.pdf$"
+description = "bank-statement"
+tags = ["finance", "bank"]
+confirm = false
+
+[[rule]]
+pattern = "^scan_(?P<day>\\d{2}\\.\\d{2}\\.\\d{4})_(?P<what>[a-z]+)"
+date_capture = "day"
+date_format = "%d.%m.%Y"
+description = "scan-${what}"
+tags = ["scans"]
+
+[[rule]]
+mime = "image/*"
+max_size = 1000
+description = "tiny-image"
+tags = ["images"]
+"#;
+
+    fn candidate<'a>(filename: &'a str, mime_type: &'a str, size: u64) -> RuleCandidate<'a> {
+        RuleCandidate {
+            filename,
+            mime_type,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = DocumentRules::parse(RULES).unwrap();
+        assert_eq!(rules.len(), 4);
+        assert!(!rules.is_empty());
+    }
+
+    #[test]
+    fn test_confirm_defaults_to_true() {
+        let rules = DocumentRules::parse(RULES).unwrap();
+
+        let invoice = candidate("Rechnung_20240315.pdf", "application/pdf", 100);
+        assert!(rules.find_match(&invoice).unwrap().confirm);
+
+        let statement = candidate("statement_may.pdf", "application/pdf", 100);
+        assert!(!rules.find_match(&statement).unwrap().confirm);
+    }
+
+    #[test]
+    fn test_date_from_capture() {
+        let rules = DocumentRules::parse(RULES).unwrap();
+        let file_manager = MockFileManager::new();
+
+        let invoice = candidate("Rechnung_20240315.pdf", "application/pdf", 100);
+        let rule = rules.find_match(&invoice).unwrap();
+        let input = rule
+            .propose(&invoice, "/inbox/Rechnung_20240315.pdf", &file_manager)
+            .unwrap();
+
+        assert_eq!(input.date, "2024-03-15");
+        assert_eq!(input.description, "invoice");
+        assert_eq!(input.tags, vec!["invoices", "telekom"]);
+    }
+
+    #[test]
+    fn test_named_capture_with_format_and_expansion() {
+        let rules = DocumentRules::parse(RULES).unwrap();
+        let file_manager = MockFileManager::new();
+
+        let scan = candidate("scan_31.01.2024_contract.pdf", "application/pdf", 100);
+        let input = rules
+            .find_match(&scan)
+            .unwrap()
+            .propose(&scan, "/inbox/scan_31.01.2024_contract.pdf", &file_manager)
+            .unwrap();
+
+        assert_eq!(input.date, "2024-01-31");
+        assert_eq!(input.description, "scan-contract");
+    }
+
+    #[test]
+    fn test_mime_filter() {
+        let rules = DocumentRules::parse(RULES).unwrap();
+
+        // Same filename, wrong MIME type: the invoice rule does not apply
+        let not_pdf = candidate("Rechnung_20240315.zip", "application/zip", 100);
+        assert!(rules.find_match(&not_pdf).is_none());
+
+        let image = candidate("anything.png", "image/png", 500);
+        assert_eq!(rules.find_match(&image).unwrap().description, "tiny-image");
+    }
+
+    #[test]
+    fn test_size_filter() {
+        let rules = DocumentRules::parse(RULES).unwrap();
+
+        let large_image = candidate("anything.png", "image/png", 5000);
+        assert!(rules.find_match(&large_image).is_none());
+    }
+
+    #[test]
+    fn test_rule_without_date_uses_suggestion() {
+        let rules = DocumentRules::parse(RULES).unwrap();
+        let file_manager = MockFileManager::new();
+
+        let statement = candidate("statement_20240501.pdf", "application/pdf", 100);
+        let input = rules
+            .find_match(&statement)
+            .unwrap()
+            .propose(&statement, "/inbox/statement_20240501.pdf", &file_manager)
+            .unwrap();
+        assert_eq!(input.date, "2024-05-01");
+    }
+
+    #[test]
+    fn test_proposal_is_validated() {
+        let rules = DocumentRules::parse(
+            "[[rule]]\npattern = \"^(\\\\w+)\"\ndescription = \"$1\"\ntags = [\"misc\"]",
+        )
+        .unwrap();
+        let file_manager = MockFileManager::new();
+
+        let upper = candidate("UPPER.pdf", "application/pdf", 1);
+        let result =
+            rules
+                .find_match(&upper)
+                .unwrap()
+                .propose(&upper, "/inbox/UPPER.pdf", &file_manager);
+        assert!(matches!(
+            result.unwrap_err(),
+            CleanboxError::InvalidUserInput(_)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let invalid = [
+            // Bad regex
+            "[[rule]]\npattern = \"(\"\ndescription = \"x\"\ntags = [\"y\"]",
+            // No condition at all
+            "[[rule]]\ndescription = \"x\"\ntags = [\"y\"]",
+            // Capture without pattern
+            "[[rule]]\nmime = \"application/pdf\"\ndate_capture = 1\ndescription = \"x\"\ntags = [\"y\"]",
+            // Invalid literal date
+            "[[rule]]\npattern = \"x\"\ndate = \"2024-13-01\"\ndescription = \"x\"\ntags = [\"y\"]",
+            // Unknown field
+            "[[rule]]\npattern = \"x\"\ndescripton = \"x\"\ntags = [\"y\"]",
+        ];
+
+        for content in invalid {
+            assert!(
+                DocumentRules::parse(content).is_err(),
+                "Should reject: {content}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_missing_rules_file() {
+        let rules =
+            DocumentRules::load_from_file("/nonexistent/rules.toml", &MockFileManager::new())
+                .unwrap();
+        assert!(rules.is_empty());
+    }
+}
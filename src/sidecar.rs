@@ -0,0 +1,420 @@
+use crate::document::DocumentInput;
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::FileManager;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+const SIDECAR_EXTENSIONS: [&str; 2] = [".cleanbox.toml", ".cleanbox.json"];
+
+/// Extensions of sidecars that editing tools and exports leave next to media
+const MEDIA_SIDECAR_EXTENSIONS: [&str; 3] = ["xmp", "aae", "json"];
+
+/// Suffixes that follow a media file's whole name, longest first
+const MEDIA_SIDECAR_NAME_SUFFIXES: [&str; 4] =
+    [".supplemental-metadata.json", ".json", ".xmp", ".aae"];
+
+/// Document answers provided next to a document, e.g. `statement.pdf.cleanbox.toml`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DocumentSidecar {
+    pub date: Option<String>,
+    pub description: String,
+    pub tags: Vec<String>,
+}
+
+impl DocumentSidecar {
+    pub fn load_from_file<P: AsRef<Path>, F: FileManager>(
+        path: P,
+        file_manager: &F,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let content = file_manager
+            .read_file(path)
+            .and_then(|bytes| {
+                String::from_utf8(bytes).map_err(|e| CleanboxError::InvalidSidecar(e.to_string()))
+            })
+            .map_err(|e| {
+                CleanboxError::InvalidSidecar(format!("Cannot read {}: {}", path.display(), e))
+            })?;
+
+        let name = path.to_string_lossy();
+        if name.ends_with(".json") {
+            serde_json::from_str(&content)
+                .map_err(|e| CleanboxError::InvalidSidecar(format!("{}: {}", path.display(), e)))
+        } else {
+            toml::from_str(&content)
+                .map_err(|e| CleanboxError::InvalidSidecar(format!("{}: {}", path.display(), e)))
+        }
+    }
+
+    /// Convert to a validated DocumentInput, suggesting a date when none is given
+    pub fn to_document_input<P: AsRef<Path>, F: FileManager>(
+        &self,
+        document_path: P,
+        file_manager: &F,
+    ) -> Result<DocumentInput> {
+        DocumentInput::for_document(
+            self.date.clone(),
+            self.description.clone(),
+            self.tags.clone(),
+            document_path,
+            file_manager,
+        )
+    }
+}
+
+/// Returns true if the path names a cleanbox sidecar file.
+pub fn is_sidecar_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| SIDECAR_EXTENSIONS.iter().any(|ext| name.ends_with(ext)))
+        .unwrap_or(false)
+}
+
+/// A cleanbox sidecar as one that follows its document's whole name, so it can be
+/// filed next to the document
+pub fn document_sidecar<P: AsRef<Path>>(sidecar_path: P) -> Option<MediaSidecar> {
+    let path = sidecar_path.as_ref();
+    let name = path.file_name()?.to_str()?;
+    let extension = SIDECAR_EXTENSIONS.iter().find(|ext| name.ends_with(*ext))?;
+    Some(MediaSidecar::after_name(path.to_path_buf(), extension))
+}
+
+/// Returns the document a sidecar file belongs to.
+pub fn sidecar_document_path<P: AsRef<Path>>(sidecar_path: P) -> Option<PathBuf> {
+    let path = sidecar_path.as_ref();
+    let name = path.file_name()?.to_str()?;
+
+    SIDECAR_EXTENSIONS
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .filter(|document_name| !document_name.is_empty())
+        .map(|document_name| path.with_file_name(document_name))
+}
+
+/// A sidecar such as `IMG_1234.xmp`, `IMG_1234.AAE` or Google Takeout's
+/// `IMG_1234.JPG.json`, matched to the media file it describes
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaSidecar {
+    pub path: PathBuf,
+    /// What follows the media file's name or stem, such as `.xmp`
+    suffix: String,
+    /// Whether the suffix follows the whole name (`IMG_1234.JPG.xmp`) rather than the stem
+    follows_name: bool,
+}
+
+impl MediaSidecar {
+    /// A sidecar whose suffix follows the whole name of its media file
+    pub(crate) fn after_name(path: PathBuf, suffix: &str) -> Self {
+        Self {
+            path,
+            suffix: suffix.to_string(),
+            follows_name: true,
+        }
+    }
+
+    /// Name of the sidecar once its media file is named `media_name`
+    pub fn renamed(&self, media_name: &str) -> String {
+        let base = if self.follows_name {
+            media_name
+        } else {
+            Path::new(media_name)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or(media_name)
+        };
+        format!("{}{}", base, self.suffix)
+    }
+
+    /// Path of the sidecar next to its media file at `media_path`
+    pub fn target(&self, media_path: &Path) -> Result<PathBuf> {
+        let media_name = media_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| CleanboxError::InvalidPath(media_path.display().to_string()))?;
+        Ok(media_path.with_file_name(self.renamed(media_name)))
+    }
+}
+
+/// Returns true if the extension is one of a media sidecar, whether or not its media
+/// file is present
+pub fn is_media_sidecar_file<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    !is_sidecar_file(path)
+        && path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                MEDIA_SIDECAR_EXTENSIONS
+                    .iter()
+                    .any(|sidecar| ext.eq_ignore_ascii_case(sidecar))
+            })
+}
+
+/// Returns true for XMP and AAE files, which unlike JSON only ever describe media
+pub fn is_media_only_sidecar_file<P: AsRef<Path>>(path: P) -> bool {
+    is_media_sidecar_file(&path)
+        && path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| !ext.eq_ignore_ascii_case("json"))
+}
+
+/// Split `paths` into files and the media sidecars that belong to one of those files,
+/// keyed by that file. A sidecar without its file in `paths` stays a file.
+pub fn match_media_sidecars(
+    paths: &[PathBuf],
+) -> (Vec<PathBuf>, HashMap<PathBuf, Vec<MediaSidecar>>) {
+    let key = |path: &Path, name: &str| (path.parent().map(Path::to_path_buf), name.to_lowercase());
+    let mut by_name = HashMap::new();
+    let mut by_stem = HashMap::new();
+    for path in paths.iter().filter(|path| !is_media_sidecar_file(path)) {
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            by_name.entry(key(path, name)).or_insert(path);
+        }
+        if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+            by_stem.entry(key(path, stem)).or_insert(path);
+        }
+    }
+
+    let mut files = Vec::new();
+    let mut sidecars: HashMap<PathBuf, Vec<MediaSidecar>> = HashMap::new();
+    for path in paths {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        let lower = name.to_lowercase();
+        let matched = is_media_sidecar_file(path)
+            .then(|| {
+                let after_name = MEDIA_SIDECAR_NAME_SUFFIXES.iter().find_map(|suffix| {
+                    let base = lower.strip_suffix(suffix)?;
+                    let media = by_name.get(&key(path, base))?;
+                    Some((media, base.len(), true))
+                });
+                after_name.or_else(|| {
+                    let (stem, _) = lower.rsplit_once('.')?;
+                    let media = by_stem.get(&key(path, stem))?;
+                    Some((media, stem.len(), false))
+                })
+            })
+            .flatten();
+
+        match matched {
+            Some((media, base_len, follows_name)) => {
+                sidecars
+                    .entry(media.to_path_buf())
+                    .or_default()
+                    .push(MediaSidecar {
+                        path: path.clone(),
+                        suffix: name[base_len..].to_string(),
+                        follows_name,
+                    });
+            }
+            None => files.push(path.clone()),
+        }
+    }
+
+    (files, sidecars)
+}
+
+/// Like `match_media_sidecars`, also matching the cleanbox sidecars that were filed
+/// next to a document
+pub fn match_document_sidecars(
+    paths: &[PathBuf],
+) -> (Vec<PathBuf>, HashMap<PathBuf, Vec<MediaSidecar>>) {
+    let (files, mut sidecars) = match_media_sidecars(paths);
+    let documents: HashSet<PathBuf> = files
+        .iter()
+        .filter(|path| !is_sidecar_file(path))
+        .cloned()
+        .collect();
+
+    let mut unmatched = Vec::new();
+    for path in files {
+        let document = sidecar_document_path(&path).filter(|document| documents.contains(document));
+        match (document, document_sidecar(&path)) {
+            (Some(document), Some(sidecar)) => sidecars.entry(document).or_default().push(sidecar),
+            _ => unmatched.push(path),
+        }
+    }
+    (unmatched, sidecars)
+}
+
+/// Finds the sidecar for a document, preferring TOML over JSON.
+pub fn find_sidecar<P: AsRef<Path>, F: FileManager>(
+    document_path: P,
+    file_manager: &F,
+) -> Option<PathBuf> {
+    let path = document_path.as_ref();
+    let name = path.file_name()?.to_str()?;
+
+    SIDECAR_EXTENSIONS
+        .iter()
+        .map(|ext| path.with_file_name(format!("{name}{ext}")))
+        .find(|candidate| file_manager.is_file(candidate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::{MockFileManager, StdFileManager};
+    use std::fs;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_sidecar_file() {
+        assert!(is_sidecar_file("/inbox/statement.pdf.cleanbox.toml"));
+        assert!(is_sidecar_file("/inbox/statement.pdf.cleanbox.json"));
+        assert!(!is_sidecar_file("/inbox/statement.pdf"));
+        assert!(!is_sidecar_file("/inbox/config.toml"));
+    }
+
+    #[test]
+    fn test_sidecar_document_path() {
+        assert_eq!(
+            sidecar_document_path("/inbox/statement.pdf.cleanbox.toml"),
+            Some(PathBuf::from("/inbox/statement.pdf"))
+        );
+        assert_eq!(sidecar_document_path("/inbox/.cleanbox.json"), None);
+        assert_eq!(sidecar_document_path("/inbox/statement.pdf"), None);
+    }
+
+    #[test]
+    fn test_find_sidecar_prefers_toml() {
+        let mut file_manager = MockFileManager::new();
+        file_manager.add_file(PathBuf::from("/inbox/a.pdf"), vec![]);
+        file_manager.add_file(PathBuf::from("/inbox/a.pdf.cleanbox.json"), vec![]);
+        file_manager.add_file(PathBuf::from("/inbox/a.pdf.cleanbox.toml"), vec![]);
+        file_manager.add_file(PathBuf::from("/inbox/b.pdf"), vec![]);
+
+        assert_eq!(
+            find_sidecar("/inbox/a.pdf", &file_manager),
+            Some(PathBuf::from("/inbox/a.pdf.cleanbox.toml"))
+        );
+        assert_eq!(find_sidecar("/inbox/b.pdf", &file_manager), None);
+    }
+
+    #[test]
+    fn test_match_media_sidecars() {
+        let paths: Vec<PathBuf> = [
+            "/inbox/IMG_0001.CR2",
+            "/inbox/IMG_0001.JPG",
+            "/inbox/IMG_0001.xmp",
+            "/inbox/IMG_0002.HEIC",
+            "/inbox/IMG_0002.AAE",
+            "/inbox/IMG_0002.HEIC.supplemental-metadata.json",
+            "/inbox/IMG_0003.JPG.json",
+            "/inbox/export.json",
+            "/inbox/statement.pdf.cleanbox.json",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        let (files, sidecars) = match_media_sidecars(&paths);
+
+        assert_eq!(
+            files,
+            [
+                "/inbox/IMG_0001.CR2",
+                "/inbox/IMG_0001.JPG",
+                "/inbox/IMG_0002.HEIC",
+                "/inbox/IMG_0003.JPG.json",
+                "/inbox/export.json",
+                "/inbox/statement.pdf.cleanbox.json",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+
+        let renamed = |media: &str, name: &str| -> Vec<String> {
+            sidecars[&PathBuf::from(media)]
+                .iter()
+                .map(|sidecar| sidecar.renamed(name))
+                .collect()
+        };
+        // A stem match goes to the first file with that stem
+        assert_eq!(
+            renamed("/inbox/IMG_0001.CR2", "2024-05-01_10-00-00.CR2"),
+            vec!["2024-05-01_10-00-00.xmp"]
+        );
+        assert_eq!(
+            renamed("/inbox/IMG_0002.HEIC", "2024-05-01_10-00-00.HEIC"),
+            vec![
+                "2024-05-01_10-00-00.AAE",
+                "2024-05-01_10-00-00.HEIC.supplemental-metadata.json"
+            ]
+        );
+        assert_eq!(sidecars.len(), 2);
+    }
+
+    #[test]
+    fn test_load_toml_sidecar() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("statement.pdf.cleanbox.toml");
+        let mut file = fs::File::create(&path).unwrap();
+        writeln!(
+            file,
+            "date = \"2024-05-01\"\ndescription = \"bank-statement\"\ntags = [\"finance\", \"bank\"]"
+        )
+        .unwrap();
+
+        let sidecar = DocumentSidecar::load_from_file(&path, &StdFileManager::new()).unwrap();
+        assert_eq!(sidecar.date, Some("2024-05-01".to_string()));
+        assert_eq!(sidecar.description, "bank-statement");
+        assert_eq!(sidecar.tags, vec!["finance", "bank"]);
+    }
+
+    #[test]
+    fn test_load_json_sidecar_without_date() {
+        let temp_dir = TempDir::new().unwrap();
+        let document = temp_dir.path().join("statement_20240501.pdf");
+        let path = temp_dir.path().join("statement_20240501.pdf.cleanbox.json");
+        fs::write(
+            &path,
+            r#"{"description": "bank-statement", "tags": ["finance"]}"#,
+        )
+        .unwrap();
+
+        let sidecar = DocumentSidecar::load_from_file(&path, &StdFileManager::new()).unwrap();
+        assert!(sidecar.date.is_none());
+
+        // Missing date falls back to the suggested document date
+        let input = sidecar
+            .to_document_input(&document, &StdFileManager::new())
+            .unwrap();
+        assert_eq!(input.date, "2024-05-01");
+    }
+
+    #[test]
+    fn test_load_invalid_sidecar() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("statement.pdf.cleanbox.toml");
+        fs::write(&path, "description = ").unwrap();
+
+        assert!(matches!(
+            DocumentSidecar::load_from_file(&path, &StdFileManager::new()).unwrap_err(),
+            CleanboxError::InvalidSidecar(_)
+        ));
+    }
+
+    #[test]
+    fn test_sidecar_input_is_validated() {
+        let sidecar = DocumentSidecar {
+            date: Some("2024-05-01".to_string()),
+            description: "Not Kebab".to_string(),
+            tags: vec!["finance".to_string()],
+        };
+
+        assert!(
+            sidecar
+                .to_document_input("/inbox/a.pdf", &MockFileManager::new())
+                .is_err()
+        );
+    }
+}
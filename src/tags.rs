@@ -1,4 +1,5 @@
 use crate::error::{CleanboxError, Result};
+use crate::filesystem::FileManager;
 use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
@@ -24,6 +25,11 @@ impl TagDictionary {
             ))
         })?;
 
+        Self::parse(&content)
+    }
+
+    /// Parse the tags file format: one tag per line, blank lines ignored
+    pub fn parse(content: &str) -> Result<Self> {
         let mut tags = HashSet::new();
 
         for line in content.lines() {
@@ -40,19 +46,29 @@ impl TagDictionary {
         Ok(Self { tags })
     }
 
-    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    pub fn save_to_file<P: AsRef<Path>, F: FileManager>(
+        &self,
+        path: P,
+        file_manager: &F,
+    ) -> Result<()> {
+        let content = self.to_file_content();
+        file_manager
+            .write_file(&path, content.as_bytes())
+            .map_err(|e| {
+                CleanboxError::TagDictionaryCorrupted(format!(
+                    "Cannot write tags file at {}: {}",
+                    path.as_ref().display(),
+                    e
+                ))
+            })
+    }
+
+    /// Serialize in the tags file format
+    pub fn to_file_content(&self) -> String {
         let mut tags: Vec<&str> = self.tags.iter().map(|s| s.as_str()).collect();
         tags.sort(); // Save in alphabetical order
 
-        let content = tags.join("\n") + "\n"; // Add final newline
-
-        fs::write(&path, content).map_err(|e| {
-            CleanboxError::TagDictionaryCorrupted(format!(
-                "Cannot write tags file at {}: {}",
-                path.as_ref().display(),
-                e
-            ))
-        })
+        tags.join("\n") + "\n" // Add final newline
     }
 
     pub fn contains(&self, tag: &str) -> bool {
@@ -243,6 +259,7 @@ pub enum TagResolution {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filesystem::StdFileManager;
     use std::fs;
     use std::path::PathBuf;
 
@@ -288,7 +305,8 @@ mod tests {
         dict.add_tag("reports".to_string()).unwrap();
         dict.add_tag("data-science".to_string()).unwrap();
 
-        dict.save_to_file(&test_file).unwrap();
+        dict.save_to_file(&test_file, &StdFileManager::new())
+            .unwrap();
 
         // Load it back and verify
         let loaded_dict = TagDictionary::load_from_file(&test_file).unwrap();
@@ -0,0 +1,468 @@
+use crate::error::{CleanboxError, Result};
+use crate::media::File;
+use crate::naming::DATETIME_FORMAT;
+use chrono::NaiveDateTime;
+use chrono::format::{Item, StrftimeItems};
+use std::fmt;
+
+/// A value a template placeholder can refer to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Datetime,
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Original,
+    Stem,
+    Ext,
+    Hash,
+    /// First six characters of the hash, kept for older templates
+    Hash6,
+    MediaType,
+    /// Make and model together, such as `Apple iPhone 15 Pro`
+    Camera,
+    Make,
+    Model,
+    Lens,
+    Serial,
+    /// Milliseconds of the capture time
+    Subsec,
+    City,
+    Country,
+    /// City and country together, such as `Lisbon, Portugal`
+    Place,
+    /// Position of the file among those named in this run, starting at 1
+    Counter,
+}
+
+impl Field {
+    const ALL: [Field; 23] = [
+        Field::Datetime,
+        Field::Year,
+        Field::Month,
+        Field::Day,
+        Field::Hour,
+        Field::Minute,
+        Field::Second,
+        Field::Original,
+        Field::Stem,
+        Field::Ext,
+        Field::Hash,
+        Field::Hash6,
+        Field::MediaType,
+        Field::Camera,
+        Field::Make,
+        Field::Model,
+        Field::Lens,
+        Field::Serial,
+        Field::Subsec,
+        Field::City,
+        Field::Country,
+        Field::Place,
+        Field::Counter,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Datetime => "datetime",
+            Field::Year => "year",
+            Field::Month => "month",
+            Field::Day => "day",
+            Field::Hour => "hour",
+            Field::Minute => "minute",
+            Field::Second => "second",
+            Field::Original => "original",
+            Field::Stem => "stem",
+            Field::Ext => "ext",
+            Field::Hash => "hash",
+            Field::Hash6 => "hash6",
+            Field::MediaType => "media_type",
+            Field::Camera => "camera",
+            Field::Make => "make",
+            Field::Model => "model",
+            Field::Lens => "lens",
+            Field::Serial => "serial",
+            Field::Subsec => "subsec",
+            Field::City => "city",
+            Field::Country => "country",
+            Field::Place => "place",
+            Field::Counter => "counter",
+        }
+    }
+
+    /// Whether the value comes from the file name or datetime alone
+    fn is_from_name_or_datetime(self) -> bool {
+        matches!(
+            self,
+            Field::Datetime
+                | Field::Year
+                | Field::Month
+                | Field::Day
+                | Field::Hour
+                | Field::Minute
+                | Field::Second
+                | Field::Original
+                | Field::Stem
+                | Field::Ext
+                | Field::Counter
+        )
+    }
+}
+
+/// How a placeholder value is formatted
+#[derive(Debug, Clone, PartialEq)]
+enum Spec {
+    /// strftime format for `{datetime:%Y%m%d}`
+    Strftime(String),
+    /// Leading characters kept for `{hash:8}`
+    Length(usize),
+    /// Zero padding for `{counter:3}`
+    Width(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Placeholder {
+    field: Field,
+    spec: Option<Spec>,
+    /// Text used when the value is unavailable, as in `{camera|unknown}`
+    fallback: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// A naming or folder template such as `{datetime:%Y%m%d}_{camera|unknown}.{ext}`.
+///
+/// Placeholders are `{field}`, `{field:spec}` or `{field|fallback}`; `{{` and `}}` are
+/// literal braces. Templates are checked when parsed, and a value that is unavailable
+/// at render time is an error unless the placeholder has a fallback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        let invalid = |msg: String| CleanboxError::InvalidTemplate(format!("{source}: {msg}"));
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(invalid("unmatched '}'".to_string())),
+                '{' => {
+                    let mut body = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => {
+                                return Err(invalid("unclosed '{'".to_string()));
+                            }
+                            Some(c) => body.push(c),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    let placeholder = Self::parse_placeholder(&body).map_err(invalid)?;
+                    segments.push(Segment::Placeholder(placeholder));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    fn parse_placeholder(body: &str) -> std::result::Result<Placeholder, String> {
+        let (body, fallback) = match body.split_once('|') {
+            Some((body, fallback)) => (body, Some(fallback.to_string())),
+            None => (body, None),
+        };
+        let (name, spec) = match body.split_once(':') {
+            Some((name, spec)) => (name.trim(), Some(spec)),
+            None => (body.trim(), None),
+        };
+
+        let field = Field::ALL
+            .into_iter()
+            .find(|field| field.name() == name)
+            .ok_or_else(|| format!("unknown placeholder {{{name}}}"))?;
+
+        let number = |spec: &str| {
+            spec.parse::<usize>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("{{{name}:{spec}}} needs a positive number"))
+        };
+        let spec = match (field, spec) {
+            (_, None) => None,
+            (Field::Datetime, Some(spec)) => {
+                let valid = !spec.is_empty()
+                    && StrftimeItems::new(spec).all(|item| !matches!(item, Item::Error));
+                if !valid {
+                    return Err(format!("invalid datetime format '{spec}'"));
+                }
+                Some(Spec::Strftime(spec.to_string()))
+            }
+            (Field::Hash, Some(spec)) => Some(Spec::Length(number(spec)?)),
+            (Field::Counter, Some(spec)) => Some(Spec::Width(number(spec)?)),
+            (field, Some(_)) => {
+                return Err(format!("{{{}}} does not take a format", field.name()));
+            }
+        };
+
+        Ok(Placeholder {
+            field,
+            spec,
+            fallback,
+        })
+    }
+
+    /// Whether any placeholder refers to `field`
+    pub fn uses(&self, field: Field) -> bool {
+        self.placeholders()
+            .any(|placeholder| placeholder.field == field)
+    }
+
+    /// Whether rendering needs metadata beyond the datetime, such as the camera
+    pub fn needs_file_metadata(&self) -> bool {
+        self.placeholders()
+            .any(|placeholder| !placeholder.field.is_from_name_or_datetime())
+    }
+
+    /// Whether rendering needs the place a GPS position was geocoded to
+    pub fn needs_location(&self) -> bool {
+        [Field::City, Field::Country, Field::Place]
+            .into_iter()
+            .any(|field| self.uses(field))
+    }
+
+    fn placeholders(&self) -> impl Iterator<Item = &Placeholder> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Placeholder(placeholder) => Some(placeholder),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Fill in the placeholders for `file`; `counter` is the value of `{counter}`
+    pub fn render(&self, file: &File, counter: u64) -> Result<String> {
+        let mut result = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => result.push_str(text),
+                Segment::Placeholder(placeholder) => {
+                    match (
+                        self.value(placeholder, file, counter)?,
+                        &placeholder.fallback,
+                    ) {
+                        (Some(value), _) => result.push_str(&value),
+                        (None, Some(fallback)) => result.push_str(fallback),
+                        (None, None) => {
+                            return Err(CleanboxError::Exif(format!(
+                                "No {} available for {}",
+                                placeholder.field.name(),
+                                self.source
+                            )));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn value(
+        &self,
+        placeholder: &Placeholder,
+        file: &File,
+        counter: u64,
+    ) -> Result<Option<String>> {
+        let metadata = file.metadata.as_ref();
+        let datetime = metadata.and_then(|m| m.datetime_original.as_deref());
+        let parsed = || -> Result<Option<NaiveDateTime>> {
+            datetime
+                .map(|datetime| {
+                    NaiveDateTime::parse_from_str(datetime, DATETIME_FORMAT).map_err(|_| {
+                        CleanboxError::InvalidDateTime(format!(
+                            "Expected YYYY-MM-DD_HH-MM-SS, got {datetime}"
+                        ))
+                    })
+                })
+                .transpose()
+        };
+        let formatted = |format: &str| -> Result<Option<String>> {
+            Ok(parsed()?.map(|datetime| datetime.format(format).to_string()))
+        };
+        let hash = metadata.and_then(|m| m.file_hash.as_deref());
+        let prefix = |hash: &str, length: usize| hash[..length.min(hash.len())].to_string();
+
+        let value = match (placeholder.field, &placeholder.spec) {
+            (Field::Datetime, Some(Spec::Strftime(format))) => formatted(format)?,
+            (Field::Datetime, _) => datetime.map(str::to_string),
+            (Field::Year, _) => formatted("%Y")?,
+            (Field::Month, _) => formatted("%m")?,
+            (Field::Day, _) => formatted("%d")?,
+            (Field::Hour, _) => formatted("%H")?,
+            (Field::Minute, _) => formatted("%M")?,
+            (Field::Second, _) => formatted("%S")?,
+            (Field::Original, _) => Some(file.file_name()?.to_string()),
+            (Field::Stem, _) => Some(file.file_stem()?.to_string()),
+            (Field::Ext, _) => Some(file.extension()?.to_string()),
+            (Field::Hash, Some(Spec::Length(length))) => hash.map(|hash| prefix(hash, *length)),
+            (Field::Hash, _) => hash.map(str::to_string),
+            (Field::Hash6, _) => hash.map(|hash| prefix(hash, 6)),
+            (Field::MediaType, _) => metadata.map(|m| format!("{:?}", m.file_type).to_lowercase()),
+            (Field::Camera, _) => metadata.and_then(|m| m.camera().as_deref().map(sanitize)),
+            (Field::Make, _) => metadata.and_then(|m| m.camera_make.as_deref().map(sanitize)),
+            (Field::Model, _) => metadata.and_then(|m| m.camera_model.as_deref().map(sanitize)),
+            (Field::Lens, _) => metadata.and_then(|m| m.lens_model.as_deref().map(sanitize)),
+            (Field::Serial, _) => metadata.and_then(|m| m.serial_number.as_deref().map(sanitize)),
+            (Field::Subsec, _) => metadata.and_then(|m| m.subsec_time.clone()),
+            (Field::City, _) => metadata.and_then(|m| m.city.as_deref().map(sanitize)),
+            (Field::Country, _) => metadata.and_then(|m| m.country.as_deref().map(sanitize)),
+            (Field::Place, _) => metadata.and_then(|m| m.place().as_deref().map(sanitize)),
+            (Field::Counter, Some(Spec::Width(width))) => Some(format!("{counter:0width$}")),
+            (Field::Counter, _) => Some(counter.to_string()),
+        };
+        Ok(value.filter(|value| !value.is_empty()))
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Keep free-text metadata from adding path separators or stray whitespace
+pub(crate) fn sanitize(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\') { '-' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::FileMetadata;
+
+    fn photo() -> File {
+        let metadata = FileMetadata::new("image/jpeg".to_string())
+            .with_datetime("2023-12-01_14-30-05".to_string())
+            .with_hash("abcdef123456".to_string())
+            .with_camera_make("Google".to_string())
+            .with_camera_model("Pixel 8 / Pro".to_string())
+            .with_subsec_time("250".to_string());
+        File::new("/inbox/IMG_0001.jpg").with_metadata(metadata)
+    }
+
+    fn render(template: &str, file: &File) -> Result<String> {
+        Template::parse(template)?.render(file, 7)
+    }
+
+    #[test]
+    fn test_render() {
+        let file = photo();
+        assert_eq!(
+            render("{datetime:%Y%m%d}_{hour}{minute}{second}.{ext}", &file).unwrap(),
+            "20231201_143005.jpg"
+        );
+        assert_eq!(
+            render("{year}/{month}/{camera}", &file).unwrap(),
+            "2023/12/Google Pixel 8 - Pro"
+        );
+        assert_eq!(
+            render("{model}_{second}.{subsec}_{serial|none}", &file).unwrap(),
+            "Pixel 8 - Pro_05.250_none"
+        );
+        assert_eq!(
+            render("{stem}_{hash:8}_{hash6}_{counter:3}", &file).unwrap(),
+            "IMG_0001_abcdef12_abcdef_007"
+        );
+        assert_eq!(
+            render("{{{original}}}_{media_type}", &file).unwrap(),
+            "{IMG_0001.jpg}_image"
+        );
+
+        let mut metadata = file.metadata.clone().unwrap();
+        metadata.city = Some("Lisbon".to_string());
+        metadata.country = Some("Portugal".to_string());
+        let file = file.with_metadata(metadata);
+        assert_eq!(
+            render("{year}/{year}-{month} {city}/{country}/{place}", &file).unwrap(),
+            "2023/2023-12 Lisbon/Portugal/Lisbon, Portugal"
+        );
+    }
+
+    #[test]
+    fn test_fallbacks_and_missing_values() {
+        let file = photo();
+        assert_eq!(
+            render("{lens|no-lens}_{city|}{datetime}", &file).unwrap(),
+            "no-lens_2023-12-01_14-30-05"
+        );
+
+        let undated =
+            File::new("/inbox/a.jpg").with_metadata(FileMetadata::new("image/jpeg".to_string()));
+        assert_eq!(render("{year|undated}", &undated).unwrap(), "undated");
+        let error = render("{year}.{ext}", &undated).unwrap_err();
+        assert_eq!(error.kind(), "exif");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for template in [
+            "{yaer}.{ext}",
+            "{datetime:%Q}",
+            "{hash:zero}",
+            "{counter:0}",
+            "{year:%Y}",
+            "{year",
+            "year}",
+            "{a{b}}",
+        ] {
+            let error = Template::parse(template).unwrap_err();
+            assert_eq!(error.kind(), "invalid_template", "{template}");
+        }
+    }
+
+    #[test]
+    fn test_fields_used() {
+        let template = Template::parse("{year}/{camera|unknown}").unwrap();
+        assert!(template.uses(Field::Camera));
+        assert!(!template.uses(Field::Counter));
+        assert!(template.needs_file_metadata());
+        assert!(!template.needs_location());
+        assert!(Template::parse("{year}/{place}").unwrap().needs_location());
+        assert!(
+            !Template::parse("{year}/{month}")
+                .unwrap()
+                .needs_file_metadata()
+        );
+    }
+}
@@ -0,0 +1,704 @@
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::FileManager;
+use crate::interactive::UserPrompt;
+use crate::media::{FileMetadata, FileType};
+use crate::metadata::MetadataParser;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
+struct VirtualFile {
+    contents: Vec<u8>,
+    modified: SystemTime,
+}
+
+#[derive(Debug, Default)]
+struct VirtualTree {
+    files: BTreeMap<PathBuf, VirtualFile>,
+    directories: BTreeSet<PathBuf>,
+}
+
+impl VirtualTree {
+    fn add_directories(&mut self, path: &Path) {
+        for ancestor in path.ancestors() {
+            if ancestor.as_os_str().is_empty() {
+                break;
+            }
+            self.directories.insert(ancestor.to_path_buf());
+        }
+    }
+}
+
+fn not_found(path: &Path) -> CleanboxError {
+    CleanboxError::IoAtPath {
+        path: path.to_path_buf(),
+        source: io::Error::from(io::ErrorKind::NotFound),
+    }
+}
+
+/// FileManager backed by an in-memory tree that really applies renames, moves and removals.
+///
+/// Clones share the same tree, so a test can hand one to a processor and inspect the
+/// result through another. Files added without a modification time get the Unix epoch.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryFileManager {
+    tree: Arc<Mutex<VirtualTree>>,
+}
+
+impl InMemoryFileManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tree(&self) -> MutexGuard<'_, VirtualTree> {
+        self.tree
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Add a file, creating its parent directories
+    pub fn add_file<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) {
+        self.add_file_with_modified_time(path, contents, SystemTime::UNIX_EPOCH);
+    }
+
+    pub fn add_file_with_modified_time<P: AsRef<Path>, C: AsRef<[u8]>>(
+        &self,
+        path: P,
+        contents: C,
+        modified_time: SystemTime,
+    ) {
+        let path = path.as_ref();
+        let mut tree = self.tree();
+        if let Some(parent) = path.parent() {
+            tree.add_directories(parent);
+        }
+        tree.files.insert(
+            path.to_path_buf(),
+            VirtualFile {
+                contents: contents.as_ref().to_vec(),
+                modified: modified_time,
+            },
+        );
+    }
+
+    pub fn add_directory<P: AsRef<Path>>(&self, path: P) {
+        self.tree().add_directories(path.as_ref());
+    }
+
+    pub fn contents<P: AsRef<Path>>(&self, path: P) -> Option<Vec<u8>> {
+        self.tree()
+            .files
+            .get(path.as_ref())
+            .map(|file| file.contents.clone())
+    }
+
+    pub fn is_directory<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.tree().directories.contains(path.as_ref())
+    }
+
+    /// All files in the tree, sorted
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.tree().files.keys().cloned().collect()
+    }
+
+    /// Files below `root`, relative to it and sorted
+    pub fn files_under<P: AsRef<Path>>(&self, root: P) -> Vec<PathBuf> {
+        let root = root.as_ref();
+        self.tree()
+            .files
+            .keys()
+            .filter_map(|path| path.strip_prefix(root).ok())
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    #[track_caller]
+    pub fn assert_file<P: AsRef<Path>>(&self, path: P) {
+        let path = path.as_ref();
+        assert!(
+            self.tree().files.contains_key(path),
+            "expected file {}, tree has {:?}",
+            path.display(),
+            self.files()
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_no_file<P: AsRef<Path>>(&self, path: P) {
+        let path = path.as_ref();
+        assert!(
+            !self.tree().files.contains_key(path),
+            "expected no file at {}",
+            path.display()
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_contents<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, expected: C) {
+        let path = path.as_ref();
+        match self.contents(path) {
+            Some(contents) => assert_eq!(
+                contents,
+                expected.as_ref(),
+                "unexpected contents in {}",
+                path.display()
+            ),
+            None => panic!(
+                "expected file {}, tree has {:?}",
+                path.display(),
+                self.files()
+            ),
+        }
+    }
+
+    /// Assert the exact set of files below `root`, given as `/`-separated relative paths
+    #[track_caller]
+    pub fn assert_tree<P: AsRef<Path>>(&self, root: P, expected: &[&str]) {
+        let actual: Vec<String> = self
+            .files_under(root)
+            .iter()
+            .map(|path| path.to_string_lossy().replace('\\', "/"))
+            .collect();
+        let mut expected: Vec<String> = expected.iter().map(|path| path.to_string()).collect();
+        expected.sort();
+        assert_eq!(actual, expected);
+    }
+}
+
+impl FileManager for InMemoryFileManager {
+    fn read_directory<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>> {
+        let path = path.as_ref();
+        let tree = self.tree();
+        if !tree.directories.contains(path) {
+            return Err(not_found(path));
+        }
+
+        let mut entries: Vec<PathBuf> = tree
+            .files
+            .keys()
+            .chain(tree.directories.iter())
+            .filter(|entry| entry.parent() == Some(path))
+            .cloned()
+            .collect();
+        entries.sort();
+        Ok(entries)
+    }
+
+    fn create_directories<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.tree().add_directories(path.as_ref());
+        Ok(())
+    }
+
+    fn rename_file<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let mut tree = self.tree();
+
+        if let Some(parent) = to.parent()
+            && !parent.as_os_str().is_empty()
+            && !tree.directories.contains(parent)
+        {
+            return Err(not_found(parent));
+        }
+
+        // Like rename(2), an existing target is replaced
+        let file = tree.files.remove(from).ok_or_else(|| not_found(from))?;
+        tree.files.insert(to.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn move_file<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        if let Some(parent) = to.as_ref().parent() {
+            self.create_directories(parent)?;
+        }
+        self.rename_file(from, to)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        self.tree()
+            .files
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn remove_directory<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut tree = self.tree();
+
+        let has_entries = tree
+            .files
+            .keys()
+            .chain(tree.directories.iter())
+            .any(|entry| entry.parent() == Some(path));
+        if has_entries {
+            return Err(CleanboxError::IoAtPath {
+                path: path.to_path_buf(),
+                source: io::Error::from(io::ErrorKind::DirectoryNotEmpty),
+            });
+        }
+
+        if tree.directories.remove(path) {
+            Ok(())
+        } else {
+            Err(not_found(path))
+        }
+    }
+
+    fn file_exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        let tree = self.tree();
+        tree.files.contains_key(path.as_ref()) || tree.directories.contains(path.as_ref())
+    }
+
+    fn is_file<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.tree().files.contains_key(path.as_ref())
+    }
+
+    fn file_size<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        let path = path.as_ref();
+        self.tree()
+            .files
+            .get(path)
+            .map(|file| file.contents.len() as u64)
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        let path = path.as_ref();
+        self.contents(path).ok_or_else(|| not_found(path))
+    }
+
+    fn write_file<P: AsRef<Path>>(&self, path: P, contents: &[u8]) -> Result<()> {
+        let path = path.as_ref();
+        let mut tree = self.tree();
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+            && !tree.directories.contains(parent)
+        {
+            return Err(not_found(parent));
+        }
+
+        tree.files.insert(
+            path.to_path_buf(),
+            VirtualFile {
+                contents: contents.to_vec(),
+                modified: SystemTime::now(),
+            },
+        );
+        Ok(())
+    }
+
+    fn get_file_modified_time<P: AsRef<Path>>(&self, path: P) -> Result<SystemTime> {
+        let path = path.as_ref();
+        self.tree()
+            .files
+            .get(path)
+            .map(|file| file.modified)
+            .ok_or_else(|| not_found(path))
+    }
+}
+
+/// MetadataParser that never reads file contents: the MIME type comes from the
+/// extension and datetimes from a table, so it pairs with InMemoryFileManager
+#[derive(Debug, Clone, Default)]
+pub struct StaticMetadataParser {
+    datetimes: HashMap<PathBuf, String>,
+    metadata: HashMap<PathBuf, FileMetadata>,
+}
+
+impl StaticMetadataParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the EXIF datetime (`YYYY-MM-DD_HH-MM-SS`) reported for a file
+    pub fn with_datetime<P: AsRef<Path>>(mut self, path: P, datetime: &str) -> Self {
+        self.datetimes
+            .insert(path.as_ref().to_path_buf(), datetime.to_string());
+        self
+    }
+
+    /// Report `metadata` for a file as it is, for camera details and the like
+    pub fn with_metadata<P: AsRef<Path>>(mut self, path: P, metadata: FileMetadata) -> Self {
+        self.metadata.insert(path.as_ref().to_path_buf(), metadata);
+        self
+    }
+
+    fn mime_from_extension(path: &Path) -> &'static str {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+
+        match extension.as_deref() {
+            Some("jpg" | "jpeg") => "image/jpeg",
+            Some("png") => "image/png",
+            Some("gif") => "image/gif",
+            Some("heic") => "image/heic",
+            Some("avif") => "image/avif",
+            Some("cr2") => "image/x-canon-cr2",
+            Some("cr3") => "image/x-canon-cr3",
+            Some("raf") => "image/x-fuji-raf",
+            Some("dng" | "nef" | "arw") => "image/tiff",
+            Some("mp4") => "video/mp4",
+            Some("mov") => "video/quicktime",
+            Some("pdf") => "application/pdf",
+            Some("txt") => "text/plain",
+            Some(_) => "application/octet-stream",
+            None => "",
+        }
+    }
+}
+
+impl MetadataParser for StaticMetadataParser {
+    fn parse_metadata<P: AsRef<Path>>(&self, file_path: P) -> Result<FileMetadata> {
+        if let Some(metadata) = self.metadata.get(file_path.as_ref()) {
+            return Ok(metadata.clone());
+        }
+        let metadata = FileMetadata::new(Self::mime_from_extension(file_path.as_ref()).to_string());
+
+        match self.datetimes.get(file_path.as_ref()) {
+            Some(datetime) => Ok(metadata.with_datetime(datetime.clone())),
+            None => Ok(metadata),
+        }
+    }
+
+    fn extract_datetime<P: AsRef<Path>>(&self, file_path: P) -> Result<String> {
+        self.metadata
+            .get(file_path.as_ref())
+            .and_then(|metadata| metadata.datetime_original.clone())
+            .or_else(|| self.datetimes.get(file_path.as_ref()).cloned())
+            .ok_or_else(|| CleanboxError::Exif("No datetime found".to_string()))
+    }
+
+    fn supports_file_type(&self, file_type: &FileType) -> bool {
+        matches!(file_type, FileType::Image | FileType::Video)
+    }
+}
+
+/// One answer in a ScriptedPrompt script
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptedAnswer {
+    Text(String),
+    Confirm(bool),
+    Select(usize),
+    /// Accept the prompt's default
+    Default,
+    /// Cancel, as Ctrl-C does in the terminal
+    Cancel,
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    answers: VecDeque<ScriptedAnswer>,
+    asked: Vec<String>,
+}
+
+/// UserPrompt that replays a fixed script of answers in order.
+///
+/// Clones share the script, so the prompts created inside a processor consume one
+/// sequence. An answer of the wrong kind or an exhausted script is an error.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedPrompt {
+    script: Rc<RefCell<Script>>,
+}
+
+impl ScriptedPrompt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_answer(self, answer: ScriptedAnswer) -> Self {
+        self.script.borrow_mut().answers.push_back(answer);
+        self
+    }
+
+    pub fn with_text(self, text: &str) -> Self {
+        self.with_answer(ScriptedAnswer::Text(text.to_string()))
+    }
+
+    pub fn with_confirmation(self, confirmed: bool) -> Self {
+        self.with_answer(ScriptedAnswer::Confirm(confirmed))
+    }
+
+    pub fn with_selection(self, index: usize) -> Self {
+        self.with_answer(ScriptedAnswer::Select(index))
+    }
+
+    pub fn with_default(self) -> Self {
+        self.with_answer(ScriptedAnswer::Default)
+    }
+
+    pub fn with_cancel(self) -> Self {
+        self.with_answer(ScriptedAnswer::Cancel)
+    }
+
+    /// Messages of all prompts asked so far, in order
+    pub fn asked(&self) -> Vec<String> {
+        self.script.borrow().asked.clone()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.script.borrow().answers.len()
+    }
+
+    #[track_caller]
+    pub fn assert_finished(&self) {
+        let script = self.script.borrow();
+        assert!(
+            script.answers.is_empty(),
+            "unused scripted answers {:?} after prompts {:?}",
+            script.answers,
+            script.asked
+        );
+    }
+
+    fn next_answer(&self, message: &str) -> Result<ScriptedAnswer> {
+        let mut script = self.script.borrow_mut();
+        script.asked.push(message.to_string());
+        match script.answers.pop_front() {
+            Some(ScriptedAnswer::Cancel) => Err(CleanboxError::UserCancelled),
+            Some(answer) => Ok(answer),
+            None => Err(CleanboxError::InvalidUserInput(format!(
+                "No scripted answer left for '{message}'"
+            ))),
+        }
+    }
+
+    fn mismatch(message: &str, answer: &ScriptedAnswer) -> CleanboxError {
+        CleanboxError::InvalidUserInput(format!(
+            "Scripted answer {answer:?} does not fit '{message}'"
+        ))
+    }
+}
+
+impl UserPrompt for ScriptedPrompt {
+    fn prompt_string(&self, message: &str, default: Option<&str>) -> Result<String> {
+        match (self.next_answer(message)?, default) {
+            (ScriptedAnswer::Text(text), Some(default)) if text.trim().is_empty() => {
+                Ok(default.to_string())
+            }
+            (ScriptedAnswer::Text(text), _) => Ok(text.trim().to_string()),
+            (ScriptedAnswer::Default, Some(default)) => Ok(default.to_string()),
+            (answer, _) => Err(Self::mismatch(message, &answer)),
+        }
+    }
+
+    fn prompt_confirmation(&self, message: &str, default: bool) -> Result<bool> {
+        match self.next_answer(message)? {
+            ScriptedAnswer::Confirm(confirmed) => Ok(confirmed),
+            ScriptedAnswer::Default => Ok(default),
+            answer => Err(Self::mismatch(message, &answer)),
+        }
+    }
+
+    fn prompt_selection(&self, message: &str, options: &[&str]) -> Result<usize> {
+        match self.next_answer(message)? {
+            ScriptedAnswer::Select(index) if index < options.len() => Ok(index),
+            answer => Err(Self::mismatch(message, &answer)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LifeConfig;
+    use crate::processor::UnifiedProcessor;
+    use std::time::Duration;
+
+    #[test]
+    fn test_in_memory_file_manager_moves_files() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file_with_modified_time(
+            "/life/inbox/a.jpg",
+            b"photo",
+            SystemTime::UNIX_EPOCH + Duration::from_secs(60),
+        );
+
+        file_manager
+            .move_file("/life/inbox/a.jpg", "/life/media/2024/05/b.jpg")
+            .unwrap();
+
+        file_manager.assert_no_file("/life/inbox/a.jpg");
+        file_manager.assert_contents("/life/media/2024/05/b.jpg", b"photo");
+        assert!(file_manager.is_directory("/life/media/2024/05"));
+        // Moves keep the modification time
+        assert_eq!(
+            file_manager
+                .get_file_modified_time("/life/media/2024/05/b.jpg")
+                .unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn test_in_memory_file_manager_rename_requires_parent() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/inbox/a.jpg", b"photo");
+
+        assert!(
+            file_manager
+                .rename_file("/inbox/a.jpg", "/missing/a.jpg")
+                .is_err()
+        );
+        assert!(
+            file_manager
+                .rename_file("/inbox/none.jpg", "/inbox/b.jpg")
+                .is_err()
+        );
+        file_manager
+            .rename_file("/inbox/a.jpg", "/inbox/b.jpg")
+            .unwrap();
+        file_manager.assert_tree("/inbox", &["b.jpg"]);
+    }
+
+    #[test]
+    fn test_in_memory_file_manager_reads_directories() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/inbox/b.pdf", b"b");
+        file_manager.add_file("/inbox/a.pdf", b"a");
+        file_manager.add_directory("/inbox/nested");
+        file_manager.add_file("/inbox/nested/c.pdf", b"c");
+
+        assert_eq!(
+            file_manager.read_directory("/inbox").unwrap(),
+            vec![
+                PathBuf::from("/inbox/a.pdf"),
+                PathBuf::from("/inbox/b.pdf"),
+                PathBuf::from("/inbox/nested"),
+            ]
+        );
+        assert!(file_manager.read_directory("/missing").is_err());
+        assert!(file_manager.file_exists("/inbox/nested"));
+        assert!(!file_manager.is_file("/inbox/nested"));
+        assert_eq!(file_manager.file_size("/inbox/a.pdf").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_in_memory_file_manager_clones_share_tree() {
+        let file_manager = InMemoryFileManager::new();
+        let clone = file_manager.clone();
+        clone.add_directory("/docs");
+        clone.write_file("/docs/tags.txt", b"finance\n").unwrap();
+        clone.remove_file("/docs/tags.txt").unwrap();
+        clone.write_file("/docs/notes.txt", b"hello").unwrap();
+
+        file_manager.assert_tree("/docs", &["notes.txt"]);
+        assert_eq!(
+            file_manager.calculate_file_hash("/docs/notes.txt").unwrap(),
+            "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+        );
+    }
+
+    #[test]
+    fn test_static_metadata_parser() {
+        let parser =
+            StaticMetadataParser::new().with_datetime("/inbox/a.jpg", "2024-05-01_10-00-00");
+
+        let metadata = parser.parse_metadata("/inbox/a.jpg").unwrap();
+        assert_eq!(metadata.file_type, FileType::Image);
+        assert_eq!(
+            metadata.datetime_original,
+            Some("2024-05-01_10-00-00".to_string())
+        );
+        assert_eq!(
+            parser.parse_metadata("/inbox/a.pdf").unwrap().file_type,
+            FileType::Document
+        );
+        assert_eq!(
+            parser.parse_metadata("/inbox/README").unwrap().file_type,
+            FileType::Unknown
+        );
+        assert!(parser.extract_datetime("/inbox/b.jpg").is_err());
+    }
+
+    #[test]
+    fn test_scripted_prompt() {
+        let prompt = ScriptedPrompt::new()
+            .with_text("invoice")
+            .with_default()
+            .with_confirmation(false)
+            .with_selection(1)
+            .with_cancel();
+        let clone = prompt.clone();
+
+        assert_eq!(
+            prompt.prompt_string("Description", None).unwrap(),
+            "invoice"
+        );
+        assert_eq!(
+            clone.prompt_string("Date", Some("2024-05-01")).unwrap(),
+            "2024-05-01"
+        );
+        assert!(!prompt.prompt_confirmation("More?", true).unwrap());
+        assert_eq!(prompt.prompt_selection("Pick", &["a", "b"]).unwrap(), 1);
+        assert!(matches!(
+            prompt.prompt_string("Description", None),
+            Err(CleanboxError::UserCancelled)
+        ));
+        assert!(prompt.prompt_confirmation("More?", true).is_err());
+
+        assert_eq!(prompt.asked().len(), 6);
+        prompt.assert_finished();
+    }
+
+    #[test]
+    fn test_scripted_prompt_rejects_mismatched_answers() {
+        let prompt = ScriptedPrompt::new()
+            .with_confirmation(true)
+            .with_selection(3);
+
+        assert!(prompt.prompt_string("Description", None).is_err());
+        assert!(prompt.prompt_selection("Pick", &["a", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_unified_processor_runs_in_memory() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file("/life/inbox/IMG_0001.jpg", b"photo");
+        file_manager.add_file("/life/inbox/statement_20240501.pdf", b"statement");
+        file_manager.add_file("/life/inbox/notes", b"no extension");
+        file_manager.add_file("/life/documents/tags.txt", b"finance\n");
+
+        let parser = StaticMetadataParser::new()
+            .with_datetime("/life/inbox/IMG_0001.jpg", "2024-05-01_10-00-00");
+        // Action, date, description, tags, create tag "bank", "Add more tags?"
+        let prompt = ScriptedPrompt::new()
+            .with_default()
+            .with_default()
+            .with_text("bank-statement")
+            .with_text("finance, bank")
+            .with_confirmation(true)
+            .with_confirmation(false);
+
+        let processor = UnifiedProcessor::new(
+            parser,
+            file_manager.clone(),
+            prompt.clone(),
+            LifeConfig::new(PathBuf::from("/life")),
+        );
+        let result = processor.process_life_directory().unwrap();
+
+        assert_eq!(result.media_processed, 1);
+        assert_eq!(result.documents_processed, 1);
+        assert_eq!(result.files_skipped, 1);
+        prompt.assert_finished();
+        file_manager.assert_tree(
+            "/life",
+            &[
+                "documents/2024/05/2024-05-01_bank-statement@@finance,bank.pdf",
+                "documents/tags.txt",
+                "inbox/notes",
+                "manifest.json",
+                "media/2024/05/2024-05-01_10-00-00.jpg",
+            ],
+        );
+        file_manager.assert_contents("/life/documents/tags.txt", "bank\nfinance\n");
+    }
+}
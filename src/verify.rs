@@ -0,0 +1,353 @@
+use crate::config::LifeConfig;
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::{FileManager, walk_files};
+use crate::hashing::{ContentHasher, HashAlgorithm};
+use crate::interactive::ProgressIndicator;
+use crate::manifest::{Manifest, ManifestEntry};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Differences between the archive and its manifest
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Files that were re-hashed
+    pub checked: usize,
+    /// Files whose contents no longer match their hash
+    pub modified: Vec<PathBuf>,
+    /// Files in the manifest that no longer exist
+    pub missing: Vec<PathBuf>,
+    /// Archived files that are not in the manifest
+    pub untracked: Vec<PathBuf>,
+    /// Files that could not be read
+    pub unreadable: Vec<(PathBuf, CleanboxError)>,
+}
+
+impl VerifyReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// True if no file was modified, lost or unreadable
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.unreadable.is_empty()
+    }
+}
+
+/// Re-hashes archived files and compares them with the manifest.
+///
+/// Every run checks for missing and untracked files, which is cheap. Hashing can be
+/// limited to the files verified longest ago (`with_limit`) or to a random sample
+/// (`with_sample`) so large archives can be covered over several runs.
+pub struct ArchiveVerifier<F: FileManager> {
+    file_manager: F,
+    life_config: LifeConfig,
+    limit: Option<usize>,
+    sample: Option<usize>,
+    seed: u64,
+}
+
+impl<F: FileManager> ArchiveVerifier<F> {
+    pub fn new(file_manager: F, life_config: LifeConfig) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+
+        Self {
+            file_manager,
+            life_config,
+            limit: None,
+            sample: None,
+            seed,
+        }
+    }
+
+    /// Hash at most `limit` files, never-verified and longest-unverified first
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Hash `sample` files picked at random
+    pub fn with_sample(mut self, sample: usize) -> Self {
+        self.sample = Some(sample);
+        self
+    }
+
+    /// Seed for picking the sample, random by default
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let life_path = &self.life_config.life_path;
+        let manifest_file = self.life_config.manifest_file();
+        let mut manifest = Manifest::load_from_file(&manifest_file, &self.file_manager)?;
+        let mut report = VerifyReport::new();
+
+        let mut present = Vec::new();
+        for (key, entry) in manifest.entries() {
+            let path = Manifest::path(life_path, key);
+            if self.file_manager.is_file(&path) {
+                present.push((key, entry));
+            } else {
+                report.missing.push(path);
+            }
+        }
+
+        let selected: Vec<(String, ManifestEntry)> = self
+            .select(present)
+            .into_iter()
+            .map(|(key, entry)| (key.to_string(), entry.clone()))
+            .collect();
+
+        if !selected.is_empty() {
+            self.check(&selected, &mut manifest, &mut report);
+            manifest.save(&manifest_file, &self.file_manager)?;
+        }
+
+        for root in [
+            self.life_config.media_root(),
+            self.life_config.documents_root(),
+        ] {
+            for path in walk_files(&self.file_manager, root)? {
+                if self.is_tracked(&manifest, &path) {
+                    continue;
+                }
+                report.untracked.push(path);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Re-hash the selected files, marking those that match as verified
+    fn check(
+        &self,
+        selected: &[(String, ManifestEntry)],
+        manifest: &mut Manifest,
+        report: &mut VerifyReport,
+    ) {
+        let life_path = &self.life_config.life_path;
+        let total_bytes = selected.iter().map(|(_, entry)| entry.size).sum();
+        let mut progress = ProgressIndicator::new(selected.len(), "Verifying".to_string())
+            .with_total_bytes(total_bytes)
+            .follow_console();
+
+        for (key, entry) in selected {
+            let path = Manifest::path(life_path, key);
+            match self.matches(&path, entry) {
+                Ok(true) => manifest.mark_verified(key),
+                Ok(false) => {
+                    progress.clear();
+                    log::error!("Modified: {}", path.display());
+                    report.modified.push(path);
+                }
+                Err(e) => {
+                    progress.clear();
+                    log::error!("Cannot verify {}: {}", path.display(), e);
+                    report.unreadable.push((path, e));
+                }
+            }
+            report.checked += 1;
+            progress.advance(entry.size);
+        }
+        progress.finish();
+    }
+
+    /// Pick the entries to hash according to the limit or sample
+    fn select<'a>(
+        &self,
+        mut entries: Vec<(&'a str, &'a ManifestEntry)>,
+    ) -> Vec<(&'a str, &'a ManifestEntry)> {
+        if let Some(sample) = self.sample {
+            let seed = self.seed.to_le_bytes();
+            entries.sort_by_cached_key(|(key, _)| {
+                let mut hasher = ContentHasher::new(HashAlgorithm::Xxh3);
+                hasher.update(&seed);
+                hasher.update(key.as_bytes());
+                hasher.finish()
+            });
+            entries.truncate(sample);
+        } else if let Some(limit) = self.limit {
+            // Timestamps sort chronologically and None sorts first
+            entries.sort_by(|(_, a), (_, b)| a.verified.cmp(&b.verified));
+            entries.truncate(limit);
+        }
+        entries
+    }
+
+    fn matches(&self, path: &Path, entry: &ManifestEntry) -> Result<bool> {
+        let (algorithm, digest) = HashAlgorithm::parse_tagged(&entry.hash)?;
+        if self.file_manager.file_size(path)? != entry.size {
+            return Ok(false);
+        }
+        Ok(self.file_manager.hash_file(path, algorithm)? == digest)
+    }
+
+    /// Whether `path` is in the manifest or is cleanbox's own bookkeeping
+    fn is_tracked(&self, manifest: &Manifest, path: &Path) -> bool {
+        if path == self.life_config.tags_file() {
+            return true;
+        }
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        hidden
+            || Manifest::key(&self.life_config.life_path, path)
+                .is_some_and(|key| manifest.contains(&key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::InMemoryFileManager;
+
+    fn archive(files: &[(&str, &str)]) -> (InMemoryFileManager, LifeConfig) {
+        let life_config = LifeConfig::new(PathBuf::from("/life"));
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_directory("/life");
+
+        let mut manifest = Manifest::new();
+        for (key, contents) in files {
+            file_manager.add_file(Manifest::path(&life_config.life_path, key), contents);
+            let digest = ContentHasher::hash_bytes(HashAlgorithm::Blake3, contents.as_bytes());
+            manifest.insert(
+                key.to_string(),
+                ManifestEntry::new(HashAlgorithm::Blake3, &digest, contents.len() as u64),
+            );
+        }
+        manifest
+            .save(life_config.manifest_file(), &file_manager)
+            .unwrap();
+
+        (file_manager, life_config)
+    }
+
+    fn load_manifest(file_manager: &InMemoryFileManager, life_config: &LifeConfig) -> Manifest {
+        Manifest::load_from_file(life_config.manifest_file(), file_manager).unwrap()
+    }
+
+    #[test]
+    fn test_reports_modified_missing_and_untracked() {
+        let (file_manager, life_config) = archive(&[
+            ("media/2024/05/a.jpg", "photo a"),
+            ("media/2024/05/b.jpg", "photo b"),
+            ("documents/2024/statement.pdf", "statement"),
+        ]);
+        file_manager.add_file("/life/media/2024/05/b.jpg", b"photo B");
+        file_manager
+            .remove_file("/life/documents/2024/statement.pdf")
+            .unwrap();
+        file_manager.add_file("/life/media/2024/05/c.jpg", b"photo c");
+        file_manager.add_file("/life/documents/tags.txt", b"bank\n");
+        file_manager.add_file("/life/media/.DS_Store", b"");
+
+        let report = ArchiveVerifier::new(file_manager.clone(), life_config.clone())
+            .verify()
+            .unwrap();
+
+        assert_eq!(report.checked, 2);
+        assert_eq!(
+            report.modified,
+            vec![PathBuf::from("/life/media/2024/05/b.jpg")]
+        );
+        assert_eq!(
+            report.missing,
+            vec![PathBuf::from("/life/documents/2024/statement.pdf")]
+        );
+        assert_eq!(
+            report.untracked,
+            vec![PathBuf::from("/life/media/2024/05/c.jpg")]
+        );
+        assert!(!report.is_clean());
+
+        let manifest = load_manifest(&file_manager, &life_config);
+        assert!(
+            manifest
+                .get("media/2024/05/a.jpg")
+                .unwrap()
+                .verified
+                .is_some()
+        );
+        assert!(
+            manifest
+                .get("media/2024/05/b.jpg")
+                .unwrap()
+                .verified
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_limit_verifies_oldest_first() {
+        let (file_manager, life_config) = archive(&[
+            ("media/a.jpg", "a"),
+            ("media/b.jpg", "b"),
+            ("media/c.jpg", "c"),
+        ]);
+        let mut manifest = load_manifest(&file_manager, &life_config);
+        let mut entry = manifest.get("media/a.jpg").unwrap().clone();
+        entry.verified = Some("2024-01-01T00:00:00Z".to_string());
+        manifest.insert("media/a.jpg".to_string(), entry);
+        manifest
+            .save(life_config.manifest_file(), &file_manager)
+            .unwrap();
+
+        let verifier =
+            ArchiveVerifier::new(file_manager.clone(), life_config.clone()).with_limit(2);
+        let report = verifier.verify().unwrap();
+        assert_eq!(report.checked, 2);
+        assert!(report.is_clean());
+
+        // b and c were never verified, so a comes next
+        let manifest = load_manifest(&file_manager, &life_config);
+        assert_eq!(
+            manifest.get("media/a.jpg").unwrap().verified.as_deref(),
+            Some("2024-01-01T00:00:00Z")
+        );
+        assert!(manifest.get("media/b.jpg").unwrap().verified.is_some());
+        assert!(manifest.get("media/c.jpg").unwrap().verified.is_some());
+
+        verifier.verify().unwrap();
+        let manifest = load_manifest(&file_manager, &life_config);
+        assert_ne!(
+            manifest.get("media/a.jpg").unwrap().verified.as_deref(),
+            Some("2024-01-01T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_sample_is_deterministic_for_a_seed() {
+        let files: Vec<(String, String)> = (0..20)
+            .map(|i| (format!("media/{i:02}.jpg"), format!("photo {i}")))
+            .collect();
+        let files: Vec<(&str, &str)> = files
+            .iter()
+            .map(|(key, contents)| (key.as_str(), contents.as_str()))
+            .collect();
+        let (file_manager, life_config) = archive(&files);
+
+        let sampled = |seed| {
+            let (file_manager, life_config) = (file_manager.clone(), life_config.clone());
+            let report = ArchiveVerifier::new(file_manager.clone(), life_config.clone())
+                .with_sample(5)
+                .with_seed(seed)
+                .verify()
+                .unwrap();
+            assert_eq!(report.checked, 5);
+            let manifest = load_manifest(&file_manager, &life_config);
+            manifest
+                .entries()
+                .filter(|(_, entry)| entry.verified.is_some())
+                .count()
+        };
+
+        assert_eq!(sampled(7), 5);
+        // The same seed picks the same files again
+        assert_eq!(sampled(7), 5);
+        assert!(sampled(8) > 5);
+    }
+}
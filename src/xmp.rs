@@ -0,0 +1,389 @@
+use crate::document::DocumentInput;
+use crate::error::{CleanboxError, Result};
+use crate::filesystem::FileManager;
+use crate::naming::DATETIME_FORMAT;
+use chrono::{FixedOffset, Local, NaiveDateTime, Offset, TimeZone};
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Start of the JPEG APP1 segment that holds XMP
+const JPEG_XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const JPEG_APP0: u8 = 0xE0;
+const JPEG_APP1: u8 = 0xE1;
+const JPEG_SOS: u8 = 0xDA;
+/// Largest segment payload the two length bytes can describe, less themselves
+const JPEG_MAX_PAYLOAD: usize = 0xFFFF - 2;
+
+/// Where the metadata cleanbox resolved for a filed file is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataWriting {
+    /// Nothing is written
+    #[default]
+    Off,
+    /// An XMP sidecar next to the file, such as `2024-06-29_14-03-27.jpg.xmp`
+    Sidecar,
+    /// Into JPEG files themselves, and a sidecar for other files
+    InPlace,
+}
+
+impl MetadataWriting {
+    pub const ALL: [MetadataWriting; 3] = [
+        MetadataWriting::Off,
+        MetadataWriting::Sidecar,
+        MetadataWriting::InPlace,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetadataWriting::Off => "off",
+            MetadataWriting::Sidecar => "sidecar",
+            MetadataWriting::InPlace => "in_place",
+        }
+    }
+}
+
+impl fmt::Display for MetadataWriting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for MetadataWriting {
+    type Err = CleanboxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        MetadataWriting::ALL
+            .into_iter()
+            .find(|mode| mode.name() == s.trim().to_lowercase())
+            .ok_or_else(|| {
+                CleanboxError::InvalidSettings(format!(
+                    "Unknown metadata writing '{s}', expected one of off, sidecar, in_place"
+                ))
+            })
+    }
+}
+
+/// An XMP packet with the capture time, description and tags cleanbox resolved
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XmpPacket {
+    datetime_original: Option<String>,
+    description: Option<String>,
+    subjects: Vec<String>,
+}
+
+impl XmpPacket {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Capture time as `exif:DateTimeOriginal`; XMP dates carry the offset that EXIF
+    /// keeps in `OffsetTimeOriginal`
+    pub fn with_datetime_original(mut self, datetime: NaiveDateTime, offset: FixedOffset) -> Self {
+        self.datetime_original = Some(format!(
+            "{}{}",
+            datetime.format("%Y-%m-%dT%H:%M:%S"),
+            offset
+        ));
+        self
+    }
+
+    /// Description as `dc:description`
+    pub fn with_description(mut self, description: String) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    /// Tags as `dc:subject` keywords
+    pub fn with_subjects(mut self, subjects: Vec<String>) -> Self {
+        self.subjects = subjects;
+        self
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut attributes = String::new();
+        if let Some(datetime) = &self.datetime_original {
+            attributes.push_str(&format!(
+                "\n    exif:DateTimeOriginal=\"{0}\"\n    photoshop:DateCreated=\"{0}\"",
+                escape(datetime)
+            ));
+        }
+
+        let mut properties = String::new();
+        if let Some(description) = &self.description {
+            properties.push_str(&format!(
+                "   <dc:description>\n    <rdf:Alt>\n     <rdf:li xml:lang=\"x-default\">{}</rdf:li>\n    </rdf:Alt>\n   </dc:description>\n",
+                escape(description)
+            ));
+        }
+        if !self.subjects.is_empty() {
+            properties.push_str("   <dc:subject>\n    <rdf:Bag>\n");
+            for subject in &self.subjects {
+                properties.push_str(&format!("     <rdf:li>{}</rdf:li>\n", escape(subject)));
+            }
+            properties.push_str("    </rdf:Bag>\n   </dc:subject>\n");
+        }
+
+        format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             \x20<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             \x20 <rdf:Description rdf:about=\"\"\n\
+             \x20   xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n\
+             \x20   xmlns:exif=\"http://ns.adobe.com/exif/1.0/\"\n\
+             \x20   xmlns:photoshop=\"http://ns.adobe.com/photoshop/1.0/\"{attributes}>\n\
+             {properties}\
+             \x20 </rdf:Description>\n\
+             \x20</rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>\n"
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Offset of local time at `datetime`, as export times are converted to local time
+fn local_offset(datetime: NaiveDateTime) -> FixedOffset {
+    Local
+        .from_local_datetime(&datetime)
+        .earliest()
+        .map(|local| local.offset().fix())
+        .unwrap_or_else(|| Local::now().offset().fix())
+}
+
+/// XMP sidecar of `path`, following its whole name as `photo.jpg.xmp`
+pub fn xmp_sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".xmp");
+    PathBuf::from(name)
+}
+
+/// `jpeg` with `packet` in an APP1 segment after its APP0 and EXIF segments, or None
+/// if it is no JPEG, already holds XMP or the packet does not fit in one segment
+pub fn embed_in_jpeg(jpeg: &[u8], packet: &str) -> Option<Vec<u8>> {
+    if !jpeg.starts_with(&JPEG_SOI) {
+        return None;
+    }
+
+    let mut insert_at = None;
+    let mut offset = JPEG_SOI.len();
+    loop {
+        let [0xFF, marker] = jpeg.get(offset..offset + 2)? else {
+            return None;
+        };
+        if *marker == JPEG_SOS {
+            break;
+        }
+        if !matches!(*marker, JPEG_APP0 | JPEG_APP1) {
+            insert_at.get_or_insert(offset);
+        }
+        let length = u16::from_be_bytes(jpeg.get(offset + 2..offset + 4)?.try_into().ok()?);
+        let payload = jpeg.get(offset + 4..offset + 2 + usize::from(length))?;
+        if *marker == JPEG_APP1 && payload.starts_with(JPEG_XMP_SIGNATURE) {
+            return None;
+        }
+        offset += 2 + usize::from(length);
+    }
+    let insert_at = insert_at.unwrap_or(offset);
+
+    let payload_length = JPEG_XMP_SIGNATURE.len() + packet.len();
+    if payload_length > JPEG_MAX_PAYLOAD {
+        return None;
+    }
+    let mut embedded = Vec::with_capacity(jpeg.len() + payload_length + 4);
+    embedded.extend_from_slice(&jpeg[..insert_at]);
+    embedded.extend([0xFF, JPEG_APP1]);
+    embedded.extend(((payload_length + 2) as u16).to_be_bytes());
+    embedded.extend_from_slice(JPEG_XMP_SIGNATURE);
+    embedded.extend_from_slice(packet.as_bytes());
+    embedded.extend_from_slice(&jpeg[insert_at..]);
+    Some(embedded)
+}
+
+/// Writes what cleanbox resolved for a filed file as XMP, so other tools still see a
+/// capture time or tags that the file itself did not carry once it is renamed
+pub struct MetadataWriter<F: FileManager> {
+    file_manager: F,
+    mode: MetadataWriting,
+}
+
+impl<F: FileManager> MetadataWriter<F> {
+    pub fn new(file_manager: F, mode: MetadataWriting) -> Self {
+        Self { file_manager, mode }
+    }
+
+    /// Store the capture time of media filed at `path`, given as `YYYY-MM-DD_HH-MM-SS`.
+    /// Returns the file written, or None with writing off.
+    pub fn write_media(&self, path: &Path, datetime: &str) -> Result<Option<PathBuf>> {
+        if self.mode == MetadataWriting::Off {
+            return Ok(None);
+        }
+        let datetime = NaiveDateTime::parse_from_str(datetime, DATETIME_FORMAT)
+            .map_err(|_| CleanboxError::InvalidDateTime(datetime.to_string()))?;
+        let packet = XmpPacket::new().with_datetime_original(datetime, local_offset(datetime));
+        self.write(path, &packet).map(Some)
+    }
+
+    /// Store the description and tags of a document filed at `path`.
+    /// Returns the file written, or None with writing off.
+    pub fn write_document(&self, path: &Path, input: &DocumentInput) -> Result<Option<PathBuf>> {
+        if self.mode == MetadataWriting::Off {
+            return Ok(None);
+        }
+        let packet = XmpPacket::new()
+            .with_description(input.description.clone())
+            .with_subjects(input.tags.clone());
+        self.write(path, &packet).map(Some)
+    }
+
+    fn write(&self, path: &Path, packet: &XmpPacket) -> Result<PathBuf> {
+        let xml = packet.to_xml();
+        let is_jpeg = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg"));
+
+        if self.mode == MetadataWriting::InPlace && is_jpeg {
+            let contents = self.file_manager.read_file(path)?;
+            if let Some(embedded) = embed_in_jpeg(&contents, &xml) {
+                // Through a temporary file so a crash never leaves the photo truncated
+                let mut temp_path = path.as_os_str().to_os_string();
+                temp_path.push(".tmp");
+                self.file_manager.write_file(&temp_path, &embedded)?;
+                self.file_manager.rename_file(&temp_path, path)?;
+                return Ok(path.to_path_buf());
+            }
+        }
+
+        // A sidecar that came along with the file is never replaced
+        let sidecar = xmp_sidecar_path(path);
+        if self.file_manager.file_exists(&sidecar) {
+            return Err(CleanboxError::FileAlreadyExists(
+                sidecar.display().to_string(),
+            ));
+        }
+        self.file_manager.write_file(&sidecar, xml.as_bytes())?;
+        Ok(sidecar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::InMemoryFileManager;
+    use chrono::NaiveDate;
+
+    fn jpeg_with_exif() -> Vec<u8> {
+        let mut jpeg = JPEG_SOI.to_vec();
+        jpeg.extend([0xFF, JPEG_APP1, 0x00, 0x08]);
+        jpeg.extend(b"Exif\0\0");
+        jpeg.extend([0xFF, 0xDB, 0x00, 0x03, 0x00]);
+        jpeg.extend([0xFF, JPEG_SOS, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    #[test]
+    fn test_xmp_packet() {
+        let datetime = NaiveDate::from_ymd_opt(2024, 6, 29)
+            .unwrap()
+            .and_hms_opt(14, 3, 27)
+            .unwrap();
+        let xml = XmpPacket::new()
+            .with_datetime_original(datetime, FixedOffset::east_opt(2 * 3600).unwrap())
+            .with_description("Rent & deposit".to_string())
+            .with_subjects(vec!["housing".to_string(), "lisbon".to_string()])
+            .to_xml();
+
+        assert!(xml.contains("exif:DateTimeOriginal=\"2024-06-29T14:03:27+02:00\""));
+        assert!(xml.contains("<rdf:li xml:lang=\"x-default\">Rent &amp; deposit</rdf:li>"));
+        assert!(xml.contains("<rdf:li>housing</rdf:li>\n     <rdf:li>lisbon</rdf:li>"));
+        assert!(xml.ends_with("<?xpacket end=\"w\"?>\n"));
+    }
+
+    #[test]
+    fn test_embed_in_jpeg() {
+        let jpeg = jpeg_with_exif();
+        let embedded = embed_in_jpeg(&jpeg, "<x:xmpmeta/>").unwrap();
+
+        // After the EXIF segment, before the quantization table
+        let xmp_at = 2 + 10;
+        assert_eq!(&embedded[..xmp_at], &jpeg[..xmp_at]);
+        assert_eq!(&embedded[xmp_at..xmp_at + 2], &[0xFF, JPEG_APP1]);
+        assert!(embedded[xmp_at + 4..].starts_with(JPEG_XMP_SIGNATURE));
+        assert!(embedded.ends_with(&jpeg[xmp_at..]));
+        assert_eq!(
+            rexif::parse_buffer(&embedded).is_ok(),
+            rexif::parse_buffer(&jpeg).is_ok()
+        );
+
+        // Existing XMP is left alone, as are files that are no JPEG
+        assert!(embed_in_jpeg(&embedded, "<x:xmpmeta/>").is_none());
+        assert!(embed_in_jpeg(b"%PDF-1.4", "<x:xmpmeta/>").is_none());
+    }
+
+    #[test]
+    fn test_metadata_writer() {
+        let file_manager = InMemoryFileManager::new();
+        file_manager.add_file(
+            "/life/media/2024/06/2024-06-29_14-03-27.jpg",
+            jpeg_with_exif(),
+        );
+        file_manager.add_file("/life/media/2024/06/2024-06-29_14-03-30.mov", b"video");
+        let photo = Path::new("/life/media/2024/06/2024-06-29_14-03-27.jpg");
+        let video = Path::new("/life/media/2024/06/2024-06-29_14-03-30.mov");
+
+        let off = MetadataWriter::new(file_manager.clone(), MetadataWriting::Off);
+        assert_eq!(off.write_media(photo, "2024-06-29_14-03-27").unwrap(), None);
+
+        let in_place = MetadataWriter::new(file_manager.clone(), MetadataWriting::InPlace);
+        assert_eq!(
+            in_place.write_media(photo, "2024-06-29_14-03-27").unwrap(),
+            Some(photo.to_path_buf())
+        );
+        let contents = file_manager.read_file(photo).unwrap();
+        assert!(
+            contents
+                .windows(JPEG_XMP_SIGNATURE.len())
+                .any(|w| w == JPEG_XMP_SIGNATURE)
+        );
+        file_manager.assert_no_file("/life/media/2024/06/2024-06-29_14-03-27.jpg.tmp");
+
+        // Other files get a sidecar, which is never replaced
+        let sidecar = in_place
+            .write_media(video, "2024-06-29_14-03-30")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            sidecar,
+            PathBuf::from("/life/media/2024/06/2024-06-29_14-03-30.mov.xmp")
+        );
+        let xml = String::from_utf8(file_manager.read_file(&sidecar).unwrap()).unwrap();
+        assert!(xml.contains("exif:DateTimeOriginal=\"2024-06-29T14:03:30"));
+        assert_eq!(
+            in_place
+                .write_media(video, "2024-06-29_14-03-30")
+                .unwrap_err()
+                .kind(),
+            "file_already_exists"
+        );
+    }
+
+    #[test]
+    fn test_metadata_writing_names() {
+        assert_eq!(
+            "in_place".parse::<MetadataWriting>().unwrap(),
+            MetadataWriting::InPlace
+        );
+        assert_eq!(MetadataWriting::Sidecar.to_string(), "sidecar");
+        assert!("jpeg".parse::<MetadataWriting>().is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn create_life_directory() -> tempfile::TempDir {
+    let life_dir = tempfile::TempDir::new().unwrap();
+    fs::create_dir_all(life_dir.path().join("inbox")).unwrap();
+    fs::create_dir_all(life_dir.path().join("documents")).unwrap();
+    fs::write(life_dir.path().join("documents/tags.txt"), "finance\n").unwrap();
+    life_dir
+}
+
+fn run_cleanbox(life_path: &Path, args: &[&str]) -> Output {
+    run_cleanbox_with_input(life_path, args, "")
+}
+
+fn run_cleanbox_with_input(life_path: &Path, args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cleanbox"))
+        .arg("--life-path")
+        .arg(life_path)
+        .arg("--no-log-file")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_json_report_on_stdout_is_valid_json() {
+    let life_dir = create_life_directory();
+    let inbox = life_dir.path().join("inbox");
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/photo.avif");
+    fs::copy(fixture, inbox.join("photo.avif")).unwrap();
+    fs::write(inbox.join("invoice.pdf"), b"%PDF-1.4\n%%EOF\n").unwrap();
+
+    let output = run_cleanbox(
+        life_dir.path(),
+        &["--non-interactive", "--report", "json", "-v"],
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&stdout).unwrap_or_else(|e| {
+        panic!("stdout is not a JSON report ({e}):\n{stdout}");
+    });
+    assert_eq!(report["files"].as_array().unwrap().len(), 2);
+    assert_eq!(report["summary"]["processed"], 1);
+    assert_eq!(report["summary"]["skipped"], 1);
+    // Progress and per-file detail still reach the terminal
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn test_scripted_answers_keep_stdout_json_only() {
+    let life_dir = create_life_directory();
+    fs::write(
+        life_dir.path().join("inbox/invoice.pdf"),
+        b"%PDF-1.4\n%%EOF\n",
+    )
+    .unwrap();
+    let answers = [
+        r#"{"answer": "p"}"#,
+        r#"{"answer": "2024-05-01"}"#,
+        r#"{"answer": "invoice"}"#,
+        r#"{"answer": "finance, bank"}"#,
+        r#"{}"#,
+        r#"{}"#,
+    ];
+
+    let output = run_cleanbox_with_input(
+        life_dir.path(),
+        &["--answers", "-"],
+        &(answers.join("\n") + "\n"),
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| {
+            serde_json::from_str(line)
+                .unwrap_or_else(|e| panic!("stdout line is not JSON ({e}): {line}"))
+        })
+        .collect();
+    assert!(events.iter().any(|event| event["event"] == "prompt"));
+    assert!(
+        events
+            .iter()
+            .any(|event| event["event"] == "info" && event["message"] == "Added tag: finance")
+    );
+    assert!(!stdout.contains("TAB"));
+    assert!(output.status.success());
+    assert!(
+        life_dir
+            .path()
+            .join("documents/2024/05/2024-05-01_invoice@@finance,bank.pdf")
+            .exists()
+    );
+}